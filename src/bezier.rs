@@ -0,0 +1,370 @@
+/*
+
+    Bicubic Bézier patches, e.g. the Utah teapot. A patch
+    has 16 control points referring to VertexData, in rows
+    of 4 along u, one row after the other along v:
+        "BezierPatch": {
+            "_id": "1", "Material": "1",
+            "ControlPoints": "1 2 3 4  5 6 7 8  9 10 11 12  13 14 15 16",
+            "Method": "tessellate", "Tolerance": "0.005"
+        }
+    Method is one of
+        - tessellate (default): the patch is split into
+          smooth Triangles at load time, as many as needed
+          for the surface to be within Tolerance of them.
+          Boundary curves are split by their own control
+          points only, so patches sharing them meet without
+          cracks. Vertex normals are the analytic ones.
+        - newton: a ray is intersected with the surface
+          itself, solving S(u, v) = o + t d by Newton's
+          method from where it hits a coarse grid of it.
+    Either way normals are dS/du x dS/dv, points where
+    it vanishes (e.g. the collapsed row at the top of the
+    teapot's lid) take the normal of a point next to them.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+use crate::csg::SurfaceHit;
+use crate::dataforms::VertexData;
+use crate::geometry::ray_box_overlap;
+use crate::interval::Interval;
+use crate::json_parser::*;
+use crate::numeric::{Float, Matrix3, Vector3};
+use crate::ray::{HitRecord, Ray};
+use crate::shapes::{HeapAllocatedVerts, PrimitiveShape, Triangle};
+
+const MAX_SEGMENTS: usize = 64; // Per side of a tessellated patch
+const SEED_CELLS: usize = 4; // Per side of the coarse grid Newton's method starts from
+const SEED_POINTS: usize = (SEED_CELLS + 1) * (SEED_CELLS + 1);
+const SEED_SLACK: Float = 0.25; // Barycentric margin around coarse triangles, the surface bulges out of them
+const NEWTON_STEPS: usize = 20;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BezierMethod {
+    #[default]
+    Tessellate,
+    Newton,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BezierPatch {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+    #[serde(rename = "ControlPoints", deserialize_with = "deser_usize_vec")]
+    pub control_points: Vec<usize>, // 16 vertex ids, checked by validation
+    #[serde(rename = "Method", default)]
+    pub method: BezierMethod,
+    #[serde(rename = "Tolerance", deserialize_with = "deser_float", default = "default_tolerance")]
+    pub tolerance: Float,
+    #[serde(skip)]
+    pub surface: Option<Arc<BezierSurface>>, // Set in SceneObjects::setup( ) for Method newton
+}
+
+fn default_tolerance() -> Float {
+    0.01
+}
+
+impl BezierPatch {
+    pub fn surface_of(&self, verts: &VertexData) -> BezierSurface {
+        BezierSurface::new(std::array::from_fn(|i| verts[self.control_points[i]]))
+    }
+
+    /// Smooth triangles approximating the patch, their vertices are appended to verts.
+    /// Returns them with the analytic normals of the new vertices.
+    pub fn to_triangles(&self, verts: &mut VertexData) -> (Vec<Triangle>, Vec<(usize, Vector3)>) {
+        let surface = self.surface_of(verts);
+        let (params, triangles) = surface.tessellate(self.tolerance);
+        let offset = verts._data.len();
+        verts._data.extend(params.iter().map(|&[u, v]| surface.point(u, v)));
+        let normals = params.iter().enumerate().map(|(i, &[u, v])| (offset + i, surface.normal(u, v))).collect();
+        let triangles = triangles.into_iter().map(|indices| {
+            let indices = indices.map(|i| offset + i);
+            let [a, b, c] = indices.map(|i| verts[i]);
+            Triangle {
+                _id: self._id,
+                indices,
+                material_idx: self.material_idx,
+                is_smooth: true,
+                normal: (b - a).cross(c - a).normalize_or_zero(),
+            }
+        }).collect();
+        (triangles, normals)
+    }
+}
+
+impl PrimitiveShape for BezierPatch {
+
+    fn indices(&self) -> Vec<usize> {
+        self.control_points.clone()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, _vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        let surface = self.surface.as_ref()?;
+        let (t, u, v) = surface.intersect(ray, t_interval)?;
+        Some(SurfaceHit { t, normal: surface.normal(u, v), material: self.material_idx, uv: Some([u, v]) }.to_record(ray))
+    }
+}
+
+fn bernstein(t: Float) -> [Float; 4] {
+    let s = 1.0 - t;
+    [s * s * s, 3.0 * t * s * s, 3.0 * t * t * s, t * t * t]
+}
+
+fn bernstein_derivative(t: Float) -> [Float; 4] {
+    let s = 1.0 - t;
+    [-3.0 * s * s, 3.0 * s * (s - 2.0 * t), 3.0 * t * (2.0 * s - t), 3.0 * t * t]
+}
+
+/// Segments for a cubic Bézier curve to be within tolerance of its polyline,
+/// from max |B''| <= 6 max |p0 - 2 p1 + p2|, |p1 - 2 p2 + p3|
+fn segments(curve: [Vector3; 4], tolerance: Float) -> usize {
+    let bend = (curve[0] - 2.0 * curve[1] + curve[2]).length().max((curve[1] - 2.0 * curve[2] + curve[3]).length());
+    ((0.75 * bend / tolerance).sqrt().ceil() as usize).clamp(1, MAX_SEGMENTS)
+}
+
+#[derive(Debug, Clone)]
+pub struct BezierSurface {
+    points: [Vector3; 16], // points[4 * j + i] is the i-th along u of the j-th row along v
+    seeds: [Vector3; SEED_POINTS], // Corners of the coarse grid, row by row as the control points
+    bounds: (Vector3, Vector3),
+}
+
+impl BezierSurface {
+    pub fn new(points: [Vector3; 16]) -> Self {
+        // Surface is inside the convex hull of its control points
+        let bounds = points.iter().fold((Vector3::INFINITY, Vector3::NEG_INFINITY), |(min, max), &p| (min.min(p), max.max(p)));
+        let mut surface = Self { points, seeds: [Vector3::ZERO; SEED_POINTS], bounds };
+        surface.seeds = std::array::from_fn(|g| {
+            let [u, v] = seed_param(g);
+            surface.point(u, v)
+        });
+        surface
+    }
+
+    fn row(&self, j: usize) -> [Vector3; 4] {
+        std::array::from_fn(|i| self.points[4 * j + i])
+    }
+
+    fn column(&self, i: usize) -> [Vector3; 4] {
+        std::array::from_fn(|j| self.points[4 * j + i])
+    }
+
+    fn combine(&self, bu: [Float; 4], bv: [Float; 4]) -> Vector3 {
+        (0..16).map(|k| self.points[k] * (bu[k % 4] * bv[k / 4])).sum()
+    }
+
+    pub fn point(&self, u: Float, v: Float) -> Vector3 {
+        self.combine(bernstein(u), bernstein(v))
+    }
+
+    /// Point with its partial derivatives along u and v
+    pub fn partials(&self, u: Float, v: Float) -> (Vector3, Vector3, Vector3) {
+        let (bu, bv) = (bernstein(u), bernstein(v));
+        let (du, dv) = (bernstein_derivative(u), bernstein_derivative(v));
+        (self.combine(bu, bv), self.combine(du, bv), self.combine(bu, dv))
+    }
+
+    pub fn normal(&self, u: Float, v: Float) -> Vector3 {
+        // Moves towards the center of the patch where the derivatives vanish or are parallel
+        for step in [0.0, 1e-4, 1e-2] {
+            let (_, su, sv) = self.partials(u + (0.5 - u) * step, v + (0.5 - v) * step);
+            let n = su.cross(sv);
+            if n.length_squared() > 1e-12 * su.length_squared() * sv.length_squared() {
+                return n.normalize();
+            }
+        }
+        Vector3::ZERO
+    }
+
+    /// Parameters of vertices and triangles between them. The grid is fine enough for every
+    /// row and column of control points, and points on the boundary are moved to where the
+    /// boundary curve alone would put them, merging some (their triangles are dropped).
+    pub fn tessellate(&self, tolerance: Float) -> (Vec<[Float; 2]>, Vec<[usize; 3]>) {
+        let nu = (0..4).map(|j| segments(self.row(j), tolerance)).max().unwrap_or(1);
+        let nv = (0..4).map(|i| segments(self.column(i), tolerance)).max().unwrap_or(1);
+        let [bottom, top] = [self.row(0), self.row(3)].map(|c| segments(c, tolerance));
+        let [left, right] = [self.column(0), self.column(3)].map(|c| segments(c, tolerance));
+        let snap = |i: usize, n: usize, boundary: usize| ((i * boundary) as Float / n as Float).round() / boundary as Float;
+
+        let mut params: Vec<[Float; 2]> = Vec::new();
+        let mut merged = HashMap::new();
+        let mut grid = vec![vec![0; nv + 1]; nu + 1];
+        for (i, column) in grid.iter_mut().enumerate() {
+            for (k, idx) in column.iter_mut().enumerate() {
+                let mut uv = [i as Float / nu as Float, k as Float / nv as Float];
+                if k == 0 { uv[0] = snap(i, nu, bottom) }
+                if k == nv { uv[0] = snap(i, nu, top) }
+                if i == 0 { uv[1] = snap(k, nv, left) }
+                if i == nu { uv[1] = snap(k, nv, right) }
+                *idx = *merged.entry(uv.map(Float::to_bits)).or_insert_with(|| {
+                    params.push(uv);
+                    params.len() - 1
+                });
+            }
+        }
+
+        let mut triangles = Vec::with_capacity(2 * nu * nv);
+        for i in 0..nu {
+            for k in 0..nv {
+                let [a, b, c, d] = [grid[i][k], grid[i + 1][k], grid[i + 1][k + 1], grid[i][k + 1]];
+                triangles.extend([[a, b, c], [a, c, d]].into_iter().filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0]));
+            }
+        }
+        (params, triangles)
+    }
+
+    /// Closest (t, u, v) where the ray hits the surface
+    pub fn intersect(&self, ray: &Ray, t_interval: &Interval) -> Option<(Float, Float, Float)> {
+        let (min, max) = self.bounds;
+        let extent = (max - min).max(Vector3::splat(1e-6));
+        let (enter, exit) = ray_box_overlap(ray.origin, ray.direction, min - extent * 1e-3, max + extent * 1e-3)?;
+        if exit < t_interval.min || enter > t_interval.max {
+            return None;
+        }
+
+        let n = SEED_CELLS;
+        let param = |g: usize| {
+            let [u, v] = seed_param(g);
+            Vector3::new(u, v, 0.0)
+        };
+        let tolerance = extent.length() * Float::EPSILON.sqrt() * 1e-2;
+        let mut closest: Option<(Float, Float, Float)> = None;
+        for cell in 0..n * n {
+            let g = cell / n * (n + 1) + cell % n;
+            for corners in [[g, g + 1, g + n + 2], [g, g + n + 2, g + n + 1]] {
+                let Some(seed) = seed(ray, corners.map(|c| self.seeds[c]), corners.map(param)) else { continue };
+                let Some((t, u, v)) = self.newton(ray, seed, tolerance) else { continue };
+                if t_interval.contains(t) && closest.is_none_or(|c| t < c.0) {
+                    closest = Some((t, u, v));
+                }
+            }
+        }
+        closest
+    }
+
+    fn newton(&self, ray: &Ray, (mut u, mut v, mut t): (Float, Float, Float), tolerance: Float) -> Option<(Float, Float, Float)> {
+        for _ in 0..NEWTON_STEPS {
+            let (s, su, sv) = self.partials(u, v);
+            let f = s - ray.at(t);
+            if f.length() < tolerance {
+                let inside = |x: Float| (-1e-6..=1.0 + 1e-6).contains(&x);
+                return (inside(u) && inside(v)).then_some((t, u.clamp(0.0, 1.0), v.clamp(0.0, 1.0)));
+            }
+            // Jacobian of S(u, v) - o - t d with respect to (u, v, t)
+            let jacobian = Matrix3::from_cols(su, sv, -ray.direction);
+            if jacobian.determinant().abs() < 1e-12 {
+                return None;
+            }
+            let step = jacobian.inverse() * -f;
+            u = (u + step.x).clamp(-0.5, 1.5);
+            v = (v + step.y).clamp(-0.5, 1.5);
+            t += step.z;
+        }
+        None
+    }
+}
+
+/// (u, v) of the g-th corner of the coarse grid
+fn seed_param(g: usize) -> [Float; 2] {
+    let n = SEED_CELLS;
+    [(g % (n + 1)) as Float / n as Float, (g / (n + 1)) as Float / n as Float]
+}
+
+/// Starting (u, v, t) where the ray hits the plane of a triangle of the coarse grid close to it
+fn seed(ray: &Ray, corners: [Vector3; 3], params: [Vector3; 3]) -> Option<(Float, Float, Float)> {
+    let (e1, e2) = (corners[1] - corners[0], corners[2] - corners[0]);
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let s = ray.origin - corners[0];
+    let b1 = s.dot(p) / det;
+    let q = s.cross(e1);
+    let b2 = ray.direction.dot(q) / det;
+    if b1 < -SEED_SLACK || b2 < -SEED_SLACK || b1 + b2 > 1.0 + SEED_SLACK {
+        return None;
+    }
+    let uv = params[0] * (1.0 - b1 - b2) + params[1] * b1 + params[2] * b2;
+    Some((uv.x, uv.y, e2.dot(q) / det))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Saddle z = (x - 0.5)(y - 0.5) over the unit square, bilinear so exact as a bicubic
+    fn saddle() -> [Vector3; 16] {
+        std::array::from_fn(|k| {
+            let (x, y) = ((k % 4) as Float / 3.0, (k / 4) as Float / 3.0);
+            Vector3::new(x, y, (x - 0.5) * (y - 0.5))
+        })
+    }
+
+    #[test]
+    fn test_newton_intersection() {
+        let surface = BezierSurface::new(saddle());
+        let ray = Ray::new(Vector3::new(0.9, 0.2, 5.0), -Vector3::Z);
+        let (t, u, v) = surface.intersect(&ray, &Interval::positive(1e-6)).unwrap();
+        assert!((u - 0.9).abs() < 1e-4 && (v - 0.2).abs() < 1e-4);
+        assert!((t - (5.0 - 0.4 * -0.3)).abs() < 1e-4, "{}", t);
+        // Normal of z = f(x, y) is (-f_x, -f_y, 1)
+        let expected = Vector3::new(0.3, -0.4, 1.0).normalize();
+        assert!(surface.normal(u, v).abs_diff_eq(expected, 1e-4));
+        assert!(surface.intersect(&Ray::new(Vector3::new(1.2, 0.5, 5.0), -Vector3::Z), &Interval::positive(1e-6)).is_none());
+
+        // Grazing ray along the surface at v = 0.75, z = (x - 0.5) / 4 is a line
+        let grazing = Ray::new(Vector3::new(-1.0, 0.75, -0.375 - 0.1), Vector3::new(1.0, 0.0, 0.25).normalize());
+        assert!(surface.intersect(&grazing, &Interval::positive(1e-6)).is_none());
+    }
+
+    #[test]
+    fn test_tessellation() {
+        // Flat patch needs one cell, a curved one more
+        let mut flat = saddle();
+        flat.iter_mut().for_each(|p| p.z = 0.0);
+        let (params, triangles) = BezierSurface::new(flat).tessellate(0.01);
+        assert_eq!((params.len(), triangles.len()), (4, 2));
+
+        let mut bulge = flat;
+        for k in [5, 6, 9, 10] {
+            bulge[k].z = 1.0;
+        }
+        let (params, triangles) = BezierSurface::new(bulge).tessellate(0.01);
+        let side = (0.75 * 1.0 / 0.01 as Float).sqrt().ceil() as usize;
+        // Boundary is a straight line, so it is a single segment and the grid fans into it
+        assert_eq!(params.len(), (side - 1) * (side - 1) + 4);
+        assert_eq!(triangles.len(), 2 * (side - 2) * (side - 2) + 4 * (side - 2) + 4);
+        // Degenerate normals at a collapsed edge are taken next to it
+        let mut cone = bulge;
+        cone[..4].fill(Vector3::ZERO);
+        assert!(BezierSurface::new(cone).normal(0.3, 0.0).length() > 0.99);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let patch: BezierPatch = serde_json::from_str(r#"{
+            "_id": "2", "Material": "1", "ControlPoints": "1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16"
+        }"#).unwrap();
+        assert_eq!((patch._id, patch.method, patch.tolerance), (2, BezierMethod::Tessellate, 0.01));
+        assert!(patch.surface.is_none());
+        // Only Method and Tolerance are optional
+        assert!(serde_json::from_str::<BezierPatch>(r#"{ "_id": "2", "Material": "1" }"#).is_err());
+        assert!(serde_json::from_str::<BezierPatch>(r#"{ "_id": "2", "ControlPoints": "1 2 3 4" }"#).is_err());
+    }
+}
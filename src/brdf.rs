@@ -0,0 +1,242 @@
+/*
+
+    Reflectance coefficients shared by every material and
+    the reflection model (BRDF) evaluated with them:
+        - Phong, Blinn-Phong (original, not energy conserving)
+        - Modified Phong, Modified Blinn-Phong (normalized or not)
+        - Torrance-Sparrow (Blinn distribution, Cook-Torrance
+          geometry term and Schlick's Fresnel approximation)
+    See slides 04 (BRDFs) for the formulas.
+
+    Models are declared in the BRDFs section of the scene
+    and referred by materials through their _BRDF id, e.g.
+        "BRDFs": { "ModifiedBlinnPhong": { "_id": "1", "_normalized": "true", "Exponent": "50" } }
+        "Material": { "_id": "1", "_BRDF": "1", ... }
+    Materials without a _BRDF use original Blinn-Phong.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use serde::Deserialize;
+
+use crate::dataforms::SingleOrVec;
+use crate::interval::FloatConst;
+use crate::json_parser::*;
+use crate::numeric::{Float, Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BRDFModel {
+    Phong,
+    #[default]
+    BlinnPhong,
+    ModifiedPhong { normalized: bool },
+    ModifiedBlinnPhong { normalized: bool },
+    TorranceSparrow { kd_fresnel: bool, refraction_index: Float }, // kd_fresnel scales diffuse by 1 - F
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BRDF {
+    #[serde(rename = "AmbientReflectance", deserialize_with = "deser_vec3")]
+    pub ambient_rf: Vector3,
+    #[serde(rename = "DiffuseReflectance", deserialize_with = "deser_vec3")]
+    pub diffuse_rf: Vector3,
+    #[serde(rename = "SpecularReflectance", deserialize_with = "deser_vec3")]
+    pub specular_rf: Vector3,
+    #[serde(rename = "PhongExponent", deserialize_with = "deser_float")]
+    pub phong_exponent: Float,
+    #[serde(rename = "Emission", alias = "Radiance", deserialize_with = "deser_vec3")]
+    pub emission: Vector3, // Not reflected but emitted radiance, nonzero makes shapes of the material lights (see light.rs)
+    #[serde(skip)]
+    pub model: BRDFModel, // Set from _BRDF of the material, see scene::parse_single_material( )
+}
+
+impl Default for BRDF {
+    fn default() -> Self {
+        Self {
+            ambient_rf: Vector3::ZERO,
+            diffuse_rf: Vector3::ONE,
+            specular_rf: Vector3::ZERO,
+            phong_exponent: 1.0,
+            emission: Vector3::ZERO,
+            model: BRDFModel::default(),
+        }
+    }
+}
+
+impl BRDF {
+    /// Take the fields that are not given in the JSON value of a material from defaults. A flattened
+    /// BRDF gets BRDF::default( ) for them, but e.g. conductors are not diffuse unless told so.
+    pub fn default_missing(&mut self, value: &serde_json::Value, defaults: &BRDF) {
+        let missing = |keys: &[&str]| keys.iter().all(|key| value.get(key).is_none());
+        if missing(&["AmbientReflectance"]) {
+            self.ambient_rf = defaults.ambient_rf;
+        }
+        if missing(&["DiffuseReflectance"]) {
+            self.diffuse_rf = defaults.diffuse_rf;
+        }
+        if missing(&["SpecularReflectance"]) {
+            self.specular_rf = defaults.specular_rf;
+        }
+        if missing(&["PhongExponent"]) {
+            self.phong_exponent = defaults.phong_exponent;
+        }
+        if missing(&["Emission", "Radiance"]) {
+            self.emission = defaults.emission;
+        }
+    }
+
+    /// Reflected radiance towards w_o per unit irradiance from w_i, i.e. f_r * cos(theta_i)
+    pub fn eval(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
+        debug_assert!(w_o.is_normalized());
+        debug_assert!(w_i.is_normalized());
+        debug_assert!(n.is_normalized());
+
+        let cos_i = w_i.dot(n);
+        let p = self.phong_exponent;
+        let (kd, ks) = (self.diffuse_rf, self.specular_rf);
+        let cos_r = || (2.0 * cos_i * n - w_i).dot(w_o).max(0.0); // Angle between perfect reflection and w_o
+        let cos_h = || n.dot((w_i + w_o).normalize()).max(0.0); // Angle between half vector and normal
+        match self.model {
+            BRDFModel::BlinnPhong => {
+                // Slides 01_B, p.73 and p.80 (specular is not multiplied by cos_i)
+                kd * cos_i.max(0.0) + ks * cos_h().powf(p)
+            }
+            _ if cos_i <= 0.0 => Vector3::ZERO,
+            BRDFModel::Phong => kd * cos_i + ks * cos_r().powf(p),
+            BRDFModel::ModifiedPhong { normalized: false } => (kd + ks * cos_r().powf(p)) * cos_i,
+            BRDFModel::ModifiedPhong { normalized: true } => {
+                (kd / Float::PI + ks * ((p + 2.0) / (2.0 * Float::PI)) * cos_r().powf(p)) * cos_i
+            }
+            BRDFModel::ModifiedBlinnPhong { normalized: false } => (kd + ks * cos_h().powf(p)) * cos_i,
+            BRDFModel::ModifiedBlinnPhong { normalized: true } => {
+                (kd / Float::PI + ks * ((p + 8.0) / (8.0 * Float::PI)) * cos_h().powf(p)) * cos_i
+            }
+            BRDFModel::TorranceSparrow { kd_fresnel, refraction_index } => {
+                let cos_o = w_o.dot(n);
+                if cos_o <= 0.0 {
+                    return Vector3::ZERO;
+                }
+                let h = (w_i + w_o).normalize();
+                let cos_alpha = n.dot(h).max(0.0);
+                let cos_beta = w_o.dot(h).max(Float::EPSILON);
+
+                let d = (p + 2.0) / (2.0 * Float::PI) * cos_alpha.powf(p);
+                let g = (2.0 * cos_alpha * cos_o / cos_beta).min(2.0 * cos_alpha * cos_i / cos_beta).min(1.0);
+                let r0 = ((refraction_index - 1.0) / (refraction_index + 1.0)).powi(2);
+                let f = r0 + (1.0 - r0) * (1.0 - cos_beta).powi(5);
+
+                let diffuse = if kd_fresnel { kd * (1.0 - f) } else { kd };
+                (diffuse / Float::PI + ks * (d * f * g / (4.0 * cos_i * cos_o))) * cos_i
+            }
+        }
+    }
+}
+
+
+/// BRDFs section of the scene, ids are shared among all models
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct SceneBRDFs {
+    #[serde(rename = "OriginalPhong")]
+    original_phong: SingleOrVec<BRDFEntry>,
+    #[serde(rename = "OriginalBlinnPhong")]
+    original_blinn_phong: SingleOrVec<BRDFEntry>,
+    #[serde(rename = "ModifiedPhong")]
+    modified_phong: SingleOrVec<BRDFEntry>,
+    #[serde(rename = "ModifiedBlinnPhong")]
+    modified_blinn_phong: SingleOrVec<BRDFEntry>,
+    #[serde(rename = "TorranceSparrow")]
+    torrance_sparrow: SingleOrVec<BRDFEntry>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct BRDFEntry {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Exponent", deserialize_with = "deser_opt_float")]
+    pub exponent: Option<Float>, // Overrides PhongExponent of the material if given
+    #[serde(rename = "_normalized", deserialize_with = "deser_bool")]
+    pub normalized: bool,
+    #[serde(rename = "_kdfresnel", deserialize_with = "deser_bool")]
+    pub kd_fresnel: bool,
+}
+
+impl SceneBRDFs {
+    /// Model and entry of given _id. refraction_index is the one of the material, used by Torrance-Sparrow.
+    pub fn get(&self, id: usize, refraction_index: Float) -> Option<(BRDFModel, BRDFEntry)> {
+        let find = |list: &SingleOrVec<BRDFEntry>| list.all().into_iter().find(|e| e._id == id);
+        if let Some(e) = find(&self.original_phong) {
+            return Some((BRDFModel::Phong, e));
+        }
+        if let Some(e) = find(&self.original_blinn_phong) {
+            return Some((BRDFModel::BlinnPhong, e));
+        }
+        if let Some(e) = find(&self.modified_phong) {
+            return Some((BRDFModel::ModifiedPhong { normalized: e.normalized }, e));
+        }
+        if let Some(e) = find(&self.modified_blinn_phong) {
+            return Some((BRDFModel::ModifiedBlinnPhong { normalized: e.normalized }, e));
+        }
+        find(&self.torrance_sparrow).map(|e| (BRDFModel::TorranceSparrow { kd_fresnel: e.kd_fresnel, refraction_index }, e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.original_phong.len() + self.original_blinn_phong.len() + self.modified_phong.len()
+            + self.modified_blinn_phong.len() + self.torrance_sparrow.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(model: BRDFModel, p: Float) -> BRDF {
+        BRDF { diffuse_rf: Vector3::ONE, specular_rf: Vector3::ONE, phong_exponent: p, model, ..Default::default() }
+    }
+
+    #[test]
+    fn test_normalized_models_conserve_energy() {
+        // Integrate f_r * cos over the hemisphere (midpoint rule in spherical coordinates), must not exceed 1 (kd + ks = 1)
+        let n = Vector3::Z;
+        let w_o = Vector3::new(0.3, 0.0, 1.0).normalize();
+        let models = [
+            BRDFModel::ModifiedPhong { normalized: true },
+            BRDFModel::ModifiedBlinnPhong { normalized: true },
+            BRDFModel::TorranceSparrow { kd_fresnel: true, refraction_index: 1.5 },
+        ];
+        for model in models {
+            let mut brdf = white(model, 20.0);
+            brdf.diffuse_rf = Vector3::splat(0.5);
+            brdf.specular_rf = Vector3::splat(0.5);
+            let (steps_theta, steps_phi) = (200, 400);
+            let mut reflected = 0.0;
+            for i in 0..steps_theta {
+                let theta = (i as Float + 0.5) / steps_theta as Float * Float::PI / 2.0;
+                for j in 0..steps_phi {
+                    let phi = (j as Float + 0.5) / steps_phi as Float * 2.0 * Float::PI;
+                    let w_i = Vector3::new(theta.sin() * phi.cos(), theta.sin() * phi.sin(), theta.cos());
+                    let d_omega = theta.sin() * (Float::PI / 2.0 / steps_theta as Float) * (2.0 * Float::PI / steps_phi as Float);
+                    reflected += brdf.eval(w_o, w_i, n).x * d_omega;
+                }
+            }
+            assert!(reflected <= 1.0 && reflected > 0.1, "{:?} reflects {}", model, reflected);
+        }
+    }
+
+    #[test]
+    fn test_no_light_from_below() {
+        let n = Vector3::Z;
+        let w_o = Vector3::Z;
+        let w_i = Vector3::new(0.0, 0.6, -0.8);
+        assert_eq!(white(BRDFModel::ModifiedPhong { normalized: false }, 10.0).eval(w_o, w_i, n), Vector3::ZERO);
+        assert_eq!(white(BRDFModel::Phong, 10.0).eval(w_o, w_i, n), Vector3::ZERO);
+    }
+}
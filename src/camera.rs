@@ -25,6 +25,15 @@ impl Cameras {
     pub fn all(&self) -> Vec<Camera> {
         self.camera.all()
     }
+
+    pub fn all_mut(&mut self) -> Vec<&mut Camera> {
+        self.camera.all_mut()
+    }
+
+    /// Keep only the cameras with given ids
+    pub fn select(&mut self, ids: &[Int]) {
+        self.camera.retain(|cam| ids.contains(&cam._id));
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -32,22 +41,22 @@ impl Cameras {
 #[serde(default)]
 pub struct Camera {
     #[serde(rename = "_id", deserialize_with = "deser_int")]
-    _id: Int,
+    pub _id: Int,
     
     #[default = ""]
-    _type: String,
+    pub(crate) _type: String,
 
     #[serde(rename = "Position", deserialize_with = "deser_vec3")]
-    position: Vector3,
+    pub(crate) position: Vector3,
 
     #[serde(rename = "Gaze", deserialize_with = "deser_vec3")]
-    gaze_dir: Vector3,
+    pub(crate) gaze_dir: Vector3,
 
     #[serde(rename = "GazePoint", deserialize_with = "deser_vec3")]
-    gaze_point: Vector3, // To be used if _type = "lookAt"
+    pub(crate) gaze_point: Vector3, // To be used if _type = "lookAt"
 
     #[serde(rename = "Up", deserialize_with = "deser_vec3")]
-    up: Vector3,
+    pub(crate) up: Vector3,
 
     #[serde(rename = "FovY", deserialize_with = "deser_float")]
     fovy: Float,
@@ -56,7 +65,7 @@ pub struct Camera {
     pub nearplane: NearPlane,
 
     #[serde(rename = "NearDistance", deserialize_with = "deser_float")]
-    near_distance: Float,
+    pub(crate) near_distance: Float,
 
     #[serde(rename = "ImageResolution", deserialize_with = "deser_pair")]
     pub image_resolution: [usize; 2],  
@@ -77,6 +86,9 @@ pub struct Camera {
     #[serde(skip)]
     u : Vector3,
 
+    #[serde(skip)]
+    corners: [Vector3; 4], // Cached nearplane corners, see setup( )
+
 }
 
 impl Camera {
@@ -104,7 +116,7 @@ impl Camera {
         // corrects Up vector if given Up was not perpendicular to
         // Gaze vector.
 
-        if self._type == String::from("lookAt") {
+        if self._type == "lookAt" {
             info!("Found camera _type = lookAt, constructing nearplane...");
             // (From h1.pdf) You can fnd the gaze direction by subtracting the camera position from this gaze point
             self.gaze_dir = self.gaze_point - self.position;
@@ -127,10 +139,25 @@ impl Camera {
         debug_assert!(approx_zero(self.u.dot(self.w))); 
         debug_assert!(approx_zero(self.v.dot(self.w))); 
         debug_assert!(approx_zero(self.v.dot(self.u))); 
+        self.corners = self.get_nearplane_corners();
         debug!("{:#?}", self);
         debug!("Nearplane corners are {:#?}", &self.get_nearplane_corners());
     }
 
+    /// Change the image resolution keeping the vertical extent of the image. A lookAt camera
+    /// derives its near plane from the aspect ratio in setup( ), an explicit near plane is
+    /// widened or narrowed around its center here so that the image is not stretched.
+    pub fn set_resolution(&mut self, resolution: [usize; 2]) {
+        if self._type != "lookAt" {
+            let aspect = |r: [usize; 2]| r[0] as Float / r[1].max(1) as Float;
+            let scale = aspect(resolution) / aspect(self.image_resolution);
+            let center = (self.nearplane.left + self.nearplane.right) / 2.0;
+            self.nearplane.left = center + (self.nearplane.left - center) * scale;
+            self.nearplane.right = center + (self.nearplane.right - center) * scale;
+        }
+        self.image_resolution = resolution;
+    }
+
     pub fn get_resolution(&self) -> (usize, usize) {
         (self.image_resolution[0], self.image_resolution[1])
     }
@@ -156,19 +183,34 @@ impl Camera {
         rays
     }
 
+    /// Ray through the point (col + offset.0, row + offset.1) on the image, offset (0.5, 0.5) is the pixel center.
+    /// WARNING: Expects setup( ) to be called beforehand.
+    pub fn generate_ray(&self, col: usize, row: usize, offset: (Float, Float)) -> Ray {
+        // Same interpolation as image::get_pixel_centers( ) but for a single point 
+        let (width, height) = self.get_resolution();
+        let u = (col as Float + offset.0) / width as Float;
+        let v = (row as Float + offset.1) / height as Float;
+
+        let [top_left, top_right, bottom_left, bottom_right] = self.corners;
+        let top = top_left * (1.0 - u) + top_right * u;
+        let bottom = bottom_left * (1.0 - u) + bottom_right * u;
+        let point = top * (1.0 - v) + bottom * v;
+        Ray::new(self.position, (point - self.position).normalize())
+    }
+
 
 }
 
 #[derive(Debug, Deserialize, Clone, Default)]
-pub(crate) struct NearPlane {
+pub struct NearPlane {
     #[serde(deserialize_with = "deser_float")]
-    pub(crate) left: Float,
+    pub left: Float,
     #[serde(deserialize_with = "deser_float")]
-    pub(crate) right: Float,
+    pub right: Float,
     #[serde(deserialize_with = "deser_float")]
-    pub(crate) bottom: Float,
+    pub bottom: Float,
     #[serde(deserialize_with = "deser_float")]
-    pub(crate) top: Float,
+    pub top: Float,
 }
 
 impl NearPlane {
@@ -0,0 +1,249 @@
+/*
+
+    Save and load the state of a progressive render so that
+    a render that dies (or is stopped) can be resumed later,
+    possibly with more samples.
+
+    A checkpoint stores the accumulated radiance, luminance
+    second moments and sample counts of a Film, number of
+    completed sample passes and the RNG seed. Since every
+    pixel sample has its own Sampler seeded by (seed, pixel,
+    pass), see sampler.rs, these two numbers fully describe
+    the RNG state. It also stores which scene file and camera
+    it belongs to, so that resuming does not mix the samples
+    of unrelated images, and whether its samples are spectral
+    since RGB and spectral samples do not average together.
+
+    File layout (little endian, always f64 regardless of Float):
+        magic   b"FTCKPT\0\0"
+        version u32
+        width, height, passes, seed  u64
+        has origin u64 (0 or 1), scene path hash u64, camera id i64
+        spectral u32 (0 or 1, 2 if unknown)
+        per pixel: radiance sum (3 x f64), luminance squared sum (f64),
+                   sample count (u32), moment count (u32)
+
+    Older versions can still be loaded. Version 4 and older files
+    do not know whether they are spectral. Version 3 and older files
+    do not know their scene and camera. Version 2 files have no
+    moment count, all samples are in the second moment. Version 1
+    files have no second moment at all, so adaptive sampling takes
+    resumed pixels as noisy until they have new samples (see
+    Film::relative_error).
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use crate::error::{TracerError, TracerResult};
+use crate::film::Film;
+use crate::numeric::{Float, Int, Vector3};
+
+const MAGIC: &[u8; 8] = b"FTCKPT\0\0";
+type F64 = f64; // Stored precision, independent of Float
+const VERSION: u32 = 5;
+
+/// Scene file and camera a checkpoint is rendered from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckpointOrigin {
+    pub scene_hash: u64,
+    pub camera_id: Int,
+}
+
+impl CheckpointOrigin {
+    pub fn new(scene_path: &Path, camera_id: Int) -> Self {
+        // Same scene may be given by a relative or an absolute path
+        let scene_path = scene_path.canonicalize().unwrap_or_else(|_| scene_path.to_path_buf());
+        // FNV-1a, unlike std's hasher it is stable between builds
+        let scene_hash = scene_path.to_string_lossy().bytes()
+            .fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+        Self { scene_hash, camera_id }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub film: Film,
+    pub passes: usize,
+    pub seed: u64,
+    pub origin: Option<CheckpointOrigin>, // None if unknown, e.g. for files older than version 4
+    pub spectral: Option<bool>, // None if unknown, e.g. for files older than version 5
+}
+
+impl Checkpoint {
+    /// Checkpoint file of an image, e.g. <dir>/cornellbox.ckpt for cornellbox.png
+    pub fn path_for(dir: &Path, image_name: &str) -> PathBuf {
+        dir.join(image_name).with_extension("ckpt")
+    }
+
+    pub fn save(&self, path: &Path) -> TracerResult<()> {
+        let io_error = |e| TracerError::io(path, e);
+        // Write to a temporary file first so that a crash during saving keeps the previous checkpoint
+        let tmp_path = path.with_extension("ckpt.tmp");
+        {
+            let film = &self.film;
+            let mut bytes = Vec::with_capacity(76 + film.radiance_sum.len() * 40);
+            bytes.extend_from_slice(MAGIC);
+            bytes.extend_from_slice(&VERSION.to_le_bytes());
+            for n in [film.width as u64, film.height as u64, self.passes as u64, self.seed] {
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            let (scene_hash, camera_id) = self.origin.map_or((0, 0), |o| (o.scene_hash, o.camera_id as i64));
+            for n in [self.origin.is_some() as u64, scene_hash, camera_id as u64] {
+                bytes.extend_from_slice(&n.to_le_bytes());
+            }
+            bytes.extend_from_slice(&self.spectral.map_or(2u32, u32::from).to_le_bytes());
+            for i in 0..film.radiance_sum.len() {
+                let sum = film.radiance_sum[i];
+                for c in [sum.x, sum.y, sum.z, film.luminance_sq_sum[i]] {
+                    bytes.extend_from_slice(&(c as F64).to_le_bytes());
+                }
+                for n in [film.sample_count[i], film.moment_count[i]] {
+                    bytes.extend_from_slice(&n.to_le_bytes());
+                }
+            }
+            std::fs::write(&tmp_path, &bytes).map_err(io_error)?;
+        }
+        std::fs::rename(&tmp_path, path).map_err(io_error)
+    }
+
+    pub fn load(path: &Path) -> TracerResult<Self> {
+        let io_error = |e| TracerError::io(path, e);
+        let invalid = |message: &str| TracerError::io(path, std::io::Error::new(std::io::ErrorKind::InvalidData, message.to_string()));
+
+        let file = File::open(path).map_err(io_error)?;
+        let mut bytes = Vec::new();
+        BufReader::new(file).read_to_end(&mut bytes).map_err(io_error)?;
+
+        let mut reader = ByteReader { bytes: &bytes, position: 0 };
+        if reader.take(8) != Some(MAGIC.as_slice()) {
+            return Err(invalid("not a checkpoint file"));
+        }
+        let version = reader.u32().ok_or_else(|| invalid("truncated header"))?;
+        if version == 0 || version > VERSION {
+            return Err(invalid(&format!("unsupported checkpoint version {}", version)));
+        }
+        let mut header = [0u64; 4];
+        for h in header.iter_mut() {
+            *h = reader.u64().ok_or_else(|| invalid("truncated header"))?;
+        }
+        let [width, height, passes, seed] = header;
+        let origin = if version >= 4 {
+            match (reader.u64(), reader.u64(), reader.u64()) {
+                (Some(known), Some(scene_hash), Some(camera_id)) => {
+                    (known != 0).then_some(CheckpointOrigin { scene_hash, camera_id: camera_id as i64 as Int })
+                }
+                _ => return Err(invalid("truncated header")),
+            }
+        } else {
+            None
+        };
+        let spectral = if version >= 5 {
+            match reader.u32().ok_or_else(|| invalid("truncated header"))? {
+                2 => None,
+                flag => Some(flag != 0),
+            }
+        } else {
+            None
+        };
+
+        let mut film = Film::new(width as usize, height as usize);
+        for i in 0..film.radiance_sum.len() {
+            let (x, y, z) = match (reader.f64(), reader.f64(), reader.f64()) {
+                (Some(x), Some(y), Some(z)) => (x, y, z),
+                _ => return Err(invalid("truncated pixel data")),
+            };
+            film.radiance_sum[i] = Vector3::new(x as Float, y as Float, z as Float);
+            if version >= 2 {
+                film.luminance_sq_sum[i] = reader.f64().ok_or_else(|| invalid("truncated pixel data"))? as Float;
+            }
+            film.sample_count[i] = reader.u32().ok_or_else(|| invalid("truncated pixel data"))?;
+            film.moment_count[i] = match version {
+                1 => 0,
+                2 => film.sample_count[i],
+                _ => reader.u32().ok_or_else(|| invalid("truncated pixel data"))?,
+            };
+        }
+        Ok(Self { film, passes: passes as usize, seed, origin, spectral })
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.position..self.position + n)?;
+        self.position += n;
+        Some(slice)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4).map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        self.take(8).map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn f64(&mut self) -> Option<f64> {
+        self.take(8).map(|b| f64::from_le_bytes(b.try_into().unwrap()))
+    }
+}
+
+
+/// Checkpoint in the version 1 layout, where every pixel has the same radiance sum and passes samples
+#[cfg(test)]
+pub(crate) fn version_1_bytes(width: usize, height: usize, passes: usize, seed: u64, radiance_sum: Vector3) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    for n in [width as u64, height as u64, passes as u64, seed] {
+        bytes.extend_from_slice(&n.to_le_bytes());
+    }
+    for _ in 0..width * height {
+        for c in radiance_sum.to_array() {
+            bytes.extend_from_slice(&(c as F64).to_le_bytes());
+        }
+        bytes.extend_from_slice(&(passes as u32).to_le_bytes());
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let mut film = Film::new(3, 2);
+        film.add_sample(4, Vector3::new(1.0, 2.0, 3.0));
+        film.add_sample(4, Vector3::new(0.5, 0.5, 0.5));
+        let origin = CheckpointOrigin::new(Path::new("scenes/cornellbox.json"), -3);
+        let checkpoint = Checkpoint { film, passes: 2, seed: 7, origin: Some(origin), spectral: Some(true) };
+
+        let path = std::env::temp_dir().join("furry_tracer_test_checkpoint.ckpt");
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!((loaded.film.width, loaded.film.height, loaded.passes, loaded.seed), (3, 2, 2, 7));
+        assert_eq!(loaded.film.sample_count, checkpoint.film.sample_count);
+        assert_eq!(loaded.film.mean(4), Vector3::new(0.75, 1.25, 1.75));
+        assert_eq!(loaded.film.moment_count, checkpoint.film.moment_count);
+        assert_eq!((loaded.origin, loaded.spectral), (Some(origin), Some(true)));
+    }
+
+    #[test]
+    fn test_load_version_1() {
+        let path = std::env::temp_dir().join("furry_tracer_test_checkpoint_v1.ckpt");
+        std::fs::write(&path, version_1_bytes(1, 1, 8, 7, Vector3::splat(16.0))).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!((loaded.passes, loaded.seed, loaded.origin, loaded.spectral), (8, 7, None, None));
+        assert_eq!((loaded.film.sample_count[0], loaded.film.moment_count[0]), (8, 0));
+        assert_eq!(loaded.film.mean(0), Vector3::splat(2.0));
+    }
+}
@@ -0,0 +1,241 @@
+/*
+
+    Command line interface of the raytracer binary.
+
+    Usage:
+        raytracer render [options] <scene.json>...
+        raytracer info <scene.json>...
+        raytracer compare [-o <heatmap.png>] <image> <reference>
+        raytracer <scene.json>...          (same as render)
+
+    Multiple scenes can be given to render them in a batch,
+    a failing scene is reported and the rest are still rendered.
+
+    Arguments are parsed by hand to avoid pulling in another
+    dependency for a handful of flags.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use furry_tracer::numeric::Int;
+use furry_tracer::{AdaptiveSettings, ProgressiveSettings, RenderOverrides, RenderSettings, ValidationMode};
+
+pub const USAGE: &str = "\
+Usage:
+    raytracer render [options] <scene.json>...
+    raytracer info <scene.json>...
+    raytracer compare [-o <heatmap.png>] <image> <reference>
+
+Compare reports MSE, PSNR and SSIM of two PNG or PFM images and saves
+a heatmap of their difference (default: <image>_diff.png).
+
+Render options:
+    -o, --output <dir>        Directory to save images into (default: ./)
+    -c, --camera <id>[,<id>]  Only render cameras with given ids (repeatable)
+    -r, --resolution <WxH>    Override image resolution of every camera
+    -s, --samples <n>         Override number of samples per pixel
+    -d, --max-depth <n>       Override maximum recursion depth
+    -j, --threads <n>         Number of render threads (default: all cores)
+        --tile-size <n>       Width and height of render tiles in pixels (default: 32)
+        --tile-order <order>  Order to render tiles: scanline, spiral or hilbert (default: spiral)
+        --seed <n>            Seed of random sampling (default: 0)
+        --progressive         Periodically save intermediate images and checkpoints to output dir
+        --checkpoint-interval <s>  Seconds between checkpoints (default: 60), implies --progressive
+        --resume              Continue from checkpoints in output dir, e.g. with more --samples
+        --time-budget <s>     Stop sampling after given seconds (for all cameras), ignores --samples
+        --target-error <x>    Sample noisy pixels until their relative error is below x, e.g. 0.01
+        --min-samples <n>     Samples per pixel before checking the error (default: 8), implies --target-error
+        --max-samples <n>     Samples per pixel at most (default: 1024), implies --target-error
+        --spectral            Trace a wavelength per sample, for dispersion of dielectrics and tabulated metals
+        --strict              Refuse to render scenes failing validation
+    -h, --help                Print this message";
+
+#[derive(Debug, Clone)]
+pub struct RenderArgs {
+    pub output_dir: PathBuf,
+    pub overrides: RenderOverrides,
+    pub settings: RenderSettings,
+    pub threads: Option<usize>,
+    pub validation: ValidationMode,
+}
+
+impl Default for RenderArgs {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from("./"),
+            overrides: RenderOverrides::default(),
+            settings: RenderSettings::default(),
+            threads: None,
+            validation: ValidationMode::Lenient,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Command {
+    Render { scenes: Vec<PathBuf>, args: Box<RenderArgs> },
+    Info { scenes: Vec<PathBuf> },
+    Compare { image: PathBuf, reference: PathBuf, heatmap: PathBuf },
+    Help,
+}
+
+/// Parse arguments excluding the program name
+pub fn parse_args<I>(args: I) -> Result<Command, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+    let subcommand = match args.peek().map(|s| s.as_str()) {
+        None => return Err(String::from("No scene was provided")),
+        Some("render") | Some("info") | Some("compare") => args.next().unwrap(),
+        Some(_) => String::from("render"), // Allow `raytracer scene.json` as before
+    };
+
+    let mut render_args = RenderArgs::default();
+    let mut scenes = Vec::new();
+    let mut progressive: Option<ProgressiveSettings> = None;
+    let mut output: Option<PathBuf> = None;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-o" | "--output" => output = Some(PathBuf::from(value(&arg)?)),
+            "-c" | "--camera" => {
+                for id in value(&arg)?.split(',') {
+                    render_args.overrides.camera_ids.push(parse_number::<Int>(&arg, id)?);
+                }
+            }
+            "-r" | "--resolution" => render_args.overrides.resolution = Some(parse_resolution(&value(&arg)?)?),
+            "-s" | "--samples" => render_args.overrides.num_samples = Some(parse_positive::<Int>(&arg, &value(&arg)?)?),
+            "-d" | "--max-depth" => render_args.overrides.max_recursion_depth = Some(parse_positive::<usize>(&arg, &value(&arg)?)?),
+            "-j" | "--threads" => render_args.threads = Some(parse_positive::<usize>(&arg, &value(&arg)?)?),
+            "--tile-size" => render_args.settings.tile_size = parse_positive::<usize>(&arg, &value(&arg)?)?,
+            "--tile-order" => render_args.settings.tile_order = value(&arg)?.parse()?,
+            "--seed" => render_args.settings.seed = parse_number::<u64>(&arg, &value(&arg)?)?,
+            "--progressive" => { progressive.get_or_insert_with(ProgressiveSettings::default); }
+            "--checkpoint-interval" => {
+                let seconds = parse_positive::<f64>(&arg, &value(&arg)?)?;
+                progressive.get_or_insert_with(ProgressiveSettings::default).checkpoint_interval = Duration::from_secs_f64(seconds);
+            }
+            "--resume" => progressive.get_or_insert_with(ProgressiveSettings::default).resume = true,
+            "--time-budget" => render_args.settings.time_budget = Some(Duration::from_secs_f64(parse_positive::<f64>(&arg, &value(&arg)?)?)),
+            "--target-error" => render_args.settings.adaptive.get_or_insert_with(AdaptiveSettings::default).target_error = parse_positive(&arg, &value(&arg)?)?,
+            "--min-samples" => render_args.settings.adaptive.get_or_insert_with(AdaptiveSettings::default).min_samples = parse_positive(&arg, &value(&arg)?)?,
+            "--max-samples" => render_args.settings.adaptive.get_or_insert_with(AdaptiveSettings::default).max_samples = parse_positive(&arg, &value(&arg)?)?,
+            "--spectral" => render_args.settings.spectral = true,
+            "--strict" => render_args.validation = ValidationMode::Strict,
+            flag if flag.starts_with('-') => return Err(format!("Unknown option '{}'", flag)),
+            scene => scenes.push(PathBuf::from(scene)),
+        }
+    }
+
+    if subcommand == "compare" {
+        let [image, reference]: [PathBuf; 2] = scenes.try_into().map_err(|_| String::from("Expected two images to compare"))?;
+        let heatmap = output.unwrap_or_else(|| {
+            let stem = image.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            PathBuf::from(format!("{}_diff.png", stem))
+        });
+        return Ok(Command::Compare { image, reference, heatmap });
+    }
+    if scenes.is_empty() {
+        return Err(String::from("No scene was provided"));
+    }
+    if let Some(output) = output {
+        render_args.output_dir = output;
+    }
+    // Checkpoints are saved next to the images
+    render_args.settings.progressive = progressive.map(|p| ProgressiveSettings { output_dir: render_args.output_dir.clone(), ..p });
+    match subcommand.as_str() {
+        "info" => Ok(Command::Info { scenes }),
+        _ => Ok(Command::Render { scenes, args: Box::new(render_args) }),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, s: &str) -> Result<T, String> {
+    s.trim().parse::<T>().map_err(|_| format!("Invalid value '{}' for {}", s, flag))
+}
+
+fn parse_positive<T: std::str::FromStr + PartialOrd + Default>(flag: &str, s: &str) -> Result<T, String> {
+    let n = parse_number::<T>(flag, s)?;
+    if n <= T::default() {
+        return Err(format!("Expected a positive value for {}, got '{}'", flag, s));
+    }
+    Ok(n)
+}
+
+fn parse_resolution(s: &str) -> Result<[usize; 2], String> {
+    // Expects <width>x<height> e.g. 800x600
+    let (w, h) = s.split_once(['x', 'X']).ok_or_else(|| format!("Expected resolution as WxH, got '{}'", s))?;
+    Ok([parse_positive("--resolution", w)?, parse_positive("--resolution", h)?])
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use furry_tracer::tile::TileOrder;
+
+    fn parse(s: &str) -> Result<Command, String> {
+        parse_args(s.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn test_render_overrides() {
+        let Ok(Command::Render { scenes, args }) = parse("render -o out -c 1,3 -r 320x240 -s 4 -d 3 -j 2 a.json b.json") else {
+            panic!("Expected render command");
+        };
+        assert_eq!(scenes, vec![PathBuf::from("a.json"), PathBuf::from("b.json")]);
+        assert_eq!(args.output_dir, PathBuf::from("out"));
+        assert_eq!(args.overrides.camera_ids, vec![1, 3]);
+        assert_eq!(args.overrides.resolution, Some([320, 240]));
+        assert_eq!(args.overrides.num_samples, Some(4));
+        assert_eq!(args.overrides.max_recursion_depth, Some(3));
+        assert_eq!(args.threads, Some(2));
+
+        let Ok(Command::Render { args, .. }) = parse("render --tile-size 8 --tile-order hilbert a.json") else {
+            panic!("Expected render command");
+        };
+        assert_eq!(args.settings.tile_size, 8);
+        assert_eq!(args.settings.tile_order, TileOrder::Hilbert);
+        assert!(args.settings.progressive.is_none());
+        assert!(!args.settings.spectral);
+
+        let Ok(Command::Render { args, .. }) = parse("render --spectral a.json") else {
+            panic!("Expected render command");
+        };
+        assert!(args.settings.spectral);
+
+        let Ok(Command::Render { args, .. }) = parse("render --resume -o out a.json") else {
+            panic!("Expected render command");
+        };
+        let progressive = args.settings.progressive.unwrap();
+        assert!(progressive.resume);
+        assert_eq!(progressive.output_dir, PathBuf::from("out"));
+
+        let Ok(Command::Render { args, .. }) = parse("render --time-budget 1.5 --max-samples 64 a.json") else {
+            panic!("Expected render command");
+        };
+        assert_eq!(args.settings.time_budget, Some(Duration::from_millis(1500)));
+        let adaptive = args.settings.adaptive.unwrap();
+        assert_eq!((adaptive.min_samples, adaptive.max_samples), (8, 64));
+    }
+
+    #[test]
+    fn test_positional_scene_renders() {
+        assert!(matches!(parse("scene.json"), Ok(Command::Render { .. })));
+        assert!(matches!(parse("info scene.json"), Ok(Command::Info { .. })));
+        assert!(parse("render").is_err());
+        assert!(parse("render -s 0 scene.json").is_err());
+        assert!(parse("render --bogus scene.json").is_err());
+
+        let Ok(Command::Compare { image, reference, heatmap }) = parse("compare out/a.png ref/a.png") else {
+            panic!("Expected compare command");
+        };
+        assert_eq!((image, reference, heatmap), (PathBuf::from("out/a.png"), PathBuf::from("ref/a.png"), PathBuf::from("a_diff.png")));
+        assert!(parse("compare a.png").is_err());
+    }
+}
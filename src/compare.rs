@@ -0,0 +1,188 @@
+/*
+
+    Compare two images of the same size, e.g. a render
+    against a reference image in golden image tests
+    (see tests/golden.rs) or with `raytracer compare`.
+
+    Reported metrics are MSE, RMSE, PSNR and SSIM (mean of
+    7x7 windows on luminance, as in Wang et al. 2004 with
+    a uniform window). Where the images differ is shown by
+    a false-color heatmap.
+
+    Errors are computed on displayed 8-bit values, i.e.
+    colors are clamped to [0, 255] and truncated the same
+    way as ImageData::to_rgb( ) does before saving a PNG,
+    so a render and its saved PNG compare as identical.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fmt;
+
+use crate::error::{TracerError, TracerResult};
+use crate::image::ImageData;
+use crate::film::luminance;
+use crate::numeric::{Float, Vector3};
+
+const PEAK: Float = 255.0;
+const DIFF_SCALE: Float = 10.0; // Amplify differences in diff images so that small ones are visible
+const SSIM_WINDOW: usize = 7;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ImageDiff {
+    pub mse: Float,
+    pub rmse: Float,
+    pub psnr: Float, // In dB, infinite for identical images
+    pub max_error: Float, // Largest difference of a single channel
+    pub num_different: usize, // Pixels with any channel different
+    pub ssim: Float, // 1 for identical images
+}
+
+impl fmt::Display for ImageDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MSE {:.4}, RMSE {:.4}, PSNR {:.2} dB, SSIM {:.4}, max error {}, {} pixel(s) differ",
+            self.mse, self.rmse, self.psnr, self.ssim, self.max_error, self.num_different)
+    }
+}
+
+fn displayed(color: Vector3) -> Vector3 {
+    color.clamp(Vector3::ZERO, Vector3::splat(PEAK)).trunc()
+}
+
+fn check_same_size(a: &ImageData, b: &ImageData) -> TracerResult<()> {
+    if (a.width(), a.height()) != (b.width(), b.height()) {
+        return Err(TracerError::Image(format!(
+            "can not compare {} ({}x{}) with {} ({}x{})",
+            a.name(), a.width(), a.height(), b.name(), b.width(), b.height()
+        )));
+    }
+    Ok(())
+}
+
+pub fn compare(a: &ImageData, b: &ImageData) -> TracerResult<ImageDiff> {
+    check_same_size(a, b)?;
+    let mut squared_sum = 0.0;
+    let mut max_error: Float = 0.0;
+    let mut num_different = 0;
+    for (&ca, &cb) in a.pixel_colors().iter().zip(b.pixel_colors()) {
+        let d = (displayed(ca) - displayed(cb)).abs();
+        squared_sum += d.length_squared();
+        max_error = max_error.max(d.max_element());
+        if d != Vector3::ZERO {
+            num_different += 1;
+        }
+    }
+    let num_values = (3 * a.pixel_colors().len()).max(1) as Float;
+    let mse = squared_sum / num_values;
+    let rmse = mse.sqrt();
+    let psnr = if rmse == 0.0 { Float::INFINITY } else { 20.0 * (PEAK / rmse).log10() };
+    let ssim = ssim(a, b);
+    Ok(ImageDiff { mse, rmse, psnr, max_error, num_different, ssim })
+}
+
+fn ssim(a: &ImageData, b: &ImageData) -> Float {
+    // Mean SSIM of all (fully inside) windows, whole image is a single window if smaller than that
+    let (width, height) = (a.width(), a.height());
+    let la: Vec<Float> = a.pixel_colors().iter().map(|&c| luminance(displayed(c))).collect();
+    let lb: Vec<Float> = b.pixel_colors().iter().map(|&c| luminance(displayed(c))).collect();
+    let (c1, c2) = ((0.01 * PEAK).powi(2), (0.03 * PEAK).powi(2));
+    let (win_w, win_h) = (SSIM_WINDOW.min(width), SSIM_WINDOW.min(height));
+    if win_w == 0 || win_h == 0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut num_windows = 0;
+    for y in 0..=(height - win_h) {
+        for x in 0..=(width - win_w) {
+            let (mut sa, mut sb, mut saa, mut sbb, mut sab) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            for row in y..y + win_h {
+                for i in row * width + x..row * width + x + win_w {
+                    let (va, vb) = (la[i], lb[i]);
+                    sa += va;
+                    sb += vb;
+                    saa += va * va;
+                    sbb += vb * vb;
+                    sab += va * vb;
+                }
+            }
+            let n = (win_w * win_h) as Float;
+            let (mean_a, mean_b) = (sa / n, sb / n);
+            let var_a = saa / n - mean_a * mean_a;
+            let var_b = sbb / n - mean_b * mean_b;
+            let covariance = sab / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + c1) * (2.0 * covariance + c2))
+                / ((mean_a * mean_a + mean_b * mean_b + c1) * (var_a + var_b + c2));
+            num_windows += 1;
+        }
+    }
+    total / num_windows as Float
+}
+
+/// Absolute per-channel difference, amplified by DIFF_SCALE
+pub fn diff_image(a: &ImageData, b: &ImageData, name: &str) -> TracerResult<ImageData> {
+    check_same_size(a, b)?;
+    let colors = a.pixel_colors().iter().zip(b.pixel_colors())
+        .map(|(&ca, &cb)| (displayed(ca) - displayed(cb)).abs() * DIFF_SCALE)
+        .collect();
+    Ok(ImageData::new(a.width(), a.height(), name.to_string(), colors))
+}
+
+/// False-color heatmap of the per-pixel error (mean of channel differences),
+/// black for no difference up to white for max_error and above.
+/// max_error of None uses the largest difference in the image.
+pub fn heatmap(a: &ImageData, b: &ImageData, max_error: Option<Float>, name: &str) -> TracerResult<ImageData> {
+    check_same_size(a, b)?;
+    let errors: Vec<Float> = a.pixel_colors().iter().zip(b.pixel_colors())
+        .map(|(&ca, &cb)| (displayed(ca) - displayed(cb)).abs().element_sum() / 3.0)
+        .collect();
+    let max_error = max_error.unwrap_or_else(|| errors.iter().cloned().fold(0.0, Float::max));
+    let colors = errors.iter()
+        .map(|&e| colormap(if max_error > 0.0 { e / max_error } else { 0.0 }))
+        .collect();
+    Ok(ImageData::new(a.width(), a.height(), name.to_string(), colors))
+}
+
+fn colormap(t: Float) -> Vector3 {
+    // Piecewise linear approximation of a black-purple-red-yellow-white ramp (like inferno)
+    const STOPS: [[Float; 3]; 5] = [
+        [0.0, 0.0, 0.0],
+        [87.0, 16.0, 110.0],
+        [188.0, 55.0, 84.0],
+        [249.0, 142.0, 9.0],
+        [252.0, 255.0, 164.0],
+    ];
+    let t = t.clamp(0.0, 1.0) * (STOPS.len() - 1) as Float;
+    let i = (t.floor() as usize).min(STOPS.len() - 2);
+    let (lo, hi) = (Vector3::from_array(STOPS[i]), Vector3::from_array(STOPS[i + 1]));
+    lo.lerp(hi, t - i as Float)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let a = ImageData::new(2, 1, String::from("a"), vec![Vector3::splat(100.0), Vector3::splat(300.0)]);
+        let b = ImageData::new(2, 1, String::from("b"), vec![Vector3::splat(100.5), Vector3::splat(255.0)]);
+        let diff = compare(&a, &b).unwrap();
+        assert_eq!(diff.rmse, 0.0); // Same once clamped and truncated
+        assert_eq!(diff.psnr, Float::INFINITY);
+        assert!((diff.ssim - 1.0).abs() < 1e-9);
+
+        let c = ImageData::new(2, 1, String::from("c"), vec![Vector3::splat(90.0), Vector3::splat(255.0)]);
+        let diff = compare(&a, &c).unwrap();
+        assert!((diff.rmse - (50.0 as Float).sqrt()).abs() < 1e-9);
+        assert_eq!((diff.max_error, diff.num_different), (10.0, 1));
+        assert!(diff.ssim < 1.0);
+        let map = heatmap(&a, &c, None, "heatmap").unwrap();
+        assert_eq!(map.pixel_colors()[0], Vector3::new(252.0, 255.0, 164.0));
+        assert_eq!(map.pixel_colors()[1], Vector3::ZERO);
+
+        let d = ImageData::new(1, 2, String::from("d"), vec![Vector3::ZERO; 2]);
+        assert!(compare(&a, &d).is_err());
+    }
+}
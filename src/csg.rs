@@ -0,0 +1,275 @@
+/*
+
+    Constructive solid geometry: union, intersection and
+    difference of closed shapes, e.g. a sphere with a hole
+    drilled by a cylinder or a lens as the intersection of
+    two spheres. Trees are declared in JSON with nested
+    operands, e.g.
+        "CSG": {
+            "_id": "1",
+            "Operation": "difference",
+            "Left": { "Sphere": { "_id": "1", "Material": "1", "Center": "1", "Radius": "1" } },
+            "Right": { "CSG": { "_id": "2", "Operation": "union", "Left": ..., "Right": ... } }
+        }
+    where an operand is a Sphere, Cylinder, Cone, Box, Torus
+    or another CSG. Operation is one of union, intersection
+    and difference (Left minus Right). An optional Material
+    overrides materials of all operands, e.g. so that a glass
+    lens is a single medium.
+
+    Closed shapes report the spans of a ray inside them (see
+    PrimitiveShape::spans( )), and a node combines spans of
+    its operands sweeping over their boundaries. Surfaces
+    carved by a difference keep the material of Right, with
+    their normals flipped.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use serde::Deserialize;
+
+use crate::json_parser::*;
+use crate::interval::Interval;
+use crate::numeric::{approx_zero, Float, Vector3};
+use crate::ray::{HitRecord, Ray};
+use crate::shapes::{Cone, Cuboid, Cylinder, HeapAllocatedVerts, PrimitiveShape, Sphere, Torus};
+
+/// Where a ray crosses the surface of a closed shape, normal points outwards
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceHit {
+    pub t: Float,
+    pub normal: Vector3,
+    pub material: usize,
+    pub uv: Option<[Float; 2]>,
+}
+
+impl SurfaceHit {
+    /// HitRecord with the normal facing the ray
+    pub fn to_record(self, ray: &Ray) -> HitRecord {
+        let is_front_face = ray.is_front_face(self.normal);
+        let normal = if is_front_face { self.normal } else { -self.normal };
+        let record = HitRecord::new(ray.at(self.t), normal, self.t, self.material, is_front_face);
+        match self.uv {
+            Some(uv) => record.with_uv(uv),
+            None => record,
+        }
+    }
+}
+
+/// Part of a ray inside a closed shape
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub enter: SurfaceHit,
+    pub exit: SurfaceHit,
+}
+
+impl Span {
+    /// Spans between consecutive crossings of a closed surface. Crossings at the same t
+    /// (e.g. on the rim of a cylinder) are merged, if their count is still odd the ray
+    /// grazed the shape and it is treated as a miss.
+    pub fn from_crossings(mut crossings: Vec<SurfaceHit>) -> Vec<Span> {
+        crossings.sort_by(|a, b| a.t.total_cmp(&b.t));
+        crossings.dedup_by(|b, a| approx_zero(b.t - a.t));
+        if !crossings.len().is_multiple_of(2) {
+            return Vec::new();
+        }
+        crossings.chunks_exact(2).map(|pair| Span { enter: pair[0], exit: pair[1] }).collect()
+    }
+}
+
+/// Closest boundary of spans within the interval
+pub fn first_hit(spans: &[Span], ray: &Ray, t_interval: &Interval) -> Option<HitRecord> {
+    spans.iter()
+        .flat_map(|span| [span.enter, span.exit])
+        .find(|hit| t_interval.contains(hit.t))
+        .map(|hit| hit.to_record(ray))
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CsgOperation {
+    #[default]
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Spans of the result given sorted, disjoint spans of both operands
+pub fn combine(operation: CsgOperation, left: &[Span], right: &[Span]) -> Vec<Span> {
+    // Boundaries of both operands in t order, with the operand they belong to and whether they enter it
+    let mut boundaries: Vec<(SurfaceHit, bool, bool)> = Vec::new();
+    for (spans, is_left) in [(left, true), (right, false)] {
+        for span in spans {
+            boundaries.push((span.enter, is_left, true));
+            boundaries.push((span.exit, is_left, false));
+        }
+    }
+    boundaries.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+    let (mut in_left, mut in_right) = (false, false);
+    let mut enter: Option<SurfaceHit> = None;
+    let mut spans = Vec::new();
+    for (mut hit, is_left, enters) in boundaries {
+        if is_left { in_left = enters } else { in_right = enters }
+        if !is_left && operation == CsgOperation::Difference {
+            hit.normal = -hit.normal; // Inside of Right is outside of the result
+        }
+        match (enter, operation.contains(in_left, in_right)) {
+            (None, true) => enter = Some(hit),
+            (Some(enter_hit), false) => {
+                spans.push(Span { enter: enter_hit, exit: hit });
+                enter = None;
+            }
+            _ => {}
+        }
+    }
+    spans
+}
+
+/// Closed shapes that can be combined, tagged as in SceneObjects
+#[derive(Debug, Deserialize, Clone)]
+pub enum CsgOperand {
+    Sphere(Sphere),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    #[serde(rename = "Box")]
+    Cuboid(Cuboid),
+    Torus(Torus),
+    #[serde(rename = "CSG")]
+    Csg(Box<Csg>),
+}
+
+impl CsgOperand {
+    pub fn shape(&self) -> &dyn PrimitiveShape {
+        match self {
+            CsgOperand::Sphere(s) => s,
+            CsgOperand::Cylinder(c) => c,
+            CsgOperand::Cone(c) => c,
+            CsgOperand::Cuboid(b) => b,
+            CsgOperand::Torus(t) => t,
+            CsgOperand::Csg(c) => c.as_ref(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Csg {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Operation")]
+    pub operation: CsgOperation,
+    #[serde(rename = "Material", default, deserialize_with = "deser_opt_usize")]
+    pub material_idx: Option<usize>, // Overrides materials of operands if given
+    #[serde(rename = "Left")]
+    pub left: CsgOperand,
+    #[serde(rename = "Right")]
+    pub right: CsgOperand,
+}
+
+impl PrimitiveShape for Csg {
+
+    fn indices(&self) -> Vec<usize> {
+        let mut indices = self.left.shape().indices();
+        indices.extend(self.right.shape().indices());
+        indices
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx.unwrap_or_else(|| self.left.shape().material_idx())
+    }
+
+    fn materials(&self) -> Vec<usize> {
+        match self.material_idx {
+            Some(material) => vec![material],
+            None => [self.left.shape().materials(), self.right.shape().materials()].concat(),
+        }
+    }
+
+    fn spans(&self, ray: &Ray, vertex_cache: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        let left = self.left.shape().spans(ray, vertex_cache)?;
+        let right = self.right.shape().spans(ray, vertex_cache)?;
+        let mut spans = combine(self.operation, &left, &right);
+        if let Some(material) = self.material_idx {
+            for span in spans.iter_mut() {
+                span.enter.material = material;
+                span.exit.material = material;
+            }
+        }
+        Some(spans)
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        first_hit(&self.spans(ray, vertex_cache)?, ray, t_interval)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::dataforms::VertexData;
+    use crate::shapes::VertexCache;
+
+    fn span(enter: Float, exit: Float, material: usize) -> Span {
+        let hit = |t, normal| SurfaceHit { t, normal, material, uv: None };
+        Span { enter: hit(enter, -Vector3::X), exit: hit(exit, Vector3::X) }
+    }
+
+    fn bounds(spans: &[Span]) -> Vec<(Float, Float)> {
+        spans.iter().map(|s| (s.enter.t, s.exit.t)).collect()
+    }
+
+    #[test]
+    fn test_combine_spans() {
+        let left = [span(0.0, 4.0, 1), span(6.0, 8.0, 1)];
+        let right = [span(2.0, 7.0, 2)];
+        assert_eq!(bounds(&combine(CsgOperation::Union, &left, &right)), vec![(0.0, 8.0)]);
+        assert_eq!(bounds(&combine(CsgOperation::Intersection, &left, &right)), vec![(2.0, 4.0), (6.0, 7.0)]);
+
+        let difference = combine(CsgOperation::Difference, &left, &right);
+        assert_eq!(bounds(&difference), vec![(0.0, 2.0), (7.0, 8.0)]);
+        // Carved surfaces come from Right, facing into its inside
+        assert_eq!(difference[0].exit.material, 2);
+        assert_eq!(difference[0].exit.normal, Vector3::X);
+        assert_eq!(difference[1].enter.normal, -Vector3::X);
+        assert!(combine(CsgOperation::Difference, &right, &[span(1.0, 9.0, 1)]).is_empty());
+    }
+
+    #[test]
+    fn test_drilled_sphere() {
+        let json = r#"{
+            "_id": "1", "Operation": "difference", "Material": "3",
+            "Left": { "Sphere": { "_id": "1", "Material": "1", "Center": "1", "Radius": "1" } },
+            "Right": { "Cylinder": { "_id": "1", "Material": "2", "Base": "2", "Axis": "0 0 1", "Radius": "0.25", "Height": "4" } }
+        }"#;
+        let csg: Csg = serde_json::from_str(json).unwrap();
+        let mut verts: VertexData = "0 0 0  0 0 -2".parse().unwrap();
+        verts.insert_dummy_at_the_beginning();
+        let cache = Arc::new(VertexCache::build(&verts, &[]));
+        let interval = Interval::positive(1e-6);
+
+        // Down the hole
+        assert!(csg.intersects_with(&Ray::new(Vector3::new(0.0, 0.0, 5.0), -Vector3::Z), &interval, &cache).is_none());
+        // Beside the hole
+        let hit = csg.intersects_with(&Ray::new(Vector3::new(0.5, 0.0, 5.0), -Vector3::Z), &interval, &cache).unwrap();
+        assert!((hit.ray_t - (5.0 - (0.75 as Float).sqrt())).abs() < 1e-5);
+        assert_eq!(hit.material, 3);
+        // Into the wall of the hole from its inside, normal faces the ray
+        let wall = csg.intersects_with(&Ray::new(Vector3::ZERO, Vector3::X), &interval, &cache).unwrap();
+        assert!((wall.ray_t - 0.25).abs() < 1e-5 && wall.is_front_face);
+        assert!(wall.normal.abs_diff_eq(-Vector3::X, 1e-5));
+    }
+}
@@ -8,11 +8,8 @@
 */
 
 
-use std::fs::File;
-use std::io::BufReader;
-use void::Void;
 use std::{ops::Index, str::FromStr};
-use tracing::{warn, info};
+use tracing::warn;
 use serde::{Deserialize, de::{Deserializer}};
 use crate::numeric::{Vector3};
 use crate::json_parser::{deser_vertex_data, deser_usize_vec, parse_string_vecvec3};
@@ -24,6 +21,7 @@ pub struct DataField<T> {
     pub(crate) _data: Vec<T>,
     pub(crate) _type: String,
     pub(crate) _ply_file: String,
+    pub(crate) _obj_file: String,
 }
 
 impl<T> Index<usize> for DataField<T> {
@@ -50,6 +48,8 @@ impl<'de> Deserialize<'de> for DataField<Vector3> {
             _type: String,
             #[serde(rename = "_plyFile", default)]
             _ply_file: String,
+            #[serde(rename = "_objFile", default)]
+            _obj_file: String,
         }
 
         let helper = Helper::deserialize(deserializer)?;
@@ -57,6 +57,7 @@ impl<'de> Deserialize<'de> for DataField<Vector3> {
             _data: helper._data,
             _type: helper._type,
             _ply_file: helper._ply_file,
+            _obj_file: helper._obj_file,
         })
     }
 }
@@ -74,6 +75,8 @@ impl<'de> Deserialize<'de> for DataField<usize> {
             _type: String,
             #[serde(rename = "_plyFile", default)]
             _ply_file: String,
+            #[serde(rename = "_objFile", default)]
+            _obj_file: String,
         }
 
         let helper = Helper::deserialize(deserializer)?;
@@ -81,6 +84,7 @@ impl<'de> Deserialize<'de> for DataField<usize> {
             _data: helper._data,
             _type: helper._type,
             _ply_file: helper._ply_file,
+            _obj_file: helper._obj_file,
         })
     }
 }
@@ -90,7 +94,9 @@ impl<'de> Deserialize<'de> for DataField<usize> {
 // or an array of <object>s 
 #[derive(Debug, Deserialize, Clone)]
 #[serde(untagged)]
+#[derive(Default)]
 pub enum SingleOrVec<T> {
+    #[default]
     Empty,
     Single(T),
     Multiple(Vec<T>),
@@ -112,27 +118,41 @@ impl<T: Clone> SingleOrVec<T>  {
             SingleOrVec::Multiple(vec) => vec.iter_mut().collect(),
         }
     }
-}
 
-impl<T: Default> Default for SingleOrVec<T> {
-    fn default() -> Self {
-        SingleOrVec::Empty
+    pub fn len(&self) -> usize {
+        match self {
+            SingleOrVec::Empty => 0,
+            SingleOrVec::Single(_) => 1,
+            SingleOrVec::Multiple(vec) => vec.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        // WARNING: Always leaves self as Multiple (or Empty)
+        let kept: Vec<T> = self.all().into_iter().filter(|t| f(t)).collect();
+        *self = if kept.is_empty() { SingleOrVec::Empty } else { SingleOrVec::Multiple(kept) };
     }
 }
 
 
 
+
 pub type VertexData = DataField<Vector3>; // TODO: use CoordLike in geometry_processing.rs?
 
 // DISCLAIMER: This function is taken from
 // https://serde.rs/string-or-struct.html
 impl FromStr for VertexData {
-    type Err = Void;
+    type Err = String;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(DataField::<Vector3>{
-            _data: parse_string_vecvec3(s).unwrap(),
+            _data: parse_string_vecvec3(s)?,
             _type: String::from("xyz"), // Default for VertexData (Note: it would be different from other DataFields)
             _ply_file: String::from(""),
+            _obj_file: String::from(""),
         })
     }
 }
@@ -178,7 +198,7 @@ impl VertexData{
 
         self._data = new_data;
         self._type = "xyz".to_string();
-        return true;
+        true
     }
 }
 
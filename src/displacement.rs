@@ -0,0 +1,360 @@
+/*
+
+    Displacement of meshes at load time, for silhouettes
+    with real detail (terrain, rocks) rather than shading
+    alone. Triangles are split until no edge is longer
+    than EdgeLength, then every vertex is moved along its
+    normal by Scale times a height, e.g.
+        "Mesh": {
+            "_id": "1", "Material": "1", "_shadingMode": "smooth",
+            "Faces": { "_plyFile": "terrain.ply" },
+            "Displacement": {
+                "EdgeLength": "0.05", "Scale": "0.4",
+                "HeightMap": "heights.png", "Projection": "y"
+            }
+        }
+    Heights either come from
+        - HeightMap: grayscale PNG (relative to the JSON)
+          with heights in [0, 1], projected along the world
+          axis given by Projection ("x", "y" or "z", default
+          "y") over the bounding box of the mesh, or
+        - Noise: fractal gradient noise in [-1, 1] at the
+          vertex position, e.g.
+            "Noise": { "Frequency": "3", "Octaves": "4", "Seed": "7" }
+    Displacement is applied after subdivision (see
+    subdivision.rs), and normals of displaced vertices are
+    recomputed by VertexCache::build( ) like any mesh.
+
+    Splitting only depends on the length of an edge, so
+    triangles sharing an edge split it at the same vertex
+    and displaced meshes stay watertight.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+use smart_default::SmartDefault;
+
+use crate::error::TracerResult;
+use crate::geometry::vertex_normals;
+use crate::image::ImageData;
+use crate::json_parser::*;
+use crate::numeric::{Float, Vector3};
+use crate::subdivision::PolygonMesh;
+
+// Every pass halves edges that are too long, so this is
+// a factor of 2^16 between the longest edge and EdgeLength
+const MAX_PASSES: usize = 16;
+// Limit of a tessellated mesh, an EdgeLength that is tiny compared
+// to the mesh would otherwise use up all memory
+pub const MAX_TRIANGLES: usize = 4_000_000;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Displacement {
+    #[serde(rename = "EdgeLength", deserialize_with = "deser_float")]
+    pub edge_length: Float,
+    #[serde(rename = "Scale", deserialize_with = "deser_float")]
+    pub scale: Float,
+    #[serde(rename = "HeightMap", default)]
+    pub height_map: Option<String>,
+    #[serde(rename = "Projection", default = "default_projection")]
+    pub projection: String,
+    #[serde(rename = "Noise", default)]
+    pub noise: Option<Noise>,
+}
+
+fn default_projection() -> String {
+    String::from("y")
+}
+
+#[derive(Debug, Deserialize, Clone, SmartDefault)]
+#[serde(default)]
+pub struct Noise {
+    #[serde(rename = "Frequency", deserialize_with = "deser_float")]
+    #[default = 1.0]
+    pub frequency: Float,
+    #[serde(rename = "Octaves", deserialize_with = "deser_usize")]
+    #[default = 4]
+    pub octaves: usize,
+    #[serde(rename = "Seed", deserialize_with = "deser_usize")]
+    pub seed: usize,
+}
+
+/// Where heights of displaced vertices come from
+pub enum HeightSource {
+    Image { image: ImageData, axes: [usize; 2] },
+    Noise(Noise),
+}
+
+impl Displacement {
+    /// Problem with the parameters, if any
+    pub fn problem(&self) -> Option<String> {
+        if self.edge_length.is_nan() || self.edge_length <= 0.0 {
+            return Some(format!("EdgeLength must be positive, got {}", self.edge_length));
+        }
+        if self.height_map.is_some() == self.noise.is_some() {
+            return Some(String::from("needs either a HeightMap or a Noise"));
+        }
+        if self.height_map.is_some() && !["x", "y", "z"].contains(&self.projection.as_str()) {
+            return Some(format!("Projection must be x, y or z, got '{}'", self.projection));
+        }
+        match &self.noise {
+            Some(noise) if noise.frequency <= 0.0 || noise.octaves == 0 => Some(String::from("Noise needs a positive Frequency and at least one octave")),
+            _ => None,
+        }
+    }
+
+    /// Load the height map (relative to the directory of the JSON) or set up the noise,
+    /// default noise is used if neither is given (see problem( ))
+    pub fn heights(&self, json_dir: &Path) -> TracerResult<HeightSource> {
+        match (&self.height_map, &self.noise) {
+            (Some(file), _) => {
+                // Image spans the two other axes, u to the right and v upwards
+                let axes = match self.projection.as_str() {
+                    "x" => [2, 1],
+                    "z" => [0, 1],
+                    _ => [0, 2],
+                };
+                Ok(HeightSource::Image { image: ImageData::load_png(&json_dir.join(file))?, axes })
+            }
+            (None, noise) => Ok(HeightSource::Noise(noise.clone().unwrap_or_default())),
+        }
+    }
+
+    /// Tessellated and displaced copy of a triangle mesh
+    pub fn apply(&self, mesh: &PolygonMesh, heights: &HeightSource) -> Result<PolygonMesh, String> {
+        let mut mesh = tessellate(mesh, self.edge_length)?;
+        let normals = vertex_normals(&mesh.positions, mesh.triangles());
+        let (min, max) = mesh.positions.iter().fold((Vector3::INFINITY, Vector3::NEG_INFINITY), |(min, max), &p| (min.min(p), max.max(p)));
+        for (p, n) in mesh.positions.iter_mut().zip(normals) {
+            *p += n * self.scale * heights.height(*p, min, max);
+        }
+        Ok(mesh)
+    }
+}
+
+impl HeightSource {
+    /// Height at a point of a mesh with the given bounding box
+    pub fn height(&self, point: Vector3, min: Vector3, max: Vector3) -> Float {
+        match self {
+            HeightSource::Image { image, axes } => {
+                let extent = (max - min).max(Vector3::splat(Float::EPSILON));
+                let [u, v] = axes.map(|a| ((point[a] - min[a]) / extent[a]).clamp(0.0, 1.0));
+                sample_gray(image, u, v)
+            }
+            HeightSource::Noise(noise) => fractal_noise(point * noise.frequency, noise.octaves, noise.seed as u32),
+        }
+    }
+}
+
+/// Bilinear gray level in [0, 1] where (0, 0) is the bottom left corner
+fn sample_gray(image: &ImageData, u: Float, v: Float) -> Float {
+    let (width, height) = (image.width(), image.height());
+    if width == 0 || height == 0 {
+        return 0.0;
+    }
+    let x = u * (width - 1) as Float;
+    let y = (1.0 - v) * (height - 1) as Float; // Rows start at the top
+    let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+    let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+    let (fx, fy) = (x - x0 as Float, y - y0 as Float);
+    let gray = |x: usize, y: usize| image.pixel_colors()[y * width + x].element_sum() / (3.0 * 255.0);
+    let top = gray(x0, y0) * (1.0 - fx) + gray(x1, y0) * fx;
+    let bottom = gray(x0, y1) * (1.0 - fx) + gray(x1, y1) * fx;
+    top * (1.0 - fy) + bottom * fy
+}
+
+/// Split triangles until no edge is longer than edge_length. An edge is split in
+/// the middle if it is too long, and a triangle is divided into 2, 3 or 4 depending
+/// on how many of its edges are split. Fails if that needs more than MAX_TRIANGLES.
+pub fn tessellate(mesh: &PolygonMesh, edge_length: Float) -> Result<PolygonMesh, String> {
+    let mut positions = mesh.positions.clone();
+    let mut triangles = mesh.triangles();
+    // No triangle with edges up to edge_length is larger than the equilateral one
+    let area: Float = triangles.iter().map(|t| 0.5 * (positions[t[1]] - positions[t[0]]).cross(positions[t[2]] - positions[t[0]]).length()).sum();
+    let too_many = || format!("EdgeLength {} needs more than {} triangles", edge_length, MAX_TRIANGLES);
+    if area / (Float::sqrt(3.0) / 4.0 * edge_length * edge_length) > MAX_TRIANGLES as Float {
+        return Err(too_many());
+    }
+    for _ in 0..MAX_PASSES {
+        let mut midpoints: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut midpoint = |a: usize, b: usize, positions: &mut Vec<Vector3>| -> Option<usize> {
+            if positions[a].distance(positions[b]) <= edge_length {
+                return None;
+            }
+            Some(*midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                positions.push((positions[a] + positions[b]) * 0.5);
+                positions.len() - 1
+            }))
+        };
+
+        let mut split = Vec::with_capacity(triangles.len());
+        for tri in triangles.iter() {
+            let mids = [0, 1, 2].map(|i| midpoint(tri[i], tri[(i + 1) % 3], &mut positions));
+            match mids.iter().filter(|m| m.is_some()).count() {
+                0 => split.push(*tri),
+                3 => {
+                    let [ab, bc, ca] = mids.map(Option::unwrap);
+                    split.extend([[tri[0], ab, ca], [ab, tri[1], bc], [ca, bc, tri[2]], [ab, bc, ca]]);
+                }
+                count => {
+                    // Rotate so that ab is split and, if there are two, bc is split as well
+                    let k = (0..3).find(|&k| mids[k].is_some() && (count == 1 || mids[(k + 1) % 3].is_some())).unwrap();
+                    let [a, b, c] = [tri[k], tri[(k + 1) % 3], tri[(k + 2) % 3]];
+                    let ab = mids[k].unwrap();
+                    match mids[(k + 1) % 3] {
+                        None => split.extend([[a, ab, c], [ab, b, c]]),
+                        Some(bc) => {
+                            split.push([ab, b, bc]);
+                            // Remaining quad a, ab, bc, c is cut along its shorter diagonal
+                            if positions[a].distance(positions[bc]) < positions[ab].distance(positions[c]) {
+                                split.extend([[a, ab, bc], [a, bc, c]]);
+                            } else {
+                                split.extend([[a, ab, c], [ab, bc, c]]);
+                            }
+                        }
+                    }
+                }
+            }
+            if split.len() > MAX_TRIANGLES {
+                return Err(too_many());
+            }
+        }
+        let done = split.len() == triangles.len();
+        triangles = split;
+        if done {
+            break;
+        }
+    }
+    Ok(PolygonMesh { positions, faces: triangles.iter().map(|t| t.to_vec()).collect() })
+}
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed.wrapping_mul(0x9E37_79B9)
+        ^ (x as u32).wrapping_mul(0x85EB_CA6B)
+        ^ (y as u32).wrapping_mul(0xC2B2_AE35)
+        ^ (z as u32).wrapping_mul(0x27D4_EB2F);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297A_2D39);
+    h ^ (h >> 15)
+}
+
+/// Gradient (Perlin) noise in about [-1, 1], gradients are the 12 edges of a cube
+pub fn gradient_noise(p: Vector3, seed: u32) -> Float {
+    const GRADIENTS: [[Float; 3]; 12] = [
+        [1.0, 1.0, 0.0], [-1.0, 1.0, 0.0], [1.0, -1.0, 0.0], [-1.0, -1.0, 0.0],
+        [1.0, 0.0, 1.0], [-1.0, 0.0, 1.0], [1.0, 0.0, -1.0], [-1.0, 0.0, -1.0],
+        [0.0, 1.0, 1.0], [0.0, -1.0, 1.0], [0.0, 1.0, -1.0], [0.0, -1.0, -1.0],
+    ];
+    let cell = p.floor();
+    let f = p - cell;
+    let fade = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let mut value = 0.0;
+    for corner in 0..8 {
+        let offset = Vector3::new((corner & 1) as Float, ((corner >> 1) & 1) as Float, ((corner >> 2) & 1) as Float);
+        let lattice = cell + offset;
+        let g = GRADIENTS[(hash(lattice.x as i32, lattice.y as i32, lattice.z as i32, seed) % 12) as usize];
+        let d = f - offset;
+        let weight = Vector3::select(offset.cmpgt(Vector3::splat(0.5)), fade, Vector3::ONE - fade);
+        value += weight.x * weight.y * weight.z * (g[0] * d.x + g[1] * d.y + g[2] * d.z);
+    }
+    value
+}
+
+/// Sum of octaves of gradient noise, each at twice the frequency and half the amplitude
+pub fn fractal_noise(p: Vector3, octaves: usize, seed: u32) -> Float {
+    let (mut sum, mut total, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+    for octave in 0..octaves {
+        sum += amplitude * gradient_noise(p * frequency, seed.wrapping_add(octave as u32));
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    if total > 0.0 { sum / total } else { 0.0 }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> PolygonMesh {
+        let positions = vec![Vector3::ZERO, Vector3::X, Vector3::new(1.0, 0.0, 1.0), Vector3::Z];
+        PolygonMesh { positions, faces: vec![vec![0, 3, 2, 1]] } // Facing +y
+    }
+
+    #[test]
+    fn test_tessellate() {
+        let mesh = tessellate(&square(), 0.3).unwrap();
+        let triangles = mesh.triangles();
+        let longest = triangles.iter()
+            .flat_map(|t| [0, 1, 2].map(|i| mesh.positions[t[i]].distance(mesh.positions[t[(i + 1) % 3]])))
+            .fold(0.0, Float::max);
+        assert!(longest <= 0.3, "{}", longest);
+        // Area is preserved and every edge inside is shared by two triangles (no T-junctions)
+        let area: Float = triangles.iter().map(|t| 0.5 * (mesh.positions[t[1]] - mesh.positions[t[0]]).cross(mesh.positions[t[2]] - mesh.positions[t[0]]).length()).sum();
+        assert!((area - 1.0).abs() < 1e-9);
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for t in triangles.iter() {
+            for i in 0..3 {
+                let (a, b) = (t[i], t[(i + 1) % 3]);
+                *edges.entry((a.min(b), a.max(b))).or_default() += 1;
+            }
+        }
+        let boundary = edges.iter().filter(|&(&(a, b), &count)| {
+            let (pa, pb) = (mesh.positions[a], mesh.positions[b]);
+            let on_border = |i: usize| (pa[i] == 0.0 && pb[i] == 0.0) || (pa[i] == 1.0 && pb[i] == 1.0);
+            assert!(count == 2 || on_border(0) || on_border(2), "edge {:?} used {} time(s)", (pa, pb), count);
+            count == 1
+        }).count();
+        assert!(boundary >= 16);
+
+        // Square of 2 triangles would need about 2 * 4^16 triangles
+        assert!(tessellate(&square(), 1e-5).is_err());
+    }
+
+    #[test]
+    fn test_height_map_displacement() {
+        // Gray levels of a ramp rising to the right and upwards, (u + v) / 2, are interpolated exactly,
+        // so the square facing +y (projected along y, u along x and v along z) becomes a sloped plane
+        let (width, height) = (5, 4);
+        let colors = (0..width * height).map(|i| {
+            let (u, v) = ((i % width) as Float / (width - 1) as Float, 1.0 - (i / width) as Float / (height - 1) as Float);
+            Vector3::splat(255.0 * (u + v) / 2.0)
+        }).collect();
+        let heights = HeightSource::Image { image: ImageData::new(width, height, String::from("ramp.png"), colors), axes: [0, 2] };
+        let displacement = Displacement { edge_length: 0.2, scale: 0.5, height_map: Some(String::from("ramp.png")), projection: default_projection(), noise: None };
+        let displaced = displacement.apply(&square(), &heights).unwrap();
+        let flat = tessellate(&square(), 0.2).unwrap();
+        assert_eq!(displaced.positions.len(), flat.positions.len());
+        for (p, q) in displaced.positions.iter().zip(&flat.positions) {
+            assert!(p.abs_diff_eq(Vector3::new(q.x, 0.5 * (q.x + q.z) / 2.0, q.z), 1e-5), "{} from {}", p, q);
+        }
+    }
+
+    #[test]
+    fn test_noise_displacement() {
+        for i in 0..100 {
+            let p = Vector3::new(i as Float * 0.37, i as Float * 0.11, -(i as Float) * 0.23);
+            let n = fractal_noise(p, 4, 3);
+            assert!(n.abs() <= 1.0, "{}", n);
+            assert_eq!(n, fractal_noise(p, 4, 3));
+        }
+        // Zero on the lattice
+        assert_eq!(gradient_noise(Vector3::new(2.0, -1.0, 5.0), 0), 0.0);
+
+        let displacement = Displacement { edge_length: 0.25, scale: 0.1, height_map: None, projection: default_projection(), noise: Some(Noise::default()) };
+        assert!(displacement.problem().is_none());
+        let heights = HeightSource::Noise(Noise { frequency: 4.0, ..Noise::default() });
+        let displaced = displacement.apply(&square(), &heights).unwrap();
+        // Flat square moves along +y only, by at most Scale
+        assert!(displaced.positions.iter().all(|p| p.y.abs() <= 0.1 && (0.0..=1.0).contains(&p.x) && (0.0..=1.0).contains(&p.z)));
+        assert!(displaced.positions.iter().any(|p| p.y.abs() > 1e-3));
+    }
+}
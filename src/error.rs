@@ -0,0 +1,99 @@
+/*
+
+    Declare the error type shared by scene loading,
+    validation and rendering. Every failure is reported
+    through TracerError so that a bad scene fails with
+    a clear message instead of rendering garbage.
+
+    Errors carry as much context as is available at the
+    point of failure, e.g. file path for I/O errors, JSON
+    path (Scene.Materials.Material[2]) and _id for objects.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fmt;
+use std::path::PathBuf;
+
+use crate::validation::Diagnostic;
+
+#[derive(Debug)]
+pub enum TracerError {
+    /// File could not be opened, created or read
+    Io { path: PathBuf, source: std::io::Error },
+    /// Scene file is not valid CENG 795 JSON
+    Parse { path: PathBuf, source: serde_json::Error },
+    /// A single JSON object could not be deserialized e.g. a material
+    Json { json_path: String, id: Option<usize>, source: serde_json::Error },
+    /// PLY file referred by a mesh could not be loaded
+    Ply { path: PathBuf, source: serde_ply::DeserializeError },
+    /// PNG encoding failed while saving an image
+    Png { path: PathBuf, source: png::EncodingError },
+    /// PNG decoding failed while loading an image
+    PngDecode { path: PathBuf, source: png::DecodingError },
+    /// Voxel grid file referred by a volume is malformed
+    Grid { path: PathBuf, message: String },
+    /// OBJ file referred by a mesh is malformed
+    Obj { path: PathBuf, message: String },
+    /// Image has an unsupported format or images can not be compared
+    Image(String),
+    /// Scene was loaded but its content is inconsistent
+    Validation { object: String, id: usize, message: String },
+    /// Scene validation found problems in strict mode
+    InvalidScene(Vec<Diagnostic>),
+    /// Something went wrong during rendering
+    Render(String),
+}
+
+pub type TracerResult<T> = Result<T, TracerError>;
+
+impl TracerError {
+    pub fn io(path: impl Into<PathBuf>, source: std::io::Error) -> Self {
+        TracerError::Io { path: path.into(), source }
+    }
+
+    pub fn validation(object: &str, id: usize, message: impl Into<String>) -> Self {
+        TracerError::Validation { object: object.to_string(), id, message: message.into() }
+    }
+}
+
+impl fmt::Display for TracerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TracerError::Io { path, source } => write!(f, "I/O error on {}: {}", path.display(), source),
+            TracerError::Parse { path, source } => write!(f, "Failed to parse {}: {}", path.display(), source),
+            TracerError::Json { json_path, id: Some(id), source } => write!(f, "Invalid {} (_id {}): {}", json_path, id, source),
+            TracerError::Json { json_path, id: None, source } => write!(f, "Invalid {}: {}", json_path, source),
+            TracerError::Ply { path, source } => write!(f, "Failed to load PLY {}: {}", path.display(), source),
+            TracerError::Png { path, source } => write!(f, "Failed to write PNG {}: {}", path.display(), source),
+            TracerError::PngDecode { path, source } => write!(f, "Failed to read PNG {}: {}", path.display(), source),
+            TracerError::Grid { path, message } => write!(f, "Failed to load voxel grid {}: {}", path.display(), message),
+            TracerError::Obj { path, message } => write!(f, "Failed to load OBJ {}: {}", path.display(), message),
+            TracerError::Image(message) => write!(f, "Image error: {}", message),
+            TracerError::Validation { object, id, message } => write!(f, "{} (_id {}): {}", object, id, message),
+            TracerError::InvalidScene(diagnostics) => {
+                write!(f, "Scene has {} problem(s):", diagnostics.len())?;
+                for d in diagnostics.iter() {
+                    write!(f, "\n  - {}", d)?;
+                }
+                Ok(())
+            }
+            TracerError::Render(message) => write!(f, "Render error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TracerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TracerError::Io { source, .. } => Some(source),
+            TracerError::Parse { source, .. } => Some(source),
+            TracerError::Json { source, .. } => Some(source),
+            TracerError::Ply { source, .. } => Some(source),
+            TracerError::Png { source, .. } => Some(source),
+            TracerError::PngDecode { source, .. } => Some(source),
+            TracerError::Validation { .. } | TracerError::InvalidScene(_) | TracerError::Render(_) | TracerError::Image(_) | TracerError::Grid { .. } | TracerError::Obj { .. } => None,
+        }
+    }
+}
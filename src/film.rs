@@ -0,0 +1,119 @@
+/*
+
+    Floating-point accumulation buffer of a camera. Every
+    sample pass adds one radiance sample per pixel, final
+    color of a pixel is the mean of its samples.
+
+    Second moment of the luminance is tracked as well, to
+    estimate how noisy a pixel still is (see relative_error)
+    which drives adaptive sampling in renderer.rs.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use crate::image::ImageData;
+use crate::numeric::{Float, Vector3};
+
+#[derive(Debug, Clone)]
+pub struct Film {
+    pub width: usize,
+    pub height: usize,
+    pub radiance_sum: Vec<Vector3>, // Sum of all samples per pixel
+    pub luminance_sq_sum: Vec<Float>, // Sum of squared luminance of the moment_count samples per pixel
+    pub sample_count: Vec<u32>,
+    pub moment_count: Vec<u32>, // Fewer than sample_count after resuming a checkpoint without second moments
+}
+
+/// Relative luminance of a linear RGB color (Rec. 709 weights)
+pub fn luminance(color: Vector3) -> Float {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            radiance_sum: vec![Vector3::ZERO; width * height],
+            luminance_sq_sum: vec![0.0; width * height],
+            sample_count: vec![0; width * height],
+            moment_count: vec![0; width * height],
+        }
+    }
+
+    #[inline]
+    pub fn index(&self, col: usize, row: usize) -> usize {
+        row * self.width + col
+    }
+
+    pub fn add_sample(&mut self, index: usize, radiance: Vector3) {
+        self.radiance_sum[index] += radiance;
+        self.luminance_sq_sum[index] += luminance(radiance).powi(2);
+        self.sample_count[index] += 1;
+        self.moment_count[index] += 1;
+    }
+
+    pub fn mean(&self, index: usize) -> Vector3 {
+        match self.sample_count[index] {
+            0 => Vector3::ZERO,
+            n => self.radiance_sum[index] / n as Float,
+        }
+    }
+
+    /// Standard error of the mean luminance relative to the mean luminance, infinite below 2 samples
+    /// with a second moment. Variance is estimated from those samples only, mean from all of them.
+    /// Luminance is floored by min_luminance so that dark pixels do not need infinitely many samples.
+    pub fn relative_error(&self, index: usize, min_luminance: Float) -> Float {
+        let (n, m) = (self.sample_count[index] as Float, self.moment_count[index] as Float);
+        if m < 2.0 {
+            return Float::INFINITY;
+        }
+        let mean = luminance(self.radiance_sum[index]) / n;
+        let variance = ((self.luminance_sq_sum[index] / m - mean * mean) * m / (m - 1.0)).max(0.0);
+        (variance / n).sqrt() / mean.abs().max(min_luminance)
+    }
+
+    pub fn to_colors(&self) -> Vec<Vector3> {
+        (0..self.radiance_sum.len()).map(|i| self.mean(i)).collect()
+    }
+
+    pub fn to_image(&self, name: &str) -> ImageData {
+        ImageData::new(self.width, self.height, name.to_string(), self.to_colors())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relative_error() {
+        // Luminance of a gray color is its value since the weights sum up to 1
+        let mut film = Film::new(2, 1);
+        film.add_sample(0, Vector3::splat(1.0));
+        assert_eq!(film.relative_error(0, 1.0), Float::INFINITY);
+        film.add_sample(0, Vector3::splat(3.0));
+        // Sample variance 2, standard error sqrt(2 / 2) = 1, relative to the mean 2
+        assert!((film.relative_error(0, 1.0) - 0.5).abs() < 1e-6);
+        // Dark pixels are relative to min_luminance instead
+        assert!((film.relative_error(0, 4.0) - 0.25).abs() < 1e-6);
+
+        film.add_sample(1, Vector3::splat(2.0));
+        film.add_sample(1, Vector3::splat(2.0));
+        assert!(film.relative_error(1, 1.0) < 1e-6);
+    }
+
+    #[test]
+    fn test_relative_error_without_second_moments() {
+        // Pixel of a version 1 checkpoint: 8 samples of 2 but no second moment
+        let mut film = Film::new(1, 1);
+        film.radiance_sum[0] = Vector3::splat(16.0);
+        film.sample_count[0] = 8;
+        assert_eq!(film.relative_error(0, 1.0), Float::INFINITY);
+        film.add_sample(0, Vector3::splat(2.0));
+        assert_eq!(film.relative_error(0, 1.0), Float::INFINITY);
+        film.add_sample(0, Vector3::splat(2.0));
+        assert!(film.relative_error(0, 1.0) < 1e-6);
+    }
+}
@@ -12,9 +12,8 @@
     @author: bartu
 */
 
-use std::{ops::Index};
 use crate::shapes::{Triangle};
-use crate::numeric::{Float, Vector3, approx_zero};
+use crate::numeric::{Float, Matrix3, Vector3, approx_zero};
 
 pub fn get_tri_normal(v1: &Vector3, v2: &Vector3, v3: &Vector3) -> Vector3{
     // WARNING: Assumes triangle indices are given in counter clockwise order 
@@ -32,12 +31,150 @@ pub fn get_tri_normal(v1: &Vector3, v2: &Vector3, v3: &Vector3) -> Vector3{
     normal
 }
 
+pub fn triangle_area(v1: &Vector3, v2: &Vector3, v3: &Vector3) -> Float {
+    0.5 * (v2 - v1).cross(v3 - v1).length()
+}
+
+/// Per-vertex normals averaging area-weighted normals of adjacent triangles,
+/// triangles referring to vertices that do not exist are skipped
+pub fn vertex_normals(positions: &[Vector3], triangles: impl IntoIterator<Item = [usize; 3]>) -> Vec<Vector3> {
+    let mut normals = vec![Vector3::ZERO; positions.len()];
+    for indices in triangles {
+        if indices.iter().any(|&i| i >= positions.len()) {
+            continue;
+        }
+        let [v1, v2, v3] = indices.map(|i| positions[i]);
+        let face_n = (v2 - v1).cross(v3 - v1); // Be careful, not normalized yet!
+        for idx in indices {
+            normals[idx] += face_n;
+        }
+    }
+    for n in normals.iter_mut() {
+        if n.length_squared() > 0.0 {
+            *n = n.normalize();
+        }
+    }
+    normals
+}
+
+/// Orthonormal frame of an analytic shape (see shapes.rs), e.g. a cylinder
+/// is intersected in its local coordinates where its axis is +z. Distances
+/// are preserved, so t of a ray is the same in both frames.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    pub origin: Vector3,
+    pub axes: Matrix3, // Columns are local x, y, z in world coordinates
+}
+
+impl Frame {
+    pub fn new(origin: Vector3, axes: Matrix3) -> Self {
+        Self { origin, axes }
+    }
+
+    /// Frame whose z is along the given axis, x and y are arbitrary
+    pub fn from_axis(origin: Vector3, axis: Vector3) -> Self {
+        let z = axis.normalize();
+        let (x, y) = z.any_orthonormal_pair();
+        Self::new(origin, Matrix3::from_cols(x, y, z))
+    }
+
+    pub fn point_to_local(&self, point: Vector3) -> Vector3 {
+        self.axes.transpose() * (point - self.origin)
+    }
+
+    pub fn dir_to_local(&self, direction: Vector3) -> Vector3 {
+        self.axes.transpose() * direction
+    }
+
+    pub fn dir_to_world(&self, direction: Vector3) -> Vector3 {
+        self.axes * direction
+    }
+}
+
+/// Range of t where a ray overlaps an axis-aligned box (slab method), None if it misses
+pub fn ray_box_overlap(origin: Vector3, direction: Vector3, min: Vector3, max: Vector3) -> Option<(Float, Float)> {
+    let t0 = (min - origin) / direction;
+    let t1 = (max - origin) / direction;
+    let t_enter = t0.min(t1).max_element();
+    let t_exit = t0.max(t1).min_element();
+    (t_enter <= t_exit).then_some((t_enter, t_exit))
+}
+
+/// Real roots of a t^2 + b t + c in ascending order, the one of b t + c if a is zero
+pub fn solve_quadratic(a: Float, b: Float, c: Float) -> Vec<Float> {
+    if a == 0.0 {
+        return if b == 0.0 { Vec::new() } else { vec![-c / b] };
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    // Avoid cancellation of -b + sqrt(D) when b^2 >> ac (Numerical Recipes 5.6)
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    vec![t0.min(t1), t0.max(t1)]
+}
+
+fn evaluate_polynomial(coefficients: &[Float], x: Float) -> Float {
+    coefficients.iter().fold(0.0, |acc, c| acc * x + c) // Horner's method
+}
+
+/// Real roots of a polynomial in [lo, hi] in ascending order, coefficients are given
+/// from the highest degree, e.g. [1, 0, -1] for x^2 - 1. Roots of the derivative split
+/// the range into monotonic pieces, each having at most one root to bisect. Roots of
+/// even multiplicity (e.g. grazing a torus) are not found.
+pub fn polynomial_roots(coefficients: &[Float], lo: Float, hi: Float) -> Vec<Float> {
+    let degree = coefficients.len().saturating_sub(1);
+    if degree == 0 {
+        return Vec::new();
+    }
+    if degree == 1 {
+        let root = -coefficients[1] / coefficients[0];
+        return if (lo..=hi).contains(&root) { vec![root] } else { Vec::new() };
+    }
+
+    let derivative: Vec<Float> = coefficients[..degree].iter().enumerate()
+        .map(|(i, c)| c * (degree - i) as Float)
+        .collect();
+    let mut bounds = vec![lo];
+    bounds.extend(polynomial_roots(&derivative, lo, hi));
+    bounds.push(hi);
+
+    let mut roots = Vec::new();
+    for pair in bounds.windows(2) {
+        let (mut a, mut b) = (pair[0], pair[1]);
+        let f_a = evaluate_polynomial(coefficients, a);
+        if f_a == 0.0 {
+            roots.push(a);
+            continue;
+        }
+        if f_a * evaluate_polynomial(coefficients, b) >= 0.0 {
+            continue;
+        }
+        for _ in 0..100 {
+            let mid = 0.5 * (a + b);
+            if mid <= a || mid >= b {
+                break; // Interval cannot be split further in this precision
+            }
+            if f_a * evaluate_polynomial(coefficients, mid) > 0.0 { a = mid } else { b = mid }
+        }
+        roots.push(0.5 * (a + b));
+    }
+    if evaluate_polynomial(coefficients, hi) == 0.0 && roots.last() != Some(&hi) {
+        roots.push(hi);
+    }
+    roots
+}
+
 
-trait StructofArrays {
+pub trait StructofArrays {
     type Item;
 
     fn vectorize(&self) -> Vec<Self::Item>; // Convert to AoS
     fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 }
 
 pub struct CoordLike {
@@ -50,7 +187,7 @@ pub struct CoordLike {
 }
 
 impl CoordLike {
-    pub fn new_from(coords: &Vec<Vector3>) -> Self {
+    pub fn new_from(coords: &[Vector3]) -> Self {
         let xs = (0..coords.len()).map(|i| coords[i][0]).collect();
         let ys = (0..coords.len()).map(|i| coords[i][1]).collect();
         let zs = (0..coords.len()).map(|i| coords[i][2]).collect();
@@ -96,7 +233,7 @@ impl StructofArrays for CoordLike {
 
 impl CoordLike {
    
-    fn tri_normals(triangles: &Vec<Triangle>, vertices: &Vec<Vector3>) -> CoordLike {
+    pub fn tri_normals(triangles: &[Triangle], vertices: &[Vector3]) -> CoordLike {
         
         let len = triangles.len();
         let mut xs: Vec<Float> = vec![0.; len];
@@ -122,6 +259,32 @@ impl CoordLike {
 mod tests {
     use super::*; // access to the outer scope
 
+    #[test]
+    fn test_polynomial_roots() {
+        // (x + 2)(x - 0.5)(x - 1)(x - 3), expanded
+        let quartic = [1.0, -2.5, -4.0, 8.5, -3.0];
+        let roots = polynomial_roots(&quartic, -10.0, 10.0);
+        assert_eq!(roots.len(), 4, "{:?}", roots);
+        for (root, expected) in roots.iter().zip([-2.0, 0.5, 1.0, 3.0]) {
+            assert!((root - expected).abs() < 1e-5, "{:?}", roots);
+        }
+        assert_eq!(polynomial_roots(&quartic, 0.75, 2.0).len(), 1);
+        assert!(polynomial_roots(&[1.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+
+        assert_eq!(solve_quadratic(1.0, -3.0, 2.0), vec![1.0, 2.0]);
+        assert_eq!(solve_quadratic(0.0, 2.0, -1.0), vec![0.5]);
+        assert!(solve_quadratic(1.0, 0.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn test_frame_roundtrip() {
+        let frame = Frame::from_axis(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.0, 3.0, 4.0));
+        let p = Vector3::new(-1.0, 0.5, 2.0);
+        let local = frame.point_to_local(p);
+        assert!((frame.dir_to_world(local) + frame.origin).abs_diff_eq(p, 1e-5));
+        assert!(frame.dir_to_local(Vector3::new(0.0, 0.6, 0.8)).abs_diff_eq(Vector3::Z, 1e-5));
+    }
+
     //#[test]
     //fn test_normals() {
     //    // WARNING: A simple test is provided, does not
@@ -1,9 +1,10 @@
 
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::io::BufWriter;
-use tracing::{warn, info, debug};
+use std::io::{BufReader, BufWriter};
+use tracing::{warn, info};
 
+use crate::error::{TracerError, TracerResult};
 use crate::numeric::{Vector3, Float};
 
 
@@ -45,24 +46,122 @@ impl ImageData {
         Self::new(width, height, name, pixel_colors)
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pixel_colors(&self) -> &[Vector3] {
+        &self.pixel_colors
+    }
+
+    pub fn load(path: &Path) -> TracerResult<Self> {
+        // Pick the loader from file extension
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase()).as_deref() {
+            Some("png") => Self::load_png(path),
+            Some("pfm") => Self::load_pfm(path),
+            Some("exr") => Err(TracerError::Image(format!("{}: EXR is not supported yet, convert it to PFM", path.display()))),
+            _ => Err(TracerError::Image(format!("{}: unknown image format, expected .png or .pfm", path.display()))),
+        }
+    }
+
+    pub fn load_pfm(path: &Path) -> TracerResult<Self> {
+        // Portable float map, see https://www.pauldebevec.com/Research/HDR/PFM/
+        // Header is "PF" (RGB) or "Pf" (grayscale), "<width> <height>" and scale 
+        // whose sign tells endianness (negative = little endian), separated by whitespace.
+        // Rows are stored bottom to top. Values are scaled from [0, 1] to [0, 255]
+        // to match rendered colors.
+        let bytes = std::fs::read(path).map_err(|e| TracerError::io(path, e))?;
+        let invalid = |message: &str| TracerError::Image(format!("{}: invalid PFM, {}", path.display(), message));
+
+        let mut position = 0;
+        let mut tokens = Vec::with_capacity(4);
+        while tokens.len() < 4 {
+            while bytes.get(position).is_some_and(|b| b.is_ascii_whitespace()) {
+                position += 1;
+            }
+            let start = position;
+            while bytes.get(position).is_some_and(|b| !b.is_ascii_whitespace()) {
+                position += 1;
+            }
+            if start == position {
+                return Err(invalid("truncated header"));
+            }
+            tokens.push(String::from_utf8_lossy(&bytes[start..position]).to_string());
+        }
+        position += 1; // Single whitespace after scale
+
+        let channels = match tokens[0].as_str() {
+            "PF" => 3,
+            "Pf" => 1,
+            _ => return Err(invalid("expected PF or Pf")),
+        };
+        let width: usize = tokens[1].parse().map_err(|_| invalid("bad width"))?;
+        let height: usize = tokens[2].parse().map_err(|_| invalid("bad height"))?;
+        let scale: f32 = tokens[3].parse().map_err(|_| invalid("bad scale"))?;
+        let data = bytes.get(position..position + width * height * channels * 4).ok_or_else(|| invalid("truncated pixel data"))?;
+
+        let values: Vec<Float> = data.chunks_exact(4).map(|b| {
+            let b: [u8; 4] = b.try_into().unwrap();
+            let v = if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) };
+            v as Float * 255.0
+        }).collect();
+        let mut pixel_colors = Vec::with_capacity(width * height);
+        for row in (0..height).rev() {
+            for p in values[row * width * channels..(row + 1) * width * channels].chunks_exact(channels) {
+                pixel_colors.push(if channels == 1 { Vector3::splat(p[0]) } else { Vector3::new(p[0], p[1], p[2]) });
+            }
+        }
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(Self::new(width, height, name, pixel_colors))
+    }
+
+    pub fn load_png(path: &Path) -> TracerResult<Self> {
+        // Colors are loaded in [0, 255] like the rendered colors,
+        // grayscale is expanded to RGB and alpha is ignored.
+        let file = File::open(path).map_err(|e| TracerError::io(path, e))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let png_error = |source| TracerError::PngDecode { path: path.to_path_buf(), source };
+        let mut reader = decoder.read_info().map_err(png_error)?;
+        let mut buffer = vec![0; reader.output_buffer_size().ok_or_else(|| TracerError::Image(format!("{} is too large", path.display())))?];
+        let info = reader.next_frame(&mut buffer).map_err(png_error)?;
+
+        let channels = info.color_type.samples();
+        let pixel_colors = buffer[..info.buffer_size()]
+            .chunks_exact(channels)
+            .map(|p| match channels {
+                1 | 2 => Vector3::splat(p[0] as Float),
+                _ => Vector3::new(p[0] as Float, p[1] as Float, p[2] as Float),
+            })
+            .collect();
+        let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(Self::new(info.width as usize, info.height as usize, name, pixel_colors))
+    }
+
     pub fn flatten_color(self) -> Vec<Float> {
         // Return [R1, G1, B1, R2, G2, B2, ...] vector
         // where each triplet is RGB color of a pixel.
         self.pixel_colors.into_iter().flat_map(|v| [v.x, v.y, v.z]).collect()
     }
     pub fn to_rgb(self) -> Vec<u8> {
-        let rgb_vec = self.flatten_color().into_iter().map(|x| {
-            if x < 0.0 || x > 255.0 {
+        self.flatten_color().into_iter().map(|x| {
+            if !(0.0..=255.0).contains(&x) {
                 // debug!("Clamping applied to x={} value for RGB conversion.", x); // sorry it prints too much 
             }
             x.clamp(0.0, 255.0) as u8
-        }).collect();
-
-        rgb_vec
+        }).collect()
     } 
 
-    pub fn check_extension(&self, path: &PathBuf, extension: &str) -> bool {
-        path.extension().unwrap().to_str().unwrap() == extension
+    pub fn check_extension(&self, path: &Path, extension: &str) -> bool {
+        path.extension().and_then(|e| e.to_str()) == Some(extension)
     }
 
     pub fn get_png_fullpath(&self, path: &str) -> PathBuf {
@@ -86,7 +185,7 @@ impl ImageData {
         }
     }
 
-    pub fn save_png(self, path: &str) -> Result<(), Box<dyn std::error::Error>>{
+    pub fn save_png(self, path: &str) -> TracerResult<()> {
         // Path is either a folder name or
         // full path including <imagename>.png
         // If full path is not provided it will use 
@@ -99,18 +198,19 @@ impl ImageData {
         // DISCLAIMER: This function is based on https://docs.rs/png/0.18.0/png/
         let path: PathBuf = self.get_png_fullpath(path);
 
-        let file = File::create(path.clone()).unwrap();
-        let ref mut w = BufWriter::new(file);
+        let file = File::create(&path).map_err(|e| TracerError::io(&path, e))?;
+        let w = &mut BufWriter::new(file);
         let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32); // Width is 2 pixels and height is 1.
     
         encoder.set_color(png::ColorType::Rgb);
         encoder.set_depth(png::BitDepth::Eight);
         // TODO / WARNING: You may need to set gamma as in this link https://docs.rs/png/0.18.0/png/
-        let mut writer = encoder.write_header().unwrap();
+        let png_error = |source| TracerError::Png { path: path.clone(), source };
+        let mut writer = encoder.write_header().map_err(png_error)?;
 
         let data = self.to_rgb();
-        writer.write_image_data(&data)?; // Save
-        info!("Image saved to {}", path.to_str().unwrap());
+        writer.write_image_data(&data).map_err(png_error)?; // Save
+        info!("Image saved to {}", path.display());
         Ok(())
     }
 }
@@ -28,16 +28,16 @@ use std::str::FromStr;
 use std::fs::File;
 use std::io::BufReader;
 
-use void::Void;
 use serde::{Deserialize, Deserializer};
 use serde::de::{self, Visitor, SeqAccess, MapAccess};
 use tracing::{debug, warn};
 
+use crate::error::{TracerError, TracerResult};
 use crate::scene::{RootScene};
 use crate::camera::{NearPlane};
-use crate::numeric::{Int, Float, Vector3};
+use crate::numeric::{Int, Float, Matrix3, Vector3, approx_zero};
 
-pub fn parse_json795(path: &str) -> Result<RootScene, Box<dyn std::error::Error>> {
+pub fn parse_json795(path: &str) -> TracerResult<RootScene> {
     /*
         Parse JSON files in CENG 795 format.
     */
@@ -46,15 +46,15 @@ pub fn parse_json795(path: &str) -> Result<RootScene, Box<dyn std::error::Error>
     let _enter = span.enter();
 
     // Open file
-    let file = File::open(path)?;
+    let file = File::open(path).map_err(|e| TracerError::io(path, e))?;
     let reader = BufReader::new(file);
     debug!("Reading file from {}", path);
     
     // Parse JSON into Scene
-    let root: RootScene = serde_json::from_reader(reader)?;
+    let root: RootScene = serde_json::from_reader(reader)
+        .map_err(|source| TracerError::Parse { path: path.into(), source })?;
     Ok(root) 
 
-
 }
 
 
@@ -116,6 +116,37 @@ where
     }
 }
 
+pub fn deser_opt_float<'de, D>(deserializer: D) -> Result<Option<Float>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deser_float(deserializer).map(Some)
+}
+
+pub fn deser_opt_usize<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deser_usize(deserializer).map(Some)
+}
+
+// Handles booleans as "true"/"false" strings or JSON booleans
+pub fn deser_bool<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s: serde_json::Value = Deserialize::deserialize(deserializer)?;
+    match s {
+        serde_json::Value::Bool(b) => Ok(b),
+        serde_json::Value::String(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            other => Err(de::Error::custom(format!("Failed to parse boolean from '{other}'"))),
+        },
+        t => Err(de::Error::custom(format!("Expected boolean or string, found {t}"))),
+    }
+}
+
 pub trait From3<T>: Sized {
     fn new(x: T, y: T, z: T) -> Self;
 }
@@ -265,6 +296,15 @@ where
 }
 
 
+// Wrapper for deser_numeric_vec<Float>
+pub fn deser_float_vec<'de, D>(deserializer: D) -> Result<Vec<Float>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    deser_numeric_vec::<D, Float>(deserializer)
+}
+
+
 // Wrapper for deser_numeric_vec<Int>
 pub fn deser_int_vec<'de, D>(deserializer: D) -> Result<Vec<Int>, D::Error>
 where
@@ -308,6 +348,22 @@ where
     })
 }
 
+pub fn deser_rotation<'de, D>(deserializer: D) -> Result<Matrix3, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    // Rotation given as "<angle> <x> <y> <z>", angle in degrees around axis (x, y, z)
+    let v = deser_float_vec(deserializer)?;
+    let [angle, x, y, z] = v[..] else {
+        return Err(de::Error::custom(format!("expected 4 values for rotation (angle x y z), got {}", v.len())));
+    };
+    let axis = Vector3::new(x, y, z);
+    if approx_zero(axis.length()) {
+        return Err(de::Error::custom("rotation axis has zero length"));
+    }
+    Ok(Matrix3::from_axis_angle(axis.normalize(), angle.to_radians()))
+}
+
 pub fn deser_vecvec3<'de, D>(deserializer: D) -> Result<Vec<Vector3>, D::Error>
 where
     D: Deserializer<'de>,
@@ -377,21 +433,21 @@ pub fn parse_string_vecvec3(s: &str) -> Result<Vec<Vector3>, String> {
     parse_string_vec(s, 3, |chunk| Ok(Vector3::new(chunk[0], chunk[1], chunk[2])))
 }
 
-fn parse_string_vec<T, F>(s: &str, chunk_len: usize, mut f: F) -> Result<Vec<T>, String>
+fn parse_string_vec<T, F>(s: &str, chunk_len: usize, f: F) -> Result<Vec<T>, String>
 where
-    F: FnMut(&[f64]) -> Result<T, String>,
+    F: FnMut(&[Float]) -> Result<T, String>,
 {
-    let nums: Vec<f64> = s
+    let nums: Vec<Float> = s
         .split_whitespace()
-        .map(|x| x.parse::<f64>().map_err(|e| e.to_string()))
+        .map(|x| x.parse::<Float>().map_err(|e| e.to_string()))
         .collect::<Result<_, _>>()?;
 
-    if nums.len() % chunk_len != 0 {
+    if !nums.len().is_multiple_of(chunk_len) {
         return Err(format!("Input length not divisible by {}", chunk_len));
     }
 
     nums.chunks(chunk_len)
-        .map(|chunk| f(chunk))
+        .map(f)
         .collect::<Result<Vec<_>, _>>()
 }
 
@@ -400,7 +456,8 @@ where
 // https://serde.rs/string-or-struct.html
 pub fn deser_string_or_struct<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
-    T: Deserialize<'de> + FromStr<Err = Void>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: fmt::Display,
     D: Deserializer<'de>,
 {
     // This is a Visitor that forwards string types to T's `FromStr` impl and
@@ -412,7 +469,8 @@ where
 
     impl<'de, T> Visitor<'de> for StringOrStruct<T>
     where
-        T: Deserialize<'de> + FromStr<Err = Void>,
+        T: Deserialize<'de> + FromStr,
+        T::Err: fmt::Display,
     {
         type Value = T;
 
@@ -424,7 +482,7 @@ where
         where
             E: de::Error,
         {
-            Ok(FromStr::from_str(value).unwrap())
+            FromStr::from_str(value).map_err(de::Error::custom)
         }
 
         fn visit_map<M>(self, map: M) -> Result<T, M::Error>
@@ -0,0 +1,63 @@
+/*
+
+    Library root of the ray tracer. Everything that used to
+    live as private modules of the `raytracer` binary is
+    declared here so that other tools (asset pipelines,
+    integration tests, ...) can link against it.
+
+    Stable entry points are re-exported at the crate root:
+        - parse_json795 / RootScene / Scene to load a scene
+        - Camera, Material, PrimitiveShape to inspect it
+        - render to produce ImageData, which can be saved as .png
+        - TracerError reported by all of the above on failure
+
+    e.g.
+        let mut root = furry_tracer::parse_json795("scene.json")?;
+        root.scene.setup_after_json(Path::new("scene.json"))?;
+        for image in furry_tracer::render(&root.scene)? {
+            image.save_png("./")?;
+        }
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+pub mod ray;
+pub mod tile;
+pub mod error;
+pub mod film;
+pub mod image;
+pub mod compare;
+pub mod scene;
+pub mod camera;
+pub mod shapes;
+pub mod csg;
+pub mod sdf;
+pub mod subdivision;
+pub mod displacement;
+pub mod bezier;
+pub mod numeric;
+pub mod interval;
+pub mod material;
+pub mod brdf;
+pub mod light;
+pub mod renderer;
+pub mod sampler;
+pub mod spectrum;
+pub mod volume;
+pub mod progress;
+pub mod checkpoint;
+pub mod geometry;
+pub mod dataforms;
+pub mod validation;
+pub mod json_parser;
+
+pub use crate::camera::Camera;
+pub use crate::image::ImageData;
+pub use crate::error::{TracerError, TracerResult};
+pub use crate::validation::{Diagnostic, ValidationMode};
+pub use crate::material::{Material, MaterialKind};
+pub use crate::renderer::{render, render_with, AdaptiveSettings, ProgressiveSettings, RenderSettings};
+pub use crate::shapes::PrimitiveShape;
+pub use crate::json_parser::parse_json795;
+pub use crate::scene::{RenderOverrides, RootScene, Scene};
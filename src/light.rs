@@ -0,0 +1,196 @@
+/*
+
+    Area lights: any triangle (of a mesh as well) or sphere
+    whose material has a nonzero Emission is a light, e.g.
+        "Material": { "_id": "1", "Emission": "20 20 20", "DiffuseReflectance": "0 0 0" }
+    Emitters are collected after the scene is set up and
+    sampled proportional to their area, i.e. a point is
+    picked uniformly on the union of emitting surfaces.
+    Emitters are two-sided, see renderer::shade_diffuse( ).
+
+    Planes are infinite so they can not be sampled and
+    they never become lights, neither do other shapes
+    without an emitter (see PrimitiveShape::as_emitter( )).
+    Their emission is only seen by rays that hit them, so
+    shapes sharing their material are not sampled either,
+    otherwise the renderer could not tell which emission
+    is already counted by light sampling.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use tracing::warn;
+
+use crate::geometry::{get_tri_normal, triangle_area};
+use crate::interval::FloatConst;
+use crate::material::{Material, MaterialKind};
+use crate::numeric::{Float, Vector3};
+use crate::shapes::{HeapAllocatedVerts, ShapeList};
+
+/// World space surface of an emitting primitive
+#[derive(Debug, Clone)]
+pub enum Emitter {
+    Triangle([Vector3; 3]),
+    Sphere { center: Vector3, radius: Float },
+}
+
+impl Emitter {
+    pub fn area(&self) -> Float {
+        match self {
+            Emitter::Triangle([a, b, c]) => triangle_area(a, b, c),
+            Emitter::Sphere { radius, .. } => 4.0 * Float::PI * radius * radius,
+        }
+    }
+
+    /// Uniformly distributed point on the surface and the normal there
+    pub fn sample_point(&self, u: (Float, Float)) -> (Vector3, Vector3) {
+        match self {
+            Emitter::Triangle([a, b, c]) => {
+                // Square root parametrization, see PBRT 4th ed. 6.5.4
+                let s = u.0.sqrt();
+                let (w_b, w_c) = (s * (1.0 - u.1), s * u.1);
+                (a * (1.0 - s) + b * w_b + c * w_c, get_tri_normal(a, b, c))
+            }
+            Emitter::Sphere { center, radius } => {
+                let z = 1.0 - 2.0 * u.0;
+                let r = (1.0 - z * z).max(0.0).sqrt();
+                let phi = 2.0 * Float::PI * u.1;
+                let normal = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+                (center + normal * *radius, normal)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AreaLight {
+    pub emitter: Emitter,
+    pub radiance: Vector3,
+}
+
+/// Point sampled on an area light, pdf is with respect to surface area
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    pub point: Vector3,
+    pub normal: Vector3,
+    pub radiance: Vector3,
+    pub pdf_area: Float,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AreaLights {
+    lights: Vec<AreaLight>,
+    cdf: Vec<Float>, // Cumulative area, last one is the total area
+    unsampled_materials: Vec<usize>, // Emissive materials of shapes that can not be sampled
+}
+
+impl AreaLights {
+
+    pub fn build(shapes: &ShapeList, materials: &[MaterialKind], vertex_cache: &HeapAllocatedVerts) -> Self {
+        let mut area_lights = Self::default();
+        let mut emitters = Vec::new();
+        for shape in shapes.iter() {
+            // Material ids in JSON start from 1, missing ones are reported by validation
+            let Some(mat) = shape.material_idx().checked_sub(1).and_then(|i| materials.get(i)) else {
+                continue;
+            };
+            let radiance = mat.emission();
+            if radiance.max_element() <= 0.0 {
+                continue;
+            }
+            match shape.as_emitter(vertex_cache) {
+                Some(emitter) => emitters.push((shape.material_idx(), AreaLight { emitter, radiance })),
+                None if !area_lights.unsampled_materials.contains(&shape.material_idx()) => {
+                    warn!("Material {} is emissive on a shape that can not be sampled, its shapes only emit light where rays hit them.", shape.material_idx());
+                    area_lights.unsampled_materials.push(shape.material_idx());
+                }
+                None => {}
+            }
+        }
+        for (material, light) in emitters {
+            if area_lights.is_sampled(material) {
+                area_lights.push(light);
+            }
+        }
+        area_lights
+    }
+
+    pub fn push(&mut self, light: AreaLight) {
+        let area = light.emitter.area();
+        if area <= 0.0 {
+            return; // Degenerate, can not be sampled
+        }
+        self.cdf.push(self.total_area() + area);
+        self.lights.push(light);
+    }
+
+    pub fn total_area(&self) -> Float {
+        self.cdf.last().copied().unwrap_or(0.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// False if the emission of shapes of the material is not found by sample( ), rays that hit them have to add it
+    pub fn is_sampled(&self, material: usize) -> bool {
+        !self.unsampled_materials.contains(&material)
+    }
+
+    /// Pick a light proportional to its area with u_light, then a point on it with u
+    pub fn sample(&self, u_light: Float, u: (Float, Float)) -> Option<LightSample> {
+        let total_area = self.total_area();
+        if total_area <= 0.0 {
+            return None;
+        }
+        let target = u_light * total_area;
+        let i = self.cdf.partition_point(|&c| c <= target).min(self.lights.len() - 1);
+        let light = &self.lights[i];
+        let (point, normal) = light.emitter.sample_point(u);
+        // (area_i / total_area) * (1 / area_i)
+        Some(LightSample { point, normal, radiance: light.radiance, pdf_area: 1.0 / total_area })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn test_sample_by_area() {
+        // Unit square as two triangles and a sphere of area 2, sphere should get 2/3 of the samples
+        let mut lights = AreaLights::default();
+        let emitters = [
+            Emitter::Triangle([Vector3::ZERO, Vector3::X, Vector3::Y]),
+            Emitter::Triangle([Vector3::X, Vector3::new(1.0, 1.0, 0.0), Vector3::Y]),
+            Emitter::Sphere { center: Vector3::new(5.0, 0.0, 0.0), radius: (0.5 / Float::PI).sqrt() },
+        ];
+        for emitter in emitters {
+            lights.push(AreaLight { emitter, radiance: Vector3::ONE });
+        }
+        assert!((lights.total_area() - 3.0).abs() < 1e-6);
+
+        let mut sampler = Sampler::new(1, 2);
+        let num_samples = 30_000;
+        let mut on_sphere = 0;
+        for _ in 0..num_samples {
+            let s = lights.sample(sampler.next_float(), sampler.next_2d()).unwrap();
+            assert!((s.pdf_area - 1.0 / 3.0).abs() < 1e-6);
+            if s.point.x > 2.0 {
+                on_sphere += 1;
+                assert!(((s.point - Vector3::new(5.0, 0.0, 0.0)).length() - (0.5 / Float::PI).sqrt()).abs() < 1e-4);
+            } else {
+                assert!(s.point.z == 0.0 && (0.0..=1.0).contains(&s.point.x) && (0.0..=1.0).contains(&s.point.y));
+            }
+        }
+        let ratio = on_sphere as Float / num_samples as Float;
+        assert!((ratio - 2.0 / 3.0).abs() < 0.02, "{}", ratio);
+    }
+}
@@ -1,75 +1,135 @@
 /*
 
     A simple ray tracer implemented for CENG 795 course.
+    See cli.rs for the supported commands and options.
 
     @date: Oct, 2025
     @author: Bartu
 
 */
 
-use std::{self, env, time::Instant, path::Path};
-use tracing::{info, warn, error, debug};
-use tracing_subscriber;
-
-mod ray;
-mod image;
-mod scene;
-mod camera;
-mod shapes;
-mod numeric;
-mod interval;
-mod material;
-mod renderer;
-mod geometry;
-mod dataforms;
-mod json_parser;
-use crate::{json_parser::parse_json795};
-// TODO: How to group these mods better to declutter main?
-
-fn main()  -> Result<(), Box<dyn std::error::Error>> {
+use std::{env, fs, path::{Path, PathBuf}, process::ExitCode, time::Instant};
+use tracing::{info, error, debug};
+
+use furry_tracer::{compare, parse_json795, render_with, validation, ImageData, Scene, TracerError, TracerResult};
+
+mod cli;
+use crate::cli::{Command, RenderArgs, USAGE};
+
+fn main() -> ExitCode {
 
     // Logging on console
-    tracing_subscriber::fmt::init(); 
+    tracing_subscriber::fmt::init();
 
     // Parse args
-    let args: Vec<String> = env::args().collect();
-    let json_path: &String = if args.len() == 1 {
-        warn!("No arguments were provided, setting default scene path...");
-        //&String::from("./inputs/deniz_sayin/lobster.json")
-        &String::from("./inputs/other_dragon.json")
-        //        &String::from("./input/chinese_dragon.json")
-
-    } else if args.len() == 2 {
-        &args[1]
-    } else {
-        error!("Usage: {} <filename>.json", args[0]);
-        std::process::exit(1);
+    let command = match cli::parse_args(env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            error!("{}", message);
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    type Run = fn(&Path, &RenderArgs) -> TracerResult<()>;
+    let (scenes, args, run): (Vec<PathBuf>, RenderArgs, Run) = match command {
+        Command::Help => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Command::Render { scenes, args } => {
+            if let Some(threads) = args.threads
+                && let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(threads).build_global() {
+                error!("Failed to set number of threads: {}", e);
+                return ExitCode::FAILURE;
+            }
+            (scenes, *args, render_scene)
+        }
+        Command::Info { scenes } => (scenes, RenderArgs::default(), print_info),
+        Command::Compare { image, reference, heatmap } => {
+            return match compare_images(&image, &reference, &heatmap) {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    error!("{}", e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
     };
-    
-    // Parse JSON
-    info!("Loading scene from {}...", json_path);
-    let mut root = parse_json795(json_path).map_err(|e| {
-        error!("Failed to load scene: {}", e);
-        Box::<dyn std::error::Error>::from(e)
-    })?;
-
-    let json_path = Path::new(json_path).canonicalize()?;
+
+    // Batch mode: keep going if a scene fails, report at the end
+    let mut failed = Vec::new();
+    for scene_path in scenes.iter() {
+        if let Err(e) = run(scene_path, &args) {
+            error!("{}: {}", scene_path.display(), e);
+            failed.push(scene_path);
+        }
+    }
+    if scenes.len() > 1 {
+        info!("Finished {} scene(s), {} failed.", scenes.len(), failed.len());
+    }
+    if failed.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+fn load_scene(json_path: &Path) -> TracerResult<Scene> {
+    info!("Loading scene from {}...", json_path.display());
+    let mut root = parse_json795(&json_path.to_string_lossy())?;
+    let json_path = json_path.canonicalize().map_err(|e| TracerError::io(json_path, e))?;
     root.scene.setup_after_json(&json_path)?; // TODO: This should be done in a different way
     debug!("Scene is setup successfully.\n {:#?}", root);
-    let root = root; // Shadow mutatability before render
+    Ok(root.scene)
+}
+
+fn render_scene(json_path: &Path, args: &RenderArgs) -> TracerResult<()> {
+    let mut scene = load_scene(json_path)?;
+    scene.apply_overrides(&args.overrides)?;
+    validation::check(&scene, args.validation)?;
+    let scene = scene; // Shadow mutatability before render
 
     // Render image and return array of RGB
     let start = Instant::now();
-    let images = renderer::render(&root.scene)?;
-    info!("Rendering of {} image(s) took: {:?}", images.len(), start.elapsed()); 
+    let images = render_with(&scene, &args.settings)?;
+    info!("Rendering of {} image(s) took: {:?}", images.len(), start.elapsed());
 
     // Write images to .png files
+    let imagefolder = &args.output_dir;
+    fs::create_dir_all(imagefolder).map_err(|e| TracerError::io(imagefolder, e))?;
     for im in images.into_iter() {
-        let imagefolder = "./"; // Save to current folder 
-        if let Err(e) = im.save_png(&imagefolder) {
-            eprintln!("Failed to save {}: {}", imagefolder, e);
-        }
+        im.save_png(&imagefolder.to_string_lossy())?;
     }
     info!("Finished execution.");
     Ok(())
 }
+
+fn print_info(json_path: &Path, _: &RenderArgs) -> TracerResult<()> {
+    let scene = load_scene(json_path)?;
+    println!("{}", json_path.display());
+    println!("{}", scene.stats());
+    for cam in scene.cameras.all() {
+        let [width, height] = cam.image_resolution;
+        println!("  Camera {}: {} ({}x{}, {} sample(s))", cam._id, cam.image_name, width, height, cam.num_samples);
+    }
+    println!("  Max recursion depth: {}", scene.max_recursion_depth);
+    let diagnostics = scene.validate();
+    println!("  Validation problems: {}", diagnostics.len());
+    for d in diagnostics.iter() {
+        println!("    - {}", d);
+    }
+    Ok(())
+}
+
+fn compare_images(image_path: &Path, reference_path: &Path, heatmap_path: &Path) -> TracerResult<()> {
+    let image = ImageData::load(image_path)?;
+    let reference = ImageData::load(reference_path)?;
+    let diff = compare::compare(&image, &reference)?;
+    println!("{} vs. {}", image_path.display(), reference_path.display());
+    println!("  MSE:  {:.4}", diff.mse);
+    println!("  RMSE: {:.4}", diff.rmse);
+    println!("  PSNR: {:.2} dB", diff.psnr);
+    println!("  SSIM: {:.4}", diff.ssim);
+    println!("  Pixels differing: {} of {}, max channel error {}", diff.num_different, image.pixel_colors().len(), diff.max_error);
+
+    let name = heatmap_path.to_string_lossy();
+    compare::heatmap(&image, &reference, None, &name)?.save_png(&name)?;
+    Ok(())
+}
@@ -4,20 +4,30 @@
     different types of materials. Currently supporting:
         - Diffuse
         - Mirror
-        - Conductor (TBI)
-        - Dielectric (TBI)
+        - Conductor
+        - Dielectric
+        - GGX (microfacet metals and plastics)
+        - Volume (invisible boundary of a participating medium)
+        - Subsurface (skin, wax, marble: random walk under the surface)
+
+    Scene materials are stored as MaterialKind, an enum of
+    all material structs, so that renderer matches on the
+    variant and the compiler checks every material is shaded.
 
     @date: Oct, 2025
     @author: Bartu
 
 */
 use std::fmt::Debug;
-use bevy_math::ops::cos;
-use tracing::{error, info, warn};
+use tracing::{info, warn};
 use serde::{Deserialize, de::DeserializeOwned};
+use crate::brdf::BRDF;
+use crate::interval::FloatConst;
 use crate::json_parser::*;
-use crate::numeric::{approx_zero, Float, Vector3};
-use crate::ray::{Ray, HitRecord}; // TODO: rename it to light or lighting, not lights?
+use crate::numeric::{Float, Vector3};
+use crate::ray::{Ray, HitRecord, Medium}; // TODO: rename it to light or lighting, not lights?
+use crate::spectrum::{cauchy, interpolate_nk, sellmeier, LAMBDA_D};
+use crate::volume::HomogeneousVolume;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 /// 
@@ -26,22 +36,26 @@ use crate::ray::{Ray, HitRecord}; // TODO: rename it to light or lighting, not l
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 pub trait Material : Debug + Send + Sync  {
     
-    fn new_from(value: &serde_json::Value) -> Self 
+    fn new_from(value: &serde_json::Value) -> Result<Self, serde_json::Error>
     where
-        Self: Sized + DeserializeOwned + Default,
+        Self: Sized + DeserializeOwned,
     {
-        match serde_json::from_value::<Self>(value.clone()) {
-            Ok(m) => m,
-            Err(e) => {
-                error!("Failed to parse DiffuseMaterial: {e}. JSON: {value}");
-                Self::default()
-            }
-        }
+        serde_json::from_value::<Self>(value.clone())
+    }
+    fn brdf(&self) -> &BRDF;
+
+    fn ambient(&self) -> Vector3 {
+        self.brdf().ambient_rf
+    }
+
+    fn emission(&self) -> Vector3 {
+        self.brdf().emission
+    }
+
+    /// Reflected radiance towards w_o per unit irradiance from w_i (see brdf.rs)
+    fn shade(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
+        self.brdf().eval(w_o, w_i, n)
     }
-    fn get_type(&self) -> &str;
-    fn diffuse(&self, w_i: Vector3, n: Vector3) -> Vector3;
-    fn specular(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3;
-    fn ambient(&self) -> Vector3; 
 
     //fn get_attenuiation(&self, ray_in: &Ray, ray_out: &mut Option<Ray>, hit_record: &HitRecord) -> Vector3;
     //fn attenuate_reflect(&self, ray_in: &Ray, ray_t: Float) -> Vector3;
@@ -54,7 +68,70 @@ pub trait Material : Debug + Send + Sync  {
     //}
 }
 
-pub type HeapAllocMaterial = Box<dyn Material>; // Box, Rc, Arc -> Probably will be Arc when we use rayon
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// 
+/// MATERIAL KIND
+/// 
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// WARNING: When adding a variant, also add its _type to scene::parse_single_material( ),
+// renderer::get_color( ) will not compile until its shading is added as well
+#[derive(Debug, Clone)]
+pub enum MaterialKind {
+    Diffuse(DiffuseMaterial),
+    Mirror(MirrorMaterial),
+    Dielectric(DielectricMaterial),
+    Conductor(ConductorMaterial),
+    GGX(GGXMaterial),
+    Volume(VolumeMaterial),
+    Subsurface(SubsurfaceMaterial),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $m:ident => $e:expr) => {
+        match $self {
+            MaterialKind::Diffuse($m) => $e,
+            MaterialKind::Mirror($m) => $e,
+            MaterialKind::Dielectric($m) => $e,
+            MaterialKind::Conductor($m) => $e,
+            MaterialKind::GGX($m) => $e,
+            MaterialKind::Volume($m) => $e,
+            MaterialKind::Subsurface($m) => $e,
+        }
+    };
+}
+
+impl MaterialKind {
+    pub fn brdf_mut(&mut self) -> &mut BRDF {
+        dispatch!(self, m => &mut m.brdf)
+    }
+
+    /// BRDF of the default material of the same type
+    pub fn default_brdf(&self) -> BRDF {
+        fn of<M: Default + Material>(_: &M) -> BRDF {
+            M::default().brdf().clone()
+        }
+        dispatch!(self, m => of(m))
+    }
+}
+
+impl Material for MaterialKind {
+    fn brdf(&self) -> &BRDF {
+        dispatch!(self, m => m.brdf())
+    }
+
+    fn shade(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
+        dispatch!(self, m => m.shade(w_o, w_i, n))
+    }
+
+    fn reflect(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
+        dispatch!(self, m => m.reflect(ray_in, hit_record, epsilon))
+    }
+
+    fn refract(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
+        dispatch!(self, m => m.refract(ray_in, hit_record, epsilon))
+    }
+}
 
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -63,44 +140,23 @@ pub type HeapAllocMaterial = Box<dyn Material>; // Box, Rc, Arc -> Probably will
 /// 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, Default)]
 #[serde(default)]
 pub struct DiffuseMaterial {
     #[serde(deserialize_with = "deser_usize")]
     pub _id: usize,
-    #[serde(rename = "AmbientReflectance", deserialize_with = "deser_vec3")]
-    pub ambient_rf: Vector3,
-    #[serde(rename = "DiffuseReflectance", deserialize_with = "deser_vec3")]
-    pub diffuse_rf: Vector3,
-    #[serde(rename = "SpecularReflectance", deserialize_with = "deser_vec3")]
-    pub specular_rf: Vector3,
-    #[serde(rename = "PhongExponent", deserialize_with = "deser_float")]
-    pub phong_exponent: Float,
+    #[serde(flatten)]
+    pub brdf: BRDF,
 }
 
 
-impl Default for DiffuseMaterial {
-    fn default() -> Self {
-        DiffuseMaterial {
-            _id: 0,
-            ambient_rf: Vector3::new(0.0, 0.0, 0.0),
-            diffuse_rf: Vector3::new(1.0, 1.0, 1.0),
-            specular_rf: Vector3::new(0.0, 0.0, 0.0),
-            phong_exponent: 1.0,
-        }
-    }
-}
-
 impl DiffuseMaterial {
-
-    
 }
 
 impl Material for DiffuseMaterial{
 
-
-    fn get_type(&self) -> &str {
-        "diffuse"
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
     }
 
     fn reflect(&self, _: &Ray, _: &HitRecord, _: Float) -> Option<(Ray, Vector3)> {
@@ -112,39 +168,6 @@ impl Material for DiffuseMaterial{
         warn!("There is no refract for DiffuseMaterial. If this is intentional please delete this warning.");
         None
     }
-
-    fn ambient(&self) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.75)
-        // e.g. for test.json it is [25, 25, 25]
-        self.ambient_rf 
-    }
-
-    fn diffuse(&self, w_i: Vector3, n: Vector3) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.73)
-        // TODO: reduce the verbosity here
-        
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
-
-        let cos_theta = w_i.dot(n).max(0.0);
-        self.diffuse_rf * cos_theta 
-    }
-
-    fn specular(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.80)
-        debug_assert!(w_o.is_normalized());
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
-
-        let h = (w_i + w_o).normalize(); //(w_i + w_o) / (w_i + w_o).norm();
-        debug_assert!(h.is_normalized());
-        
-        let p = self.phong_exponent;
-        let cos_a = n.dot(h).max(0.0);
-        self.specular_rf * cos_a.powf(p)
-    }   
-    
-
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -158,27 +181,18 @@ impl Material for DiffuseMaterial{
 pub struct MirrorMaterial {
     #[serde(deserialize_with = "deser_usize")]
     pub _id: usize,
-    #[serde(rename = "AmbientReflectance", deserialize_with = "deser_vec3")]
-    pub ambient_rf: Vector3,
-    #[serde(rename = "DiffuseReflectance", deserialize_with = "deser_vec3")]
-    pub diffuse_rf: Vector3,
-    #[serde(rename = "SpecularReflectance", deserialize_with = "deser_vec3")]
-    pub specular_rf: Vector3,
+    #[serde(flatten)]
+    pub brdf: BRDF,
     #[serde(rename = "MirrorReflectance", deserialize_with = "deser_vec3")]
     pub mirror_rf: Vector3,
-    #[serde(rename = "PhongExponent", deserialize_with = "deser_float")]
-    pub phong_exponent: Float,
 }
 
 impl Default for MirrorMaterial {
     fn default() -> Self {
         Self {
             _id: 0,
-            ambient_rf: Vector3::new(0.0, 0.0, 0.0),
-            diffuse_rf: Vector3::new(0.5, 0.5, 0.5),
-            specular_rf: Vector3::new(0.0, 0.0, 0.0),
+            brdf: BRDF { diffuse_rf: Vector3::new(0.5, 0.5, 0.5), ..BRDF::default() },
             mirror_rf: Vector3::new(0.5, 0.5, 0.5),
-            phong_exponent: 1.0,
         }
     }
 }
@@ -189,8 +203,8 @@ impl MirrorMaterial {
 
 impl Material for MirrorMaterial {
 
-    fn get_type(&self) -> &str {
-        "mirror"
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
     }
 
     fn reflect(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
@@ -202,7 +216,7 @@ impl Material for MirrorMaterial {
         let w_r = w_i - 2. * n * (n.dot(w_i));
         debug_assert!(w_r.is_normalized());
         
-        let ray = Ray::new(hit_record.point + (n * epsilon), w_r);
+        let ray = ray_in.spawn(hit_record.point + (n * epsilon), w_r);
         let attenuation = self.mirror_rf;
         Some((ray, attenuation)) // Always reflects
     }
@@ -210,35 +224,6 @@ impl Material for MirrorMaterial {
     fn refract(&self, _: &Ray, _: &HitRecord, _: Float) -> Option<(Ray, Vector3)> {
         None // Never refract
     }
-    
-    fn ambient(&self) -> Vector3 {
-        self.ambient_rf  
-    }
-
-    fn diffuse(&self, w_i: Vector3, n: Vector3) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.73)
-        // TODO: reduce the verbosity here
-        
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
-
-        let cos_theta = w_i.dot(n).max(0.0);
-        self.diffuse_rf * cos_theta  
-    }
-
-    fn specular(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.80)
-        debug_assert!(w_o.is_normalized());
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
-
-        let h = (w_i + w_o).normalize(); //(w_i + w_o) / (w_i + w_o).norm();
-        debug_assert!(h.is_normalized());
-        
-        let p = self.phong_exponent;
-        let cos_a = n.dot(h).max(0.0);
-        self.specular_rf * cos_a.powf(p)  
-    }   
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -261,45 +246,64 @@ struct FresnelData {
 pub struct DielectricMaterial {
     #[serde(deserialize_with = "deser_usize")]
     pub _id: usize,
-    #[serde(rename = "AmbientReflectance", deserialize_with = "deser_vec3")]
-    pub ambient_rf: Vector3,
-    #[serde(rename = "DiffuseReflectance", deserialize_with = "deser_vec3")]
-    pub diffuse_rf: Vector3,
-    #[serde(rename = "SpecularReflectance", deserialize_with = "deser_vec3")]
-    pub specular_rf: Vector3,
+    #[serde(flatten)]
+    pub brdf: BRDF,
     #[serde(rename = "MirrorReflectance", deserialize_with = "deser_vec3")]
     pub mirror_rf: Vector3,
-    #[serde(rename = "PhongExponent", deserialize_with = "deser_float")]
-    pub phong_exponent: Float,
     #[serde(rename = "AbsorptionCoefficient", deserialize_with = "deser_vec3")]
     pub absorption_coeff: Vector3,
     #[serde(rename = "RefractionIndex", deserialize_with = "deser_float")]
     pub refraction_index: Float,
+    #[serde(rename = "CauchyCoefficients", deserialize_with = "deser_float_vec")]
+    pub cauchy: Vec<Float>, // A B [C], for dispersion (see spectrum.rs), overrides RefractionIndex
+    #[serde(rename = "SellmeierCoefficients", deserialize_with = "deser_float_vec")]
+    pub sellmeier: Vec<Float>, // B1 B2 B3 C1 C2 C3, overrides both of the above
+    #[serde(rename = "ScatteringCoefficient", deserialize_with = "deser_vec3")]
+    pub scattering_coeff: Vector3, // Murky dielectrics, e.g. milky glass (see volume.rs)
+    #[serde(rename = "Anisotropy", deserialize_with = "deser_float")]
+    pub anisotropy: Float,
 }
 
 impl Default for DielectricMaterial {
     fn default() -> Self {
         Self {
             _id: 0,
-            ambient_rf: Vector3::new(0.0, 0.0, 0.0),
-            diffuse_rf: Vector3::new(0.5, 0.5, 0.5),
-            specular_rf: Vector3::new(0.0, 0.0, 0.0),
+            brdf: BRDF { diffuse_rf: Vector3::new(0.5, 0.5, 0.5), ..BRDF::default() },
             mirror_rf: Vector3::new(0.5, 0.5, 0.5),
-            phong_exponent: 1.0,
             absorption_coeff: Vector3::new(0.01, 0.01, 0.01),
             refraction_index: 1.5,
+            cauchy: Vec::new(),
+            sellmeier: Vec::new(),
+            scattering_coeff: Vector3::ZERO,
+            anisotropy: 0.0,
         }
     }
 }
 
 impl DielectricMaterial {
 
-    fn get_beers_law_attenuation(&self, ray_in: &Ray, ray_t: Float) -> Vector3 {
-        // Slides 02, p.27, only e^(-Cx) part
-        // where C is the absorption coefficient
-        // WARNING: ray_in.origin is assumed to be the location of the last hit point
-        // i.e. point in p.28 with arrow to L(x)
-        (- self.absorption_coeff * ray_in.distance_at(ray_t)).exp() 
+    /// Refraction index at given wavelength, the one of sodium D line in RGB mode (None)
+    pub fn refraction_index_at(&self, wavelength: Option<Float>) -> Float {
+        let wavelength = wavelength.unwrap_or(LAMBDA_D);
+        if !self.sellmeier.is_empty() {
+            sellmeier(&self.sellmeier, wavelength)
+        } else if !self.cauchy.is_empty() {
+            cauchy(&self.cauchy, wavelength)
+        } else {
+            self.refraction_index
+        }
+    }
+
+    /// Medium inside the dielectric, material is the id its surfaces are hit with
+    pub fn medium(&self, material: usize, wavelength: Option<Float>) -> Medium {
+        Medium {
+            material,
+            refraction_index: self.refraction_index_at(wavelength),
+            absorption_coeff: self.absorption_coeff,
+            scattering_coeff: self.scattering_coeff,
+            anisotropy: self.anisotropy,
+            grid: None,
+        }
     }
 
     fn fresnel(&self, ray_in: &Ray, hit_record: &HitRecord, fresnel: &mut FresnelData) ->  bool {
@@ -317,13 +321,13 @@ impl DielectricMaterial {
         debug_assert!(n.is_normalized());
         let cos_theta = n.dot(-d);
         
-        // TODO: Would it be more flexible if we read it from FresnelData?
-        let mut n1 = 1.00029 as Float; // Assuming Air in slides 02, p.22
-        let mut n2 = self.refraction_index;
-        if !hit_record.is_front_face {
-            n1 = self.refraction_index;
-            n2 = 1.00029 as Float;
-        }
+        // n1 is the medium ray travels in, n2 the one on the other side (air unless dielectrics are nested)
+        let refraction_index = self.refraction_index_at(ray_in.wavelength);
+        let (n1, n2) = if hit_record.is_front_face {
+            (ray_in.media.current().refraction_index, refraction_index)
+        } else {
+            (refraction_index, ray_in.media.outside_of(hit_record.material).refraction_index)
+        };
         
         let ratio_squared: Float = (n1 / n2).powi(2);
         let one_minus_cossqrd: Float = 1. - (cos_theta.powi(2));
@@ -348,7 +352,7 @@ impl DielectricMaterial {
         // I assumed this is a typo and checked Fresnel from wikipedia... but I gotta ask for confirmation
 
         let f_r = 0.5 * (r_parallel.powi(2) + r_perp.powi(2));
-        debug_assert!( (f_r > 1e-20) && (f_r < 1.+1e-20)); // in range [0,1]
+        debug_assert!((0. ..1.+1e-20).contains(&f_r)); // in range [0,1], 0 between media of the same refraction index
 
         fresnel.n_ratio = n1 / n2;
         fresnel.cos_theta = cos_theta; 
@@ -362,10 +366,10 @@ impl DielectricMaterial {
 
 impl Material for DielectricMaterial {
 
-    fn get_type(&self) -> &str {
-        "dielectric"
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
     }
-    
+
     fn reflect(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
         
         let mut fresnel = FresnelData::default();
@@ -377,7 +381,7 @@ impl Material for DielectricMaterial {
             let w_r = w_i - 2.0 * n * (n.dot(w_i));
             debug_assert!(w_r.is_normalized());
             
-            let ray = Ray::new(hit_record.point + (n * epsilon), w_r);
+            let ray = ray_in.spawn(hit_record.point + (n * epsilon), w_r); // Stays in the same medium
             let attenuation = fresnel.f_r * self.mirror_rf; // TODO: Am I doing it right?? scalar times a vector, is that really the attenuation from glass reflectance?
             Some((ray, attenuation))
         } else {
@@ -395,14 +399,14 @@ impl Material for DielectricMaterial {
             let refracted_direction = ((d + (n * frd.cos_theta)) * frd.n_ratio) - (n * frd.cos_phi); // p.15
             debug_assert!(refracted_direction.is_normalized());
 
-            let ray = Ray::new(hit_record.point - n * epsilon, refracted_direction);
-            let mut attenuation = frd.f_t * Vector3::ONE;
-            if !hit_record.is_front_face {
-                // Attenuate as it goes out of object 
-                // assumes glass object is empty
-                attenuation *= self.get_beers_law_attenuation(ray_in, hit_record.ray_t);
-            } 
-           
+            let media = if hit_record.is_front_face {
+                ray_in.media.entering(self.medium(hit_record.material, ray_in.wavelength))
+            } else {
+                ray_in.media.exiting(hit_record.material)
+            };
+            let ray = ray_in.spawn_into(hit_record.point - n * epsilon, refracted_direction, media);
+            // Absorption along the ray is applied by renderer::get_color( ) using the medium of the ray
+            let attenuation = frd.f_t * Vector3::ONE;
             Some((ray, attenuation))
         }
         else {
@@ -411,41 +415,6 @@ impl Material for DielectricMaterial {
        
         
     }
-    fn ambient(&self) -> Vector3 {
-        self.ambient_rf  
-    }
-
-    fn diffuse(&self, w_i: Vector3, n: Vector3) -> Vector3 {
-        // TODO: these are copy paste from Diffuse material,
-        // should we refactor them into a single function within
-        // this crate?
-        // Actually a better implementation would be to create a struct
-        // for diffuse, specular, ambient, and phong as these four are common
-        // and then just store them in material, that way you can move diffuse
-        // and other common functions inside Material trait! I believe this is 
-        // a Rusty way to implement it but before that I better decouple json
-        // parser from these material structs...
-        
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
-
-        let cos_theta = w_i.dot(n).max(0.0);
-        self.diffuse_rf * cos_theta  
-    }
-
-    fn specular(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.80)
-        debug_assert!(w_o.is_normalized());
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
-
-        let h = (w_i + w_o).normalize(); //(w_i + w_o) / (w_i + w_o).norm();
-        debug_assert!(h.is_normalized());
-        
-        let p = self.phong_exponent;
-        let cos_a = n.dot(h).max(0.0);
-        self.specular_rf * cos_a.powf(p)  
-    }   
 }
 
 
@@ -461,33 +430,27 @@ impl Material for DielectricMaterial {
 pub struct ConductorMaterial {
     #[serde(deserialize_with = "deser_usize")]
     pub _id: usize,
-    #[serde(rename = "AmbientReflectance", deserialize_with = "deser_vec3")]
-    pub ambient_rf: Vector3,
-    #[serde(rename = "DiffuseReflectance", deserialize_with = "deser_vec3")]
-    pub diffuse_rf: Vector3,
-    #[serde(rename = "SpecularReflectance", deserialize_with = "deser_vec3")]
-    pub specular_rf: Vector3,
+    #[serde(flatten)]
+    pub brdf: BRDF,
     #[serde(rename = "MirrorReflectance", deserialize_with = "deser_vec3")]
     pub mirror_rf: Vector3,
-    #[serde(rename = "PhongExponent", deserialize_with = "deser_float")]
-    pub phong_exponent: Float,
     #[serde(rename = "AbsorptionIndex", deserialize_with = "deser_float")]
     pub absorption_index: Float,
     #[serde(rename = "RefractionIndex", deserialize_with = "deser_float")]
     pub refraction_index: Float,
+    #[serde(rename = "NKSpectrum", deserialize_with = "deser_float_vec")]
+    pub nk_spectrum: Vec<Float>, // (wavelength in nm, n, k) triples, overrides the indices above
 }
 
 impl Default for ConductorMaterial {
     fn default() -> Self {
         Self {
             _id: 0,
-            ambient_rf: Vector3::new(0., 0., 0.),
-            diffuse_rf: Vector3::new(0., 0., 0.),
-            specular_rf: Vector3::new(0., 0., 0.),
+            brdf: BRDF { diffuse_rf: Vector3::new(0., 0., 0.), ..BRDF::default() },
             mirror_rf: Vector3::new(1., 1., 1.),
-            phong_exponent: 1., // TODO: Is that a good default? WARNING: cornellbox_recursive missing phong 
             absorption_index: 2.82,
             refraction_index: 0.37,
+            nk_spectrum: Vec::new(),
         }
     }
 }
@@ -505,29 +468,35 @@ impl ConductorMaterial {
         debug_assert!(n.is_normalized());
         let cos_theta = n.dot(-d);
         
-        let n2 = self.refraction_index;
-        let k2 = self.absorption_index; // TODO: Why this is named as _index but not _coefficient as in p.21?
-        
-        let sum_nk = n2.powi(2) + k2.powi(2); 
-        let two_n_cos = 2. * n2 * cos_theta;
-        let cos_squared = cos_theta.powi(2);
-        let sum_nk_cos = sum_nk * cos_squared;
-
-        let r_s = (sum_nk - two_n_cos + cos_squared) / (sum_nk + two_n_cos + cos_squared);
-        let r_p = (sum_nk_cos - two_n_cos + 1.) / (sum_nk_cos + two_n_cos + 1.);
-
+        // TODO: Why absorption_index is named as _index but not _coefficient as in p.21?
+        // In spectral mode the color of a tabulated metal comes from F_r varying with wavelength,
+        // so MirrorReflectance is expected to be white then
+        let (n2, k2) = interpolate_nk(&self.nk_spectrum, ray_in.wavelength.unwrap_or(LAMBDA_D))
+            .unwrap_or((self.refraction_index, self.absorption_index));
         fresnel.cos_theta = cos_theta; 
-        fresnel.f_r =  0.5 * (r_s + r_p); // Reflection ratio
+        fresnel.f_r = conductor_fresnel(n2, k2, cos_theta); // Reflection ratio
         fresnel.f_t = 0.;
     }
 }
 
+/// Reflection ratio of a conductor with complex index of refraction n2 + i k2 (slides 02, p.21)
+pub fn conductor_fresnel(n2: Float, k2: Float, cos_theta: Float) -> Float {
+    let sum_nk = n2.powi(2) + k2.powi(2); 
+    let two_n_cos = 2. * n2 * cos_theta;
+    let cos_squared = cos_theta.powi(2);
+    let sum_nk_cos = sum_nk * cos_squared;
+
+    let r_s = (sum_nk - two_n_cos + cos_squared) / (sum_nk + two_n_cos + cos_squared);
+    let r_p = (sum_nk_cos - two_n_cos + 1.) / (sum_nk_cos + two_n_cos + 1.);
+    0.5 * (r_s + r_p)
+}
+
 impl Material for ConductorMaterial {
 
-    fn get_type(&self) -> &str {
-        "dielectric"
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
     }
-    
+
     fn reflect(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
         // TODO: This should be the same reflection logic with dielectric, right? Only fresnel is different?
         // Also it seems like we don't need FresnelData at all for conductor, since we only need F_r?
@@ -540,7 +509,7 @@ impl Material for ConductorMaterial {
             let w_r = w_i - 2.0 * n * (n.dot(w_i));
             debug_assert!(w_r.is_normalized());
             
-            let ray = Ray::new(hit_record.point + (n * epsilon), w_r);
+            let ray = ray_in.spawn(hit_record.point + (n * epsilon), w_r);
             let attenuation = fresnel.f_r * self.mirror_rf; // TODO: Am I doing it right?? scalar times a vector, is that really the attenuation from glass reflectance?
             Some((ray, attenuation))
         } else {
@@ -552,40 +521,430 @@ impl Material for ConductorMaterial {
     fn refract(&self, _: &Ray, _: &HitRecord, _: Float) -> Option<(Ray, Vector3)> {
         None // F_t = 0 (see slides 02, p.21)
     }
+}
 
-    fn ambient(&self) -> Vector3 {
-        self.ambient_rf  
-    }
-
-    fn diffuse(&self, w_i: Vector3, n: Vector3) -> Vector3 {
-        // TODO: these are copy paste from Diffuse material,
-        // should we refactor them into a single function within
-        // this crate?
-        // Actually a better implementation would be to create a struct
-        // for diffuse, specular, ambient, and phong as these four are common
-        // and then just store them in material, that way you can move diffuse
-        // and other common functions inside Material trait! I believe this is 
-        // a Rusty way to implement it but before that I better decouple json
-        // parser from these material structs...
-        
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// 
+/// GGX (MICROFACET)
+/// 
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
 
-        let cos_theta = w_i.dot(n).max(0.0);
-        self.diffuse_rf * cos_theta  
+// Metals and plastics similar to Blender's Principled BSDF: a Lambertian base
+// under a GGX specular layer (Walter et al. 2007) with Smith shadowing-masking.
+// Metallic blends a dielectric of RefractionIndex (plastic, only the base is colored)
+// with a metal whose reflection is tinted by BaseColor. If AbsorptionIndex is given,
+// metal Fresnel uses the complex IOR like ConductorMaterial instead of Schlick's
+// approximation with F0 = BaseColor. Only AmbientReflectance of the BRDF is used, e.g.
+//     { "_id": "1", "_type": "ggx", "BaseColor": "0.9 0.6 0.2", "Roughness": "0.3", "Metallic": "1" }
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct GGXMaterial {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(flatten)]
+    pub brdf: BRDF,
+    #[serde(rename = "BaseColor", deserialize_with = "deser_vec3")]
+    pub base_color: Vector3,
+    #[serde(rename = "Roughness", deserialize_with = "deser_float")]
+    pub roughness: Float,
+    #[serde(rename = "Metallic", deserialize_with = "deser_float")]
+    pub metallic: Float,
+    #[serde(rename = "RefractionIndex", deserialize_with = "deser_float")]
+    pub refraction_index: Float,
+    #[serde(rename = "AbsorptionIndex", deserialize_with = "deser_float")]
+    pub absorption_index: Float,
+}
+
+impl Default for GGXMaterial {
+    fn default() -> Self {
+        Self {
+            _id: 0,
+            brdf: BRDF::default(),
+            base_color: Vector3::new(0.8, 0.8, 0.8),
+            roughness: 0.5,
+            metallic: 0.0,
+            refraction_index: 1.5,
+            absorption_index: 0.0,
+        }
     }
+}
 
-    fn specular(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
-        // Returns outgoing radiance (see Slides 01_B, p.80)
-        debug_assert!(w_o.is_normalized());
-        debug_assert!(w_i.is_normalized());
-        debug_assert!(n.is_normalized());
+/// Direction sampled from a material, weight is f_r * cos(theta_i) / pdf
+#[derive(Debug, Clone, Copy)]
+pub struct ScatterSample {
+    pub direction: Vector3,
+    pub weight: Vector3,
+    pub pdf: Float,
+}
 
-        let h = (w_i + w_o).normalize(); //(w_i + w_o) / (w_i + w_o).norm();
-        debug_assert!(h.is_normalized());
-        
-        let p = self.phong_exponent;
-        let cos_a = n.dot(h).max(0.0);
-        self.specular_rf * cos_a.powf(p)  
-    }   
+const MIN_ALPHA: Float = 1e-3; // Keeps D finite for perfectly smooth surfaces
+
+fn schlick_weight(cos_theta: Float) -> Float {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+/// Reflection ratio of a dielectric seen from air, Schlick's approximation
+pub fn schlick_fresnel(refraction_index: Float, cos_theta: Float) -> Float {
+    let f0 = ((refraction_index - 1.0) / (refraction_index + 1.0)).powi(2);
+    f0 + (1.0 - f0) * schlick_weight(cos_theta)
+}
+
+/// Cosine weighted direction on the hemisphere around n, pdf is cos(theta) / pi
+pub fn sample_cosine_hemisphere(n: Vector3, u: (Float, Float)) -> Vector3 {
+    let r = u.0.sqrt();
+    let phi = 2.0 * Float::PI * u.1;
+    let (t, b) = n.any_orthonormal_pair();
+    (t * (r * phi.cos()) + b * (r * phi.sin()) + n * (1.0 - u.0).max(0.0).sqrt()).normalize()
+}
+
+impl GGXMaterial {
+
+    fn alpha(&self) -> Float {
+        self.roughness.clamp(0.0, 1.0).powi(2).max(MIN_ALPHA) // Perceptually linear roughness as in Disney/Blender
+    }
+
+    fn distribution(&self, cos_h: Float) -> Float {
+        if cos_h <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha().powi(2);
+        let d = cos_h * cos_h * (a2 - 1.0) + 1.0;
+        a2 / (Float::PI * d * d)
+    }
+
+    fn smith_g1(&self, cos_theta: Float) -> Float {
+        let a2 = self.alpha().powi(2);
+        2.0 * cos_theta / (cos_theta + (a2 + (1.0 - a2) * cos_theta * cos_theta).sqrt())
+    }
+
+    fn dielectric_fresnel(&self, cos_d: Float) -> Float {
+        schlick_fresnel(self.refraction_index, cos_d)
+    }
+
+    /// Fresnel of the specular layer, cos_d is the cosine between w_o and the microfacet normal
+    pub fn fresnel(&self, cos_d: Float) -> Vector3 {
+        let dielectric = Vector3::splat(self.dielectric_fresnel(cos_d));
+        let metal = if self.absorption_index > 0.0 {
+            self.base_color * conductor_fresnel(self.refraction_index, self.absorption_index, cos_d)
+        } else {
+            self.base_color + (Vector3::ONE - self.base_color) * schlick_weight(cos_d)
+        };
+        dielectric.lerp(metal, self.metallic.clamp(0.0, 1.0))
+    }
+
+    /// f_r * cos(theta_i), zero if either direction is below the surface
+    pub fn eval(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
+        let (cos_o, cos_i) = (w_o.dot(n), w_i.dot(n));
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Vector3::ZERO;
+        }
+        let h = (w_o + w_i).normalize();
+        let cos_d = w_o.dot(h).max(0.0);
+        let g = self.smith_g1(cos_o) * self.smith_g1(cos_i);
+        let specular = self.fresnel(cos_d) * (self.distribution(n.dot(h)) * g / (4.0 * cos_i * cos_o));
+        // Light not reflected by the dielectric coating reaches the base, metals have no base
+        let diffuse = self.base_color * ((1.0 - self.metallic.clamp(0.0, 1.0)) * (1.0 - self.dielectric_fresnel(cos_d)) / Float::PI);
+        (diffuse + specular) * cos_i
+    }
+
+    fn specular_probability(&self) -> Float {
+        0.5 * (1.0 + self.metallic.clamp(0.0, 1.0)) // Plastics split samples between lobes, metals only have specular
+    }
+
+    /// Microfacet normal with density D(h) cos(theta_h)
+    fn sample_half_vector(&self, n: Vector3, u: (Float, Float)) -> Vector3 {
+        let a2 = self.alpha().powi(2);
+        let cos_t = ((1.0 - u.0) / (1.0 + (a2 - 1.0) * u.0)).sqrt();
+        let sin_t = (1.0 - cos_t * cos_t).max(0.0).sqrt();
+        let phi = 2.0 * Float::PI * u.1;
+        let (t, b) = n.any_orthonormal_pair();
+        (t * (sin_t * phi.cos()) + b * (sin_t * phi.sin()) + n * cos_t).normalize()
+    }
+
+    fn specular_pdf(&self, w_o: Vector3, h: Vector3, n: Vector3) -> Float {
+        let cos_h = n.dot(h);
+        self.distribution(cos_h) * cos_h / (4.0 * w_o.dot(h).max(Float::EPSILON))
+    }
+
+    /// Density of sample( ) choosing w_i, with respect to solid angle
+    pub fn pdf(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Float {
+        let cos_i = w_i.dot(n);
+        if cos_i <= 0.0 || w_o.dot(n) <= 0.0 {
+            return 0.0;
+        }
+        let p = self.specular_probability();
+        p * self.specular_pdf(w_o, (w_o + w_i).normalize(), n) + (1.0 - p) * cos_i / Float::PI
+    }
+
+    /// Importance sample both lobes, u_lobe picks the lobe and u the direction in it (for path tracing)
+    pub fn sample(&self, w_o: Vector3, n: Vector3, u: (Float, Float), u_lobe: Float) -> Option<ScatterSample> {
+        let w_i = if u_lobe < self.specular_probability() {
+            let h = self.sample_half_vector(n, u);
+            (2.0 * w_o.dot(h) * h - w_o).normalize()
+        } else {
+            sample_cosine_hemisphere(n, u)
+        };
+        let pdf = self.pdf(w_o, w_i, n);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterSample { direction: w_i, weight: self.eval(w_o, w_i, n) / pdf, pdf })
+    }
+
+    /// Importance sample the specular lobe only, i.e. glossy reflection for the recursive ray tracer
+    /// where the diffuse lobe is already covered by direct lighting
+    pub fn sample_specular(&self, w_o: Vector3, n: Vector3, u: (Float, Float)) -> Option<ScatterSample> {
+        let h = self.sample_half_vector(n, u);
+        let cos_d = w_o.dot(h);
+        let w_i = (2.0 * cos_d * h - w_o).normalize();
+        let (cos_o, cos_i, cos_h) = (w_o.dot(n), w_i.dot(n), n.dot(h));
+        if cos_o <= 0.0 || cos_i <= 0.0 || cos_d <= 0.0 {
+            return None;
+        }
+        // f_spec * cos_i / pdf_spec, D cancels out
+        let weight = self.fresnel(cos_d) * (self.smith_g1(cos_o) * self.smith_g1(cos_i) * cos_d / (cos_o * cos_h));
+        Some(ScatterSample { direction: w_i, weight, pdf: self.specular_pdf(w_o, h, n) })
+    }
+}
+
+impl Material for GGXMaterial {
+
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
+    }
+
+    fn shade(&self, w_o: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
+        self.eval(w_o, w_i, n)
+    }
+
+    fn reflect(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
+        // Smooth limit of the specular lobe, rough reflections are sampled with sample_specular( )
+        let n = hit_record.normal;
+        let w_i = ray_in.direction;
+        let w_r = w_i - 2.0 * n * (n.dot(w_i));
+        let ray = ray_in.spawn(hit_record.point + (n * epsilon), w_r);
+        Some((ray, self.fresnel(n.dot(-w_i).max(0.0))))
+    }
+
+    fn refract(&self, _: &Ray, _: &HitRecord, _: Float) -> Option<(Ray, Vector3)> {
+        None // Opaque, transmission of the dielectric goes to the diffuse base
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// 
+/// VOLUME (PARTICIPATING MEDIUM)
+/// 
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct VolumeMaterial {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(flatten)]
+    pub brdf: BRDF,
+    #[serde(flatten)]
+    pub volume: HomogeneousVolume,
+}
+
+impl Material for VolumeMaterial {
+
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
+    }
+
+    fn shade(&self, _: Vector3, _: Vector3, _: Vector3) -> Vector3 {
+        Vector3::ZERO // Boundary is not a surface, medium inside scatters instead
+    }
+
+    fn reflect(&self, _: &Ray, _: &HitRecord, _: Float) -> Option<(Ray, Vector3)> {
+        None
+    }
+
+    fn refract(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
+        // Always passes through unbent, entering or leaving the medium
+        let media = if hit_record.is_front_face {
+            // Volumes sharing the material may have different grids, the one of the hit volume fills the medium
+            ray_in.media.entering(Medium { grid: hit_record.grid, ..self.volume.medium(hit_record.material) })
+        } else {
+            ray_in.media.exiting(hit_record.material)
+        };
+        let ray = ray_in.spawn_into(hit_record.point - (hit_record.normal * epsilon), ray_in.direction, media);
+        Some((ray, Vector3::ONE))
+    }
+}
+
+
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+/// 
+/// SUBSURFACE
+/// 
+////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+// Light enters the surface diffusely, scatters in the medium under it (random walk, see
+// renderer::subsurface_walk( )) and leaves it diffusely somewhere else. A smooth dielectric
+// coat reflects the rest. Works for closed meshes and spheres, walks leaving open surfaces are lost.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SubsurfaceMaterial {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(flatten)]
+    pub brdf: BRDF,
+    #[serde(rename = "Albedo", deserialize_with = "deser_vec3")]
+    pub albedo: Vector3, // Overall color, as DiffuseReflectance would be
+    #[serde(rename = "MeanFreePath", deserialize_with = "deser_vec3")]
+    pub mean_free_path: Vector3, // How far light travels under the surface per channel, in scene units
+    #[serde(rename = "RefractionIndex", deserialize_with = "deser_float")]
+    pub refraction_index: Float,
+    #[serde(rename = "Anisotropy", deserialize_with = "deser_float")]
+    pub anisotropy: Float,
+}
+
+impl Default for SubsurfaceMaterial {
+    fn default() -> Self {
+        Self {
+            _id: 0,
+            brdf: BRDF::default(),
+            albedo: Vector3::new(0.8, 0.8, 0.8),
+            mean_free_path: Vector3::new(0.1, 0.1, 0.1),
+            refraction_index: 1.4,
+            anisotropy: 0.0,
+        }
+    }
+}
+
+impl SubsurfaceMaterial {
+
+    /// Medium under the surface. Albedo and mean free path are mapped to single scattering
+    /// albedo and extinction so that the surface looks like it has the given albedo overall,
+    /// fits of Chiang et al. 2016 (Practical and Controllable Subsurface Scattering for Production Path Tracing)
+    pub fn medium(&self, material: usize) -> Medium {
+        let albedo = self.albedo.clamp(Vector3::ZERO, Vector3::splat(0.999));
+        let single_scattering = Vector3::ONE - (albedo * (Vector3::splat(-5.09406) + albedo * (Vector3::splat(2.61188) - albedo * 4.31805))).exp();
+        let s = Vector3::splat(1.9) - albedo + 3.5 * (albedo - 0.8).powf(2.0);
+        let extinction = Vector3::ONE / (self.mean_free_path * s).max(Vector3::splat(1e-6));
+        Medium {
+            material,
+            refraction_index: self.refraction_index,
+            absorption_coeff: extinction * (Vector3::ONE - single_scattering),
+            scattering_coeff: extinction * single_scattering,
+            anisotropy: self.anisotropy,
+            grid: None,
+        }
+    }
+
+    pub fn fresnel(&self, cos_theta: Float) -> Float {
+        schlick_fresnel(self.refraction_index, cos_theta)
+    }
+}
+
+impl Material for SubsurfaceMaterial {
+
+    fn brdf(&self) -> &BRDF {
+        &self.brdf
+    }
+
+    fn shade(&self, _: Vector3, w_i: Vector3, n: Vector3) -> Vector3 {
+        // Where the walk leaves the surface, diffuse as DiffuseReflectance of 1 (albedo is in the walk)
+        Vector3::splat(w_i.dot(n).max(0.0))
+    }
+
+    fn reflect(&self, ray_in: &Ray, hit_record: &HitRecord, epsilon: Float) -> Option<(Ray, Vector3)> {
+        // Coat
+        let n = hit_record.normal;
+        let w_i = ray_in.direction;
+        let w_r = w_i - 2.0 * n * (n.dot(w_i));
+        let ray = ray_in.spawn(hit_record.point + (n * epsilon), w_r);
+        Some((ray, Vector3::splat(self.fresnel(n.dot(-w_i).max(0.0)))))
+    }
+
+    fn refract(&self, _: &Ray, _: &HitRecord, _: Float) -> Option<(Ray, Vector3)> {
+        None // Transmitted light enters diffusely, see renderer::subsurface_walk( )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    fn ggx(roughness: Float, metallic: Float) -> GGXMaterial {
+        GGXMaterial { base_color: Vector3::ONE, roughness, metallic, ..Default::default() }
+    }
+
+    #[test]
+    fn test_ggx_sampling_matches_eval_and_pdf() {
+        // Monte Carlo estimate of reflected energy (white furnace) must not exceed 1 (up to noise)
+        // and importance sampling must agree with hemisphere sampling of eval( )
+        let n = Vector3::Z;
+        let w_o = Vector3::new(0.5, 0.0, 1.0).normalize();
+        for mat in [ggx(0.3, 0.0), ggx(0.5, 1.0), ggx(0.8, 0.5)] {
+            let mut sampler = Sampler::new(7, 0);
+            let num_samples = 200_000;
+            let (mut importance, mut uniform) = (0.0, 0.0);
+            for _ in 0..num_samples {
+                if let Some(s) = mat.sample(w_o, n, sampler.next_2d(), sampler.next_float()) {
+                    assert!((s.pdf - mat.pdf(w_o, s.direction, n)).abs() < 1e-4 * s.pdf.max(1.0));
+                    importance += s.weight.x;
+                }
+                let w_i = sample_cosine_hemisphere(n, sampler.next_2d());
+                uniform += mat.eval(w_o, w_i, n).x / (w_i.dot(n) / Float::PI);
+            }
+            let (importance, uniform) = (importance / num_samples as Float, uniform / num_samples as Float);
+            assert!(importance < 1.01 && importance > 0.5, "{:?} reflects {}", mat, importance);
+            assert!((importance - uniform).abs() < 0.02, "{} vs {}", importance, uniform);
+        }
+    }
+
+    #[test]
+    fn test_ggx_specular_sample_weight() {
+        // Weight of the specular lobe is its part of eval( ) divided by its pdf
+        let n = Vector3::Z;
+        let w_o = Vector3::new(-0.3, 0.2, 1.0).normalize();
+        let mat = ggx(0.4, 1.0);
+        let mut sampler = Sampler::new(3, 1);
+        for _ in 0..100 {
+            if let Some(s) = mat.sample_specular(w_o, n, sampler.next_2d()) {
+                let expected = mat.eval(w_o, s.direction, n) / s.pdf;
+                assert!((s.weight - expected).abs().max_element() < 1e-4 * expected.max_element().max(1.0), "{} vs {}", s.weight, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_subsurface_albedo_is_reflectance() {
+        // Random walks entering a half-space (z < 0) diffusely leave it with about albedo of the energy
+        use crate::volume::{sample_free_flight, sample_henyey_greenstein, FreeFlight};
+        let mut sampler = Sampler::new(11, 3);
+        for albedo in [0.2, 0.5, 0.8] {
+            let mat = SubsurfaceMaterial { albedo: Vector3::splat(albedo), ..Default::default() };
+            let medium = mat.medium(1);
+            let num_walks = 20_000;
+            let mut reflected = 0.0;
+            for _ in 0..num_walks {
+                let (mut point, mut direction) = (Vector3::ZERO, sample_cosine_hemisphere(-Vector3::Z, sampler.next_2d()));
+                let mut weight = 1.0;
+                for _ in 0..1000 {
+                    let t_exit = if direction.z > 0.0 { -point.z / direction.z } else { Float::INFINITY };
+                    match sample_free_flight(&medium, t_exit, sampler.next_float(), Vector3::splat(weight)) {
+                        FreeFlight::Scatter { t, weight: w } => {
+                            weight *= w.x;
+                            point += direction * t;
+                            direction = sample_henyey_greenstein(direction, 0.0, sampler.next_2d());
+                        }
+                        FreeFlight::Pass { weight: w } => {
+                            reflected += weight * w.x;
+                            break;
+                        }
+                        FreeFlight::Absorbed => break,
+                    }
+                }
+            }
+            let reflectance = reflected / num_walks as Float;
+            assert!((reflectance - albedo).abs() < 0.03, "albedo {} reflects {}", albedo, reflectance);
+        }
+    }
 }
@@ -2,10 +2,14 @@
 
     Declare numeric types used throughout this repo.
 
-    WARNING: If you like to use f32 instead of f64
-    during computations, you need to change both of these:
-    pub type Float = f32;
-    pub type Vector3 = Vec3;
+    Computations are done in f64 by default, build with
+    `--features f32` to use single precision instead e.g.
+        cargo run --release --features f32 -- <scene>.json
+    to save memory (and time) on huge meshes.
+
+    Code should not assume a precision: use Float and its
+    associated constants (Float::EPSILON, FloatConst::INF),
+    and the tolerances declared below instead of literals.
 
     TODO: maybe provide Vector3 struct to avoid this
     explicit coupling rather than depending on bevy_math.
@@ -14,11 +18,27 @@
     @author: Bartu
 */
 
-use bevy_math::{DMat3, DVec3};
 pub type Int = i32;
-pub type Float = f64; // WARNING: If you want to change it to f32, don't forget to update Vector3 and Matrix3 types
-pub type Vector3 = DVec3; 
-pub type Matrix3 = DMat3;
+
+#[cfg(not(feature = "f32"))]
+mod precision {
+    pub type Float = f64;
+    pub type Vector3 = bevy_math::DVec3;
+    pub type Matrix3 = bevy_math::DMat3;
+    pub const APPROX_ZERO: Float = 1e-8;
+    pub const DEFAULT_EPSILON: Float = 1e-10; // Used when a scene gives an epsilon of 0
+}
+
+#[cfg(feature = "f32")]
+mod precision {
+    pub type Float = f32;
+    pub type Vector3 = bevy_math::Vec3;
+    pub type Matrix3 = bevy_math::Mat3;
+    pub const APPROX_ZERO: Float = 1e-5;
+    pub const DEFAULT_EPSILON: Float = 1e-4;
+}
+
+pub use precision::*;
 
 //#[derive(Clone, Copy, Debug, Default)]
 //pub struct Vector3(pub DVec3); // To declare a type and use impl traits on this type
@@ -26,5 +46,5 @@ pub type Matrix3 = DMat3;
 // this requires trait bounds to be satisfied, so it breaks most of the code atm
 
 pub fn approx_zero(x: Float) -> bool {
-    x.abs() < 1e-8
+    x.abs() < APPROX_ZERO
 }
@@ -0,0 +1,76 @@
+/*
+
+    Report progress of long renders through tracing, e.g.
+        INFO  cornellbox.png: 45.0% (90/200 tiles), elapsed 12.3s, ETA 15.0s
+
+    Progress is shared between rayon threads, so counting is
+    atomic and reports are throttled to at most one per
+    REPORT_INTERVAL (except the final one).
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+pub(crate) const REPORT_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub struct Progress {
+    name: String,
+    unit: &'static str,
+    total: usize,
+    done: AtomicUsize,
+    start: Instant,
+    last_report: Mutex<Instant>,
+}
+
+impl Progress {
+    pub fn new(name: &str, unit: &'static str, total: usize) -> Self {
+        let now = Instant::now();
+        Self {
+            name: name.to_string(),
+            unit,
+            total,
+            done: AtomicUsize::new(0),
+            start: now,
+            last_report: Mutex::new(now),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    /// Estimated remaining time assuming the rest goes as fast as what is done so far
+    pub fn eta(&self, done: usize) -> Option<Duration> {
+        if done == 0 {
+            return None;
+        }
+        let per_unit = self.elapsed().as_secs_f64() / done as f64;
+        Some(Duration::from_secs_f64(per_unit * self.total.saturating_sub(done) as f64))
+    }
+
+    /// Mark one more unit as done and report if it is time to
+    pub fn tick(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        let finished = done == self.total;
+        {
+            let mut last_report = self.last_report.lock().unwrap();
+            if !finished && last_report.elapsed() < REPORT_INTERVAL {
+                return;
+            }
+            *last_report = Instant::now();
+        }
+        self.report(done);
+    }
+
+    fn report(&self, done: usize) {
+        let percent = 100.0 * done as f64 / self.total.max(1) as f64;
+        let eta = self.eta(done).map(|eta| format!("{:.1?}", eta)).unwrap_or_else(|| String::from("?"));
+        info!("{}: {:.1}% ({}/{} {}), elapsed {:.1?}, ETA {}", self.name, percent, done, self.total, self.unit, self.elapsed(), eta);
+    }
+}
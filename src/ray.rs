@@ -1,19 +1,19 @@
 
 
 
-use bevy_math::curve::derivatives;
+use std::sync::Arc;
+
 use bevy_math::NormedVectorSpace;
 
-use crate::dataforms::VertexData;
-use crate::material;
 use crate::numeric::{Vector3, Float};
-use crate::interval::{Interval};
 
 
 #[derive(Debug)]
 pub struct Ray {
     pub origin: Vector3,
     pub direction: Vector3,
+    pub media: MediumStack, // Dielectrics the ray is inside of, empty if it travels in air
+    pub wavelength: Option<Float>, // In nm, only in spectral mode (see spectrum.rs)
 }
 
 impl Ray {
@@ -21,6 +21,23 @@ impl Ray {
         Self {
             origin,
             direction,
+            media: MediumStack::default(),
+            wavelength: None,
+        }
+    }
+
+    /// Secondary ray of this one, travelling in the same media with the same wavelength
+    pub fn spawn(&self, origin: Vector3, direction: Vector3) -> Self {
+        self.spawn_into(origin, direction, self.media.clone())
+    }
+
+    /// Secondary ray crossing into other media, e.g. refracted ones
+    pub fn spawn_into(&self, origin: Vector3, direction: Vector3, media: MediumStack) -> Self {
+        Self {
+            origin,
+            direction,
+            media,
+            wavelength: self.wavelength,
         }
     }
 
@@ -47,6 +64,99 @@ impl Ray {
 }
 
 
+// Medium inside a dielectric or a volume, e.g. water, glass or smoke. A ray entering
+// one pushes its medium and leaving pops it, so that nested dielectrics (ice in a drink,
+// glass filled with water) know the media on both sides of a surface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Medium {
+    pub material: usize, // Id of the material the medium belongs to, 0 for air (or fog of the scene)
+    pub refraction_index: Float,
+    pub absorption_coeff: Vector3,
+    pub scattering_coeff: Vector3, // Zero for clear media, see volume.rs otherwise
+    pub anisotropy: Float, // g of Henyey-Greenstein phase function
+    pub grid: Option<usize>, // Density grid of the voxel volume it fills (see Scene::density_grid( )), None if homogeneous
+}
+
+impl Medium {
+    pub const AIR: Self = Self {
+        material: 0,
+        refraction_index: 1.00029, // Slides 02, p.22
+        absorption_coeff: Vector3::ZERO,
+        scattering_coeff: Vector3::ZERO,
+        anisotropy: 0.0,
+        grid: None,
+    };
+
+    pub fn beers_law_attenuation(&self, distance: Float) -> Vector3 {
+        // Slides 02, p.27, only e^(-Cx) part
+        // where C is the extinction (absorption and out-scattering) coefficient
+        // and x the distance travelled in the medium
+        (- self.extinction_coeff() * distance).exp()
+    }
+
+    pub fn extinction_coeff(&self) -> Vector3 {
+        self.absorption_coeff + self.scattering_coeff
+    }
+
+    pub fn scatters(&self) -> bool {
+        self.scattering_coeff.max_element() > 0.0
+    }
+}
+
+// Every secondary ray copies the stack of its parent, so it is shared rather
+// than cloned, and only entering or exiting a medium allocates a new one
+#[derive(Debug, Clone, Default)]
+pub struct MediumStack {
+    media: Option<Arc<[Medium]>>, // Innermost last, None in air
+}
+
+impl MediumStack {
+    fn from_vec(media: Vec<Medium>) -> Self {
+        Self { media: (!media.is_empty()).then(|| media.into()) }
+    }
+
+    fn as_slice(&self) -> &[Medium] {
+        self.media.as_deref().unwrap_or(&[])
+    }
+
+    /// Medium the ray travels in
+    pub fn current(&self) -> Medium {
+        self.as_slice().last().copied().unwrap_or(Medium::AIR)
+    }
+
+    /// Medium on the other side of a surface of given material when leaving it
+    pub fn outside_of(&self, material: usize) -> Medium {
+        let mut found = false;
+        for medium in self.as_slice().iter().rev() {
+            if !found && medium.material == material {
+                found = true;
+                continue;
+            }
+            return *medium;
+        }
+        Medium::AIR
+    }
+
+    pub fn entering(&self, medium: Medium) -> Self {
+        let mut media = self.as_slice().to_vec();
+        media.push(medium);
+        Self::from_vec(media)
+    }
+
+    pub fn exiting(&self, material: usize) -> Self {
+        // Rays may miss an entry due to epsilon offsets, then the stack is kept as it is
+        match self.as_slice().iter().rposition(|m| m.material == material) {
+            Some(i) => {
+                let mut media = self.as_slice().to_vec();
+                media.remove(i);
+                Self::from_vec(media)
+            }
+            None => self.clone(),
+        }
+    }
+}
+
+
 // Question: Couldn't we just use point to see which point is closer?
 // but this is relative to camera, and t is a single scalar that encaptures
 // which HitRecord is closer to the - actually not to camera (for primary rays only it is camera)
@@ -60,6 +170,8 @@ pub struct HitRecord {
     pub ray_t: Float,  // To check which HitRecord has smaller t 
     pub material: usize, // TODO: Should we hold the index of material or actually Option<Rc<dyn Material>> as in here https://the-ray-tracing-road-to-rust.vercel.app/9-metal? Or Arc instead of Rc if we use rayon in future.
     pub is_front_face: bool,
+    pub uv: Option<[Float; 2]>, // Surface coordinates in [0, 1], for shapes having a parametrization
+    pub grid: Option<usize>, // Density grid of the medium behind the surface, for boundaries of voxel volumes
 }
 
 impl HitRecord {
@@ -70,8 +182,20 @@ impl HitRecord {
             ray_t,
             material,
             is_front_face,
+            uv: None,
+            grid: None,
         }
     }
+
+    pub fn with_uv(mut self, uv: [Float; 2]) -> Self {
+        self.uv = Some(uv);
+        self
+    }
+
+    pub fn with_grid(mut self, grid: usize) -> Self {
+        self.grid = Some(grid);
+        self
+    }
     //pub fn new_from(ray: &Ray, n: Vector3, t: Float, material: usize) -> Self {
     //    let is_front_face = ray.is_front_face(n);
     //    Self {
@@ -83,3 +207,42 @@ impl HitRecord {
     //    }
     //}
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn medium(material: usize, refraction_index: Float) -> Medium {
+        Medium { material, refraction_index, ..Medium::AIR }
+    }
+
+    #[test]
+    fn test_nested_media() {
+        // Ice (3) in water (2) in a glass (1)
+        let (glass, water, ice) = (medium(1, 1.5), medium(2, 1.33), medium(3, 1.31));
+        let air = MediumStack::default();
+        assert_eq!(air.current(), Medium::AIR);
+
+        let in_ice = air.entering(glass).entering(water).entering(ice);
+        assert_eq!(in_ice.current(), ice);
+        assert_eq!(in_ice.outside_of(3), water);
+
+        // Leaving the glass first (e.g. overlapping objects) keeps the others
+        let stack = in_ice.exiting(1);
+        assert_eq!(stack.current(), ice);
+        assert_eq!(stack.exiting(3).current(), water);
+        assert_eq!(stack.exiting(3).exiting(2).current(), Medium::AIR);
+
+        // Leaving a medium that was never entered is ignored
+        assert_eq!(air.exiting(2).current(), Medium::AIR);
+        assert_eq!(air.outside_of(2), Medium::AIR);
+
+        // Spawned rays share the stack of their parent
+        let ray = Ray::new(Vector3::ZERO, Vector3::Z);
+        let ray = ray.spawn_into(Vector3::ZERO, Vector3::Z, stack.clone());
+        let child = ray.spawn(Vector3::ZERO, Vector3::X);
+        assert!(Arc::ptr_eq(ray.media.media.as_ref().unwrap(), child.media.media.as_ref().unwrap()));
+        assert!(air.media.is_none() && stack.exiting(3).exiting(2).media.is_none());
+    }
+}
@@ -11,17 +11,24 @@
     @author: Bartu
 */
 
-use std::rc::Rc;
-use std::sync::Arc;
 use rayon::prelude::*;
-use std::io::{self, Write};
-use bevy_math::{NormedVectorSpace, VectorSpace};
-use tracing::span::Record;
-use tracing::{debug, info, warn, error};
+use bevy_math::NormedVectorSpace;
+use tracing::{debug, info, warn};
+
+use crate::material::{sample_cosine_hemisphere, Material, MaterialKind, SubsurfaceMaterial};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
 use crate::camera::Camera;
-use crate::material::{HeapAllocMaterial};
-use crate::ray::{HitRecord, Ray};
+use crate::checkpoint::{Checkpoint, CheckpointOrigin};
+use crate::film::Film;
+use crate::error::{TracerError, TracerResult};
+use crate::progress::{Progress, REPORT_INTERVAL};
+use crate::sampler::Sampler;
+use crate::spectrum::{sample_wavelength, spectral_weight};
+use crate::volume::{henyey_greenstein, sample_free_flight, sample_henyey_greenstein, FreeFlight};
+use crate::tile::{make_tiles, Tile, TileOrder};
+use crate::ray::{HitRecord, Medium, Ray};
 use crate::scene::{PointLight, Scene};
 use crate::numeric::{Float, Vector3};
 use crate::image::{ImageData};
@@ -35,7 +42,7 @@ pub fn closest_hit(ray: &Ray, t_interval: &Interval, shapes: &ShapeList, vertex_
     let mut rec = None;
     let mut t_min = FloatConst::INF;
     for shape in shapes.iter() { // TODO: later we'll use acceleration structures instead of checking *all* objects like this
-       if let Some(hit_record) = shape.intersects_with(ray, &t_interval, vertex_cache){
+       if let Some(hit_record) = shape.intersects_with(ray, t_interval, vertex_cache){
            // Update if new hit is closer 
            if t_min > hit_record.ray_t { 
                t_min = hit_record.ray_t;
@@ -49,14 +56,15 @@ pub fn closest_hit(ray: &Ray, t_interval: &Interval, shapes: &ShapeList, vertex_
 pub fn any_hit(ray: &Ray, t_interval: &Interval, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts) -> bool {
     // Check if ray intersects with any shape in the scene
     for shape in shapes.iter() { // TODO: later we'll use acceleration structures instead of checking *all* objects like this
-       if let Some(_) = shape.intersects_with(ray, &t_interval, vertex_cache){
+       if shape.intersects_with(ray, t_interval, vertex_cache).is_some(){
            return true;
        }
    }
    false
 }
 
-pub fn get_shadow_ray(point_light: &PointLight, hit_record: &HitRecord, epsilon: Float) -> (Ray, Interval) { // TODO: Should we box hitrecord here?
+// Shadow ray starts in the media of ray_in, i.e. on the side of the surface the ray came from
+pub fn get_shadow_ray(point_light: &PointLight, hit_record: &HitRecord, ray_in: &Ray, epsilon: Float) -> (Ray, Interval) { // TODO: Should we box hitrecord here?
     
     debug_assert!(hit_record.normal.is_normalized());
     let ray_origin = hit_record.point + (hit_record.normal * epsilon);
@@ -65,18 +73,59 @@ pub fn get_shadow_ray(point_light: &PointLight, hit_record: &HitRecord, epsilon:
     let distance = distance_squared.sqrt();
     let dir = distance_vec / distance;
     debug_assert!(dir.is_normalized());
-    let shadow_ray = Ray::new(ray_origin, dir);
+    let shadow_ray = ray_in.spawn(ray_origin, dir);
     let interval = Interval::new(0.0, distance); 
     (shadow_ray, interval)
 }
 
+fn material_of<'a>(scene: &'a Scene, hit_record: &HitRecord) -> Option<&'a MaterialKind> {
+    hit_record.material
+        .checked_sub(1) // Material ids in JSON start from 1
+        .and_then(|i| scene.materials.materials.get(i))
+}
+
+// Transmittance of the medium the ray is in from its origin to distance
+fn medium_transmittance(scene: &Scene, ray: &Ray, distance: Float, sampler: &mut Sampler) -> Vector3 {
+    let medium = ray.media.current();
+    match scene.density_grid(&medium) {
+        Some(grid) => grid.transmittance(&medium, ray, distance, sampler),
+        None => medium.beers_law_attenuation(distance),
+    }
+}
+
+/// Fraction of light travelling back along the shadow ray from interval.max to its origin.
+/// Surfaces block it, except boundaries of volumes which it passes through attenuated
+/// by the media in between (nothing in air, so it is either zero or one without volumes).
+pub fn transmittance(scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, shadow_ray: &Ray, interval: &Interval, sampler: &mut Sampler) -> Vector3 {
+    if !any_hit(shadow_ray, interval, shapes, vertex_cache) {
+        return medium_transmittance(scene, shadow_ray, interval.max, sampler);
+    }
+    // Something is in between, follow the ray through volume boundaries until it is blocked or reaches the end
+    let mut ray = shadow_ray.spawn(shadow_ray.origin, shadow_ray.direction);
+    let mut remaining = interval.max;
+    let mut attenuation = Vector3::ONE;
+    while let Some(hit_record) = closest_hit(&ray, &Interval::new(interval.min, remaining), shapes, vertex_cache) {
+        let Some(MaterialKind::Volume(mat)) = material_of(scene, &hit_record) else {
+            return Vector3::ZERO;
+        };
+        let Some((next_ray, _)) = mat.refract(&ray, &hit_record, scene.intersection_test_epsilon) else {
+            return Vector3::ZERO;
+        };
+        attenuation *= medium_transmittance(scene, &ray, hit_record.ray_t, sampler);
+        remaining -= (next_ray.origin - ray.origin).norm();
+        ray = next_ray;
+    }
+    attenuation * medium_transmittance(scene, &ray, remaining.max(0.0), sampler)
+}
+
 // TODO: Wait why there is both scene and shapes where scene already should contain shapes?
-pub fn shade_diffuse(scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, hit_record: &HitRecord, ray_in: &Ray, mat: &HeapAllocMaterial) -> Vector3 {
+pub fn shade_diffuse(scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, hit_record: &HitRecord, ray_in: &Ray, mat: &impl Material, sampler: &mut Sampler) -> Vector3 {
     let mut color = Vector3::ZERO;
     for point_light in scene.lights.point_lights.all() {
             
-            let (shadow_ray, interval) = get_shadow_ray(&point_light, hit_record, scene.shadow_ray_epsilon);
-            if !any_hit(&shadow_ray, &interval, shapes, vertex_cache) {
+            let (shadow_ray, interval) = get_shadow_ray(&point_light, hit_record, ray_in, scene.shadow_ray_epsilon);
+            let visibility = transmittance(scene, shapes, vertex_cache, &shadow_ray, &interval, sampler);
+            if visibility.max_element() > 0.0 {
                 // TODO: We can implement attenuate( ) for diffuse by taking 
                 // denominator part out of irradiance and use it in attenuate( )
                 // that way get_shadow_ray( ) can return ray_t: Float, instead of interval
@@ -84,97 +133,631 @@ pub fn shade_diffuse(scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAlloc
                 let n = hit_record.normal;
                 let w_i = shadow_ray.direction;
                 let w_o = -ray_in.direction;
-                color += mat.diffuse(w_i, n) * irradiance;
-                color += mat.specular(w_o, w_i, n) * irradiance; 
+                color += mat.shade(w_o, w_i, n) * irradiance * visibility;
+            }
+    }
+
+    // Area lights, a single point is sampled per call and pixel samples average them out
+    let area_lights = &scene.lights.area_lights;
+    if !area_lights.is_empty() && let Some(light) = area_lights.sample(sampler.next_float(), sampler.next_2d()) {
+        let n = hit_record.normal;
+        let shadow_ray_origin = hit_record.point + (n * scene.shadow_ray_epsilon);
+        let distance_vec = light.point - shadow_ray_origin;
+        let distance_squared = distance_vec.norm_squared();
+        let distance = distance_squared.sqrt();
+        let w_i = distance_vec / distance;
+        let cos_light = light.normal.dot(w_i).abs(); // Emitters are two-sided
+        if distance > scene.shadow_ray_epsilon && w_i.dot(n) > 0.0 {
+            // Stop before the emitter, otherwise it would shadow itself
+            let shadow_ray = ray_in.spawn(shadow_ray_origin, w_i);
+            let interval = Interval::new(0.0, distance - scene.shadow_ray_epsilon);
+            let visibility = transmittance(scene, shapes, vertex_cache, &shadow_ray, &interval, sampler);
+            if visibility.max_element() > 0.0 {
+                // Convert pdf of the point from area to solid angle: pdf_area * d^2 / cos_light
+                let w_o = -ray_in.direction;
+                color += mat.shade(w_o, w_i, n) * light.radiance * visibility * (cos_light / (distance_squared * light.pdf_area));
             }
+        }
     }
     color
 }
 
-pub fn get_color(ray_in: &Ray, scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, depth: usize) -> Vector3 { // TODO: add depth & check depth > scene.max_recursion_depth
+/// Radiance scattered towards the origin of ray_in at a point in its medium: direct light
+/// through the phase function, plus light from a direction sampled from the phase function
+pub fn in_scattered(scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, ray_in: &Ray, point: Vector3, depth: usize, sampler: &mut Sampler) -> TracerResult<Vector3> {
+    let mut radiance = Vector3::ZERO;
+    let g = ray_in.media.current().anisotropy;
+    for point_light in scene.lights.point_lights.all() {
+        let distance_vec = point_light.position - point;
+        let distance_squared = distance_vec.norm_squared();
+        let distance = distance_squared.sqrt();
+        let w_i = distance_vec / distance;
+        let shadow_ray = ray_in.spawn(point, w_i);
+        let visibility = transmittance(scene, shapes, vertex_cache, &shadow_ray, &Interval::new(0.0, distance), sampler);
+        radiance += henyey_greenstein(ray_in.direction.dot(w_i), g) * point_light.rgb_intensity * visibility / distance_squared;
+    }
+
+    let area_lights = &scene.lights.area_lights;
+    if !area_lights.is_empty() && let Some(light) = area_lights.sample(sampler.next_float(), sampler.next_2d()) {
+        let distance_vec = light.point - point;
+        let distance_squared = distance_vec.norm_squared();
+        let distance = distance_squared.sqrt();
+        let w_i = distance_vec / distance;
+        let cos_light = light.normal.dot(w_i).abs();
+        if distance > scene.shadow_ray_epsilon {
+            let shadow_ray = ray_in.spawn(point, w_i);
+            let visibility = transmittance(scene, shapes, vertex_cache, &shadow_ray, &Interval::new(0.0, distance - scene.shadow_ray_epsilon), sampler);
+            radiance += henyey_greenstein(ray_in.direction.dot(w_i), g) * light.radiance * visibility * (cos_light / (distance_squared * light.pdf_area));
+        }
+    }
+
+    // Multiple scattering, weight of the sampled direction is phase / pdf = 1.
+    // Background is not added at the last bounce, the medium would glow otherwise.
+    if depth + 1 < scene.max_recursion_depth {
+        let w_i = sample_henyey_greenstein(ray_in.direction, g, sampler.next_2d());
+        // Emitters are part of direct light above
+        radiance += get_color(&ray_in.spawn(point, w_i), scene, shapes, vertex_cache, depth + 1, sampler, false)?;
+    }
+    Ok(radiance)
+}
+
+/// Radiance leaving the surface of a subsurface material where ray_in hit it, by a random walk
+/// in the medium under the surface from the hit point until the walk leaves the surface again.
+/// Light enters diffusely and the exit point is shaded with direct light (see SubsurfaceMaterial).
+pub fn subsurface_walk(scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, ray_in: &Ray, hit_record: &HitRecord, mat: &SubsurfaceMaterial, sampler: &mut Sampler) -> Vector3 {
+    const MAX_BOUNCES: usize = 256; // Walks of (nearly) white media can be long, rest is lost
+    let medium = mat.medium(hit_record.material);
+    let epsilon = scene.intersection_test_epsilon;
+    let t_interval = Interval::positive(epsilon);
+    let n = hit_record.normal;
+    // Start the walk as far under the surface as shadow rays start above it, a start closer than
+    // that can hit the entry surface again in f32 and the walk would end right away
+    let mut ray = ray_in.spawn(hit_record.point - (n * scene.shadow_ray_epsilon), sample_cosine_hemisphere(-n, sampler.next_2d()));
+    let mut weight = Vector3::ONE;
+    for _ in 0..MAX_BOUNCES {
+        // Walk leaves through the back face of a surface, a front face is an object inside
+        let Some(exit) = closest_hit(&ray, &t_interval, shapes, vertex_cache).filter(|exit| !exit.is_front_face) else {
+            return Vector3::ZERO;
+        };
+        match sample_free_flight(&medium, ray.distance_at(exit.ray_t), sampler.next_float(), weight) {
+            FreeFlight::Scatter { t, weight: w } => {
+                weight *= w;
+                let direction = sample_henyey_greenstein(ray.direction, medium.anisotropy, sampler.next_2d());
+                ray = ray.spawn(ray.at(t), direction);
+            }
+            FreeFlight::Pass { weight: w } => {
+                // Normal of the exit faces the inside since the walk hit it from there
+                let exit_record = HitRecord::new(exit.point, -exit.normal, exit.ray_t, exit.material, true);
+                return weight * w * shade_diffuse(scene, shapes, vertex_cache, &exit_record, ray_in, mat, sampler);
+            }
+            FreeFlight::Absorbed => return Vector3::ZERO,
+        }
+    }
+    Vector3::ZERO
+}
+
+// with_emission is false for rays whose emitted radiance is already counted by shade_diffuse( ) sampling area lights,
+// emission of shapes that are not sampled (see AreaLights::build( )) is added regardless
+pub fn get_color(ray_in: &Ray, scene: &Scene, shapes: &ShapeList, vertex_cache: &HeapAllocatedVerts, depth: usize, sampler: &mut Sampler, with_emission: bool) -> TracerResult<Vector3> { // TODO: add depth & check depth > scene.max_recursion_depth
    // TODO: Shouldn't we box the scene or even Rc<scene> here? otherwise it lives on the stack
    // and it's a huge struct, isn't it?
    if depth >= scene.max_recursion_depth {
-        return scene.background_color;
+        return Ok(scene.background_color);
    }
    
    let t_interval = Interval::positive(scene.intersection_test_epsilon);
-   if let Some(hit_record) = closest_hit(ray_in, &t_interval, shapes, vertex_cache) {
+   let hit = closest_hit(ray_in, &t_interval, shapes, vertex_cache);
+
+   // Ray may scatter in the medium before reaching the surface (see volume.rs),
+   // otherwise it is attenuated until the hit
+   let medium = ray_in.media.current();
+   let grid = scene.density_grid(&medium);
+   let mut attenuation = Vector3::ONE;
+   if medium.scatters() || grid.is_some() {
+        let t_max = hit.as_ref().map_or(FloatConst::INF, |hit_record| ray_in.distance_at(hit_record.ray_t));
+        let flight = match grid {
+            Some(grid) => grid.sample_free_flight(&medium, ray_in, t_max, sampler),
+            None => sample_free_flight(&medium, t_max, sampler.next_float(), Vector3::ONE),
+        };
+        match flight {
+            FreeFlight::Scatter { t, weight } => {
+                let point = ray_in.at(t);
+                return Ok(weight * in_scattered(scene, shapes, vertex_cache, ray_in, point, depth, sampler)?);
+            }
+            FreeFlight::Pass { weight } => attenuation = weight,
+            FreeFlight::Absorbed => return Ok(Vector3::ZERO),
+        }
+   }
+   else if medium != Medium::AIR && let Some(hit_record) = &hit {
+        // Absorption of the medium the ray travelled in until the hit (Beer's law), nothing in air
+        attenuation = medium.beers_law_attenuation(ray_in.distance_at(hit_record.ray_t));
+   }
+
+   if let Some(hit_record) = hit {
         
-        let mat: &HeapAllocMaterial = &scene.materials.materials[hit_record.material - 1];
+        let mat: &MaterialKind = material_of(scene, &hit_record)
+            .ok_or_else(|| TracerError::Render(format!("hit an object with material {} which does not exist", hit_record.material)))?;
         let mut color = mat.ambient() * scene.lights.ambient_light;
-        let mat_type = mat.get_type();
+        if with_emission || !scene.lights.area_lights.is_sampled(hit_record.material) {
+            color += mat.emission();
+        }
         let epsilon = scene.intersection_test_epsilon; // TODO: Is this the correct epsilon? Seems like yes, visually checked with other epsilon vs. given output image 
-        color += match mat_type{ // WARNING: Expecting lowercase material
-            "diffuse" => {
-                shade_diffuse(scene, shapes, vertex_cache, &hit_record, &ray_in, mat)
+        color += match mat {
+            MaterialKind::Diffuse(mat) => {
+                shade_diffuse(scene, shapes, vertex_cache, &hit_record, ray_in, mat, sampler)
             },
-            "mirror" => {
+            MaterialKind::Mirror(mat) => {
                     //let attenuation = mat.attenuate_reflect(ray_in, hit_record.ray_t); 
                     if let Some((reflected_ray, attenuation)) = mat.reflect(ray_in, &hit_record, epsilon) {
-                        shade_diffuse(scene, shapes, vertex_cache, &hit_record, &ray_in, mat) + attenuation * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1) 
+                        shade_diffuse(scene, shapes, vertex_cache, &hit_record, ray_in, mat, sampler) + attenuation * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1, sampler, true)? 
                     }
                     else {
                         warn!("Mirror reflection is missing in 'mirror' arm in renderer.rs .");
                         Vector3::ZERO // Perfect mirror always reflects so this hopefully is not triggered
                     }
             }, 
-           "dielectric" | "conductor" => {
+            MaterialKind::Conductor(mat) => {
+                // Conductors reflect but never transmit (F_t = 0, see slides 02, p.21)
+                let mut tot_radiance = shade_diffuse(scene, shapes, vertex_cache, &hit_record, ray_in, mat, sampler);
+                if let Some((reflected_ray, attenuation)) = mat.reflect(ray_in, &hit_record, epsilon) {
+                        tot_radiance += attenuation * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1, sampler, true)?;
+                }
+                tot_radiance
+            }
+            MaterialKind::Dielectric(mat) => {
                 let mut tot_radiance = Vector3::ZERO;
                 
                 // Only add diffuse, specular, and ambient components if front face (see slides 02, p.29)
                 if hit_record.is_front_face { 
-                    tot_radiance += shade_diffuse(scene, shapes, vertex_cache, &hit_record, &ray_in, mat);
+                    tot_radiance += shade_diffuse(scene, shapes, vertex_cache, &hit_record, ray_in, mat, sampler);
                 }
  
                 // Reflected
                 if let Some((reflected_ray, attenuation)) = mat.reflect(ray_in, &hit_record, epsilon) {
-                        tot_radiance += attenuation * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1);
+                        tot_radiance += attenuation * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1, sampler, true)?;
                 }
         
                 // Refracted 
                 // TODO: Should we check !is_front_face here? 
                 if let Some((refracted_ray, attenuation)) = mat.refract(ray_in, &hit_record, epsilon) {
-                        tot_radiance += attenuation * get_color(&refracted_ray, scene, shapes, vertex_cache, depth + 1);
+                        tot_radiance += attenuation * get_color(&refracted_ray, scene, shapes, vertex_cache, depth + 1, sampler, true)?;
+                }
+                tot_radiance
+            }
+            MaterialKind::GGX(mat) => {
+                // Direct light through the microfacet BRDF, plus a single glossy reflection sampled from the specular lobe
+                let mut tot_radiance = shade_diffuse(scene, shapes, vertex_cache, &hit_record, ray_in, mat, sampler);
+                let n = hit_record.normal;
+                if let Some(scatter) = mat.sample_specular(-ray_in.direction, n, sampler.next_2d()) {
+                        let reflected_ray = ray_in.spawn(hit_record.point + (n * epsilon), scatter.direction);
+                        // Emitters seen in the glossy reflection are part of direct light above
+                        tot_radiance += scatter.weight * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1, sampler, false)?;
+                }
+                tot_radiance
+            }
+            MaterialKind::Volume(mat) => {
+                // Invisible boundary, ray continues into (or out of) the medium without using up recursion depth
+                match mat.refract(ray_in, &hit_record, epsilon) {
+                    Some((ray, _)) => get_color(&ray, scene, shapes, vertex_cache, depth, sampler, with_emission)?,
+                    None => Vector3::ZERO,
+                }
+            }
+            MaterialKind::Subsurface(mat) => {
+                let mut tot_radiance = Vector3::ZERO;
+                // Rays from inside (e.g. camera in the object) see nothing, walks handle the inside
+                if hit_record.is_front_face {
+                    if let Some((reflected_ray, attenuation)) = mat.reflect(ray_in, &hit_record, epsilon) {
+                        tot_radiance += attenuation * get_color(&reflected_ray, scene, shapes, vertex_cache, depth + 1, sampler, true)?;
+                    }
+                    let transmitted = 1.0 - mat.fresnel(hit_record.normal.dot(-ray_in.direction).max(0.0));
+                    tot_radiance += transmitted * subsurface_walk(scene, shapes, vertex_cache, ray_in, &hit_record, mat, sampler);
                 }
                 tot_radiance
             }
-            _ => {
-                // WARNING: Below does not panic when json has unknown material because parser defaults it to Diffuse (however it does panic if you make a typo or not implement shading function)
-                panic!(">> Unknown material type '{}'! Shading function for this material is missing.", mat_type); 
-            },
         };
-        color
+        Ok(color * attenuation)
    }
    else {
-        scene.background_color // no hit
+        Ok(scene.background_color) // no hit
    }
 }
 
-pub fn render(scene: &Scene) -> Result<Vec<ImageData>, Box<dyn std::error::Error>>
+/// Options of the renderer itself, not related to the scene description
+#[derive(Debug, Clone)]
+pub struct RenderSettings {
+    pub tile_size: usize,
+    pub tile_order: TileOrder,
+    pub seed: u64,
+    pub progressive: Option<ProgressiveSettings>,
+    pub adaptive: Option<AdaptiveSettings>,
+    pub time_budget: Option<Duration>, // Wall-clock limit of the whole render, shared among cameras
+    pub spectral: bool, // Trace a wavelength per sample for dispersion, see spectrum.rs
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            tile_size: 32,
+            tile_order: TileOrder::default(),
+            seed: 0,
+            progressive: None,
+            adaptive: None,
+            time_budget: None,
+            spectral: false,
+        }
+    }
+}
+
+/// Write intermediate images and checkpoints while rendering, see checkpoint.rs
+#[derive(Debug, Clone)]
+pub struct ProgressiveSettings {
+    pub output_dir: PathBuf,
+    pub checkpoint_interval: Duration,
+    pub resume: bool, // Continue from existing checkpoints in output_dir, if any
+}
+
+impl Default for ProgressiveSettings {
+    fn default() -> Self {
+        Self {
+            output_dir: PathBuf::from("./"),
+            checkpoint_interval: Duration::from_secs(60),
+            resume: false,
+        }
+    }
+}
+
+/// Keep sampling only the pixels that are still noisy instead of a fixed NumSamples.
+/// A pixel is converged once the standard error of its mean luminance is below
+/// target_error times its mean luminance.
+#[derive(Debug, Clone)]
+pub struct AdaptiveSettings {
+    pub target_error: Float,
+    pub min_samples: usize, // Variance estimate of fewer samples is not reliable
+    pub max_samples: usize, // Upper bound for pixels that never converge
+}
+
+impl Default for AdaptiveSettings {
+    fn default() -> Self {
+        Self {
+            target_error: 0.01,
+            min_samples: 8,
+            max_samples: 1024,
+        }
+    }
+}
+
+// Luminance below one 8-bit level counts as one level when computing relative
+// error, otherwise (nearly) black pixels would never converge
+const MIN_LUMINANCE: Float = 1.0;
+
+pub fn render(scene: &Scene) -> TracerResult<Vec<ImageData>> {
+    render_with(scene, &RenderSettings::default())
+}
+
+pub fn render_with(scene: &Scene, settings: &RenderSettings) -> TracerResult<Vec<ImageData>>
 {
     let mut images: Vec<ImageData> = Vec::new();
 
-    for mut cam in scene.cameras.all() {
+    let cameras = scene.cameras.all();
+    let num_cameras = cameras.len();
+    let deadline = settings.time_budget.map(|budget| Instant::now() + budget);
+    for (i, mut cam) in cameras.into_iter().enumerate() {
         cam.setup(); // TODO: Could this be integrated to deserialization? Because it's easy to forget calling it
-        if cam.num_samples != 1 { warn!("Found num_samples = '{}' > 1, sampling is not implemented yet...", cam.num_samples); }
-        
-        let eye_rays = cam.generate_primary_rays();
-        let shapes: &ShapeList = &scene.objects.all_shapes;
-        // get a reference to the vertex cache stored in the scene
-        let vcache: &HeapAllocatedVerts = &scene.vertex_cache;
-
-        // --- Rayon Multithreading ---
-        let pixel_colors: Vec<_> = eye_rays
-            .par_iter()
-            .map(|ray| get_color(ray, scene, shapes, vcache, 0))
-            .collect();
-        // -----------------------------
-            
-        let im = ImageData::new_from_colors(cam.image_resolution, cam.image_name, pixel_colors);
-        images.push(im);
+        // Split the remaining time budget evenly among the remaining cameras
+        let camera_deadline = deadline.map(|deadline| {
+            let now = Instant::now();
+            now + deadline.saturating_duration_since(now) / (num_cameras - i) as u32
+        });
+        let film = render_camera(scene, &cam, settings, camera_deadline)?;
+        images.push(film.to_image(&cam.image_name));
     }
     
     Ok(images)
 }
 
+/// State of a single sample pass shared by all tiles
+struct Pass<'a> {
+    index: usize,
+    seed: u64,
+    spectral: bool,
+    active: Option<&'a [bool]>, // Pixels still to be sampled, all if None
+    deadline: Option<Instant>,
+}
+
+fn render_camera(scene: &Scene, cam: &Camera, settings: &RenderSettings, deadline: Option<Instant>) -> TracerResult<Film> {
+    // Render in sample passes, every pass adds one sample to every (active) pixel of the film.
+    // Within a pass image is rendered tile by tile and camera rays are generated lazily 
+    // so memory usage does not depend on resolution or number of samples.
+    // With adaptive sampling or a time budget NumSamples of the camera is ignored and
+    // passes continue until every pixel converges or time is up.
+    let (width, height) = cam.get_resolution();
+    let max_passes = match (&settings.adaptive, deadline) {
+        (Some(adaptive), _) => adaptive.max_samples.max(1),
+        (None, Some(_)) => usize::MAX,
+        (None, None) => cam.num_samples.max(1) as usize,
+    };
+
+    let mut film = Film::new(width, height);
+    let mut first_pass = 0;
+    let mut seed = settings.seed;
+    let origin = CheckpointOrigin::new(&scene.path, cam._id);
+    if let Some(progressive) = settings.progressive.as_ref().filter(|p| p.resume) {
+        let path = Checkpoint::path_for(&progressive.output_dir, &cam.image_name);
+        if path.exists() {
+            let checkpoint = Checkpoint::load(&path)?;
+            if (checkpoint.film.width, checkpoint.film.height) != (width, height) {
+                return Err(TracerError::Render(format!(
+                    "checkpoint {} is {}x{} but camera renders {}x{}",
+                    path.display(), checkpoint.film.width, checkpoint.film.height, width, height
+                )));
+            }
+            match checkpoint.origin {
+                Some(saved) if saved.scene_hash != origin.scene_hash => {
+                    return Err(TracerError::Render(format!(
+                        "checkpoint {} is rendered from another scene file than {}", path.display(), scene.path.display()
+                    )));
+                }
+                Some(saved) if saved.camera_id != origin.camera_id => {
+                    return Err(TracerError::Render(format!(
+                        "checkpoint {} is rendered by camera {} but camera {} renders {}",
+                        path.display(), saved.camera_id, origin.camera_id, cam.image_name
+                    )));
+                }
+                Some(_) => {}
+                None => warn!("Checkpoint {} does not record its scene and camera, assuming they match.", path.display()),
+            }
+            // RGB and spectral estimates of the same pixel differ until both converge, averaging them mixes noise patterns
+            match checkpoint.spectral {
+                Some(spectral) if spectral != settings.spectral => {
+                    let mode = |spectral| if spectral { "spectral" } else { "RGB" };
+                    return Err(TracerError::Render(format!(
+                        "checkpoint {} has {} samples but the render is {}", path.display(), mode(spectral), mode(settings.spectral)
+                    )));
+                }
+                Some(_) => {}
+                None => warn!("Checkpoint {} does not record whether it is spectral, assuming it is {}.", path.display(), if settings.spectral { "spectral" } else { "RGB" }),
+            }
+            info!("Resuming {} from {} after {} pass(es).", cam.image_name, path.display(), checkpoint.passes);
+            (film, first_pass, seed) = (checkpoint.film, checkpoint.passes, checkpoint.seed);
+        }
+    }
+
+    let tiles = make_tiles(width, height, settings.tile_size, settings.tile_order);
+    // Total amount of work is only known for a fixed number of samples, otherwise report passes periodically
+    let fixed = settings.adaptive.is_none() && deadline.is_none();
+    let overall_progress = fixed.then(|| Progress::new(&cam.image_name, "tiles", tiles.len() * max_passes.saturating_sub(first_pass)));
+    let start = Instant::now();
+    let mut last_checkpoint = Instant::now();
+    let mut last_report = Instant::now();
+    let mut active: Option<Vec<bool>> = None;
+    let mut passes = first_pass;
+    while passes < max_passes {
+        // First pass always completes so that no pixel is left empty
+        if passes > 0 && deadline.is_some_and(|d| Instant::now() >= d) {
+            info!("Time budget of {} is used up after {} pass(es).", cam.image_name, passes);
+            break;
+        }
+        let pass = Pass {
+            index: passes,
+            seed,
+            spectral: settings.spectral,
+            active: active.as_deref(),
+            deadline: deadline.filter(|_| passes > 0),
+        };
+        for (index, color) in render_pass(scene, cam, &tiles, &pass, overall_progress.as_ref())?.into_iter().flatten() {
+            film.add_sample(index, color);
+        }
+        passes += 1;
+        if overall_progress.is_none() && last_report.elapsed() >= REPORT_INTERVAL {
+            let left = deadline.map(|d| format!(", {:.1?} left", d.saturating_duration_since(Instant::now()))).unwrap_or_default();
+            info!("{}: {} pass(es), elapsed {:.1?}{}", cam.image_name, passes, start.elapsed(), left);
+            last_report = Instant::now();
+        }
+
+        if let Some(adaptive) = &settings.adaptive && passes >= adaptive.min_samples {
+            let noisy: Vec<bool> = (0..film.sample_count.len())
+                .map(|i| film.relative_error(i, MIN_LUMINANCE) > adaptive.target_error)
+                .collect();
+            let num_noisy = noisy.iter().filter(|&&n| n).count();
+            debug!("{}: {} noisy pixel(s) after {} pass(es).", cam.image_name, num_noisy, passes);
+            if num_noisy == 0 {
+                info!("All pixels of {} converged after {} pass(es).", cam.image_name, passes);
+                break;
+            }
+            active = Some(noisy);
+        }
+
+        if let Some(progressive) = &settings.progressive
+            && passes < max_passes && last_checkpoint.elapsed() >= progressive.checkpoint_interval {
+            write_checkpoint(&film, cam, passes, seed, origin, settings.spectral, progressive)?;
+            last_checkpoint = Instant::now();
+        }
+    }
+    // Render may stop early (converged or out of time), final checkpoint is written here in any case
+    if let Some(progressive) = &settings.progressive && passes > first_pass {
+        write_checkpoint(&film, cam, passes, seed, origin, settings.spectral, progressive)?;
+    }
+    info!("Rendered {} in {:.2?}", cam.image_name, start.elapsed());
+    Ok(film)
+}
+
+fn render_pass(scene: &Scene, cam: &Camera, tiles: &[Tile], pass: &Pass, progress: Option<&Progress>) -> TracerResult<Vec<Vec<(usize, Vector3)>>> {
+    let shapes: &ShapeList = &scene.objects.all_shapes;
+    // get a reference to the vertex cache stored in the scene
+    let vcache: &HeapAllocatedVerts = &scene.vertex_cache;
+    let width = cam.get_resolution().0;
+
+    // --- Rayon Multithreading ---
+    // par_bridge( ) hands out tiles in the given order, unlike par_iter( ) which splits the list
+    tiles
+        .iter()
+        .par_bridge()
+        .map(|tile| {
+            let mut samples = Vec::with_capacity(tile.pixel_count());
+            // Out of time: skip the rest of the pass, pixels keep the samples they have so far
+            if pass.deadline.is_none_or(|d| Instant::now() < d) {
+                for (col, row) in tile.pixels() {
+                    let index = row * width + col;
+                    if pass.active.is_some_and(|active| !active[index]) {
+                        continue;
+                    }
+                    // Every sample goes through the pixel center, samples differ by what is sampled along the path
+                    let mut sampler = Sampler::for_pixel(pass.seed, index, pass.index);
+                    let mut ray = cam.generate_ray(col, row, (0.5, 0.5));
+                    ray.media = scene.initial_media();
+                    if pass.spectral {
+                        let (wavelength, pdf) = sample_wavelength(sampler.next_float());
+                        ray.wavelength = Some(wavelength);
+                        let color = get_color(&ray, scene, shapes, vcache, 0, &mut sampler, true)?;
+                        samples.push((index, spectral_weight(wavelength, pdf) * color));
+                    } else {
+                        samples.push((index, get_color(&ray, scene, shapes, vcache, 0, &mut sampler, true)?));
+                    }
+                }
+            }
+            if let Some(progress) = progress {
+                progress.tick();
+            }
+            Ok(samples)
+        })
+        .collect()
+    // -----------------------------
+}
+
+fn write_checkpoint(film: &Film, cam: &Camera, passes: usize, seed: u64, origin: CheckpointOrigin, spectral: bool, progressive: &ProgressiveSettings) -> TracerResult<()> {
+    let dir = &progressive.output_dir;
+    std::fs::create_dir_all(dir).map_err(|e| TracerError::io(dir, e))?;
+    film.to_image(&cam.image_name).save_png(&dir.to_string_lossy())?;
+    let path = Checkpoint::path_for(dir, &cam.image_name);
+    Checkpoint { film: film.clone(), passes, seed, origin: Some(origin), spectral: Some(spectral) }.save(&path)?;
+    info!("Saved checkpoint of {} after {} pass(es) to {}", cam.image_name, passes, path.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_parser::parse_json795;
+
+    // Diffuse sphere in front of a gray background, corners only see the background
+    const SPHERE: &str = r#"{
+      "Scene": {
+        "MaxRecursionDepth": "2",
+        "BackgroundColor": "50 50 50",
+        "ShadowRayEpsilon": "1e-3",
+        "IntersectionTestEpsilon": "1e-6",
+        "Cameras": {
+          "Camera": {
+            "_id": "1", "Position": "0 0 5", "Gaze": "0 0 -1", "Up": "0 1 0",
+            "NearPlane": "-1 1 -1 1", "NearDistance": "2", "ImageResolution": "8 8",
+            "NumSamples": "4", "ImageName": "adaptive.png"
+          }
+        },
+        "Lights": {
+          "AmbientLight": "25 25 25",
+          "PointLight": { "_id": "1", "Position": "0 4 4", "Intensity": "1000 1000 1000" }
+        },
+        "Materials": {
+          "Material": {
+            "_id": "1", "AmbientReflectance": "1 1 1", "DiffuseReflectance": "1 1 1",
+            "SpecularReflectance": "0 0 0", "PhongExponent": "1"
+          }
+        },
+        "VertexData": "0 0 0",
+        "Objects": { "Sphere": { "_id": "1", "Material": "1", "Center": "1", "Radius": "1" } }
+      }
+    }"#;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("furry_tracer_renderer_tests").join(name);
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn load_scene(dir: &std::path::Path) -> (Scene, Camera) {
+        let path = dir.join("scene.json");
+        std::fs::write(&path, SPHERE).unwrap();
+        let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+        root.scene.setup_after_json(&path).unwrap();
+        let mut cam = root.scene.cameras.all().remove(0);
+        cam.setup();
+        (root.scene, cam)
+    }
+
+    fn adaptive(target_error: Float) -> RenderSettings {
+        let adaptive = AdaptiveSettings { target_error, min_samples: 4, max_samples: 256 };
+        RenderSettings { adaptive: Some(adaptive), ..RenderSettings::default() }
+    }
+
+    #[test]
+    fn test_adaptive_stops_at_convergence() {
+        let (scene, cam) = load_scene(&test_dir("convergence"));
+        let film = render_camera(&scene, &cam, &adaptive(0.05), None).unwrap();
+        // Background has no variance, it converges as soon as the estimate is trusted
+        assert_eq!(film.sample_count[0], 4);
+        assert!(film.sample_count.iter().all(|&n| (4..256).contains(&n)));
+        assert!((0..film.sample_count.len()).all(|i| film.relative_error(i, MIN_LUMINANCE) <= 0.05));
+    }
+
+    #[test]
+    fn test_time_budget_stops_after_first_pass() {
+        let (scene, cam) = load_scene(&test_dir("time_budget"));
+        // Budget is used up before rendering starts, yet the first pass completes
+        let film = render_camera(&scene, &cam, &RenderSettings::default(), Some(Instant::now())).unwrap();
+        assert!(film.sample_count.iter().all(|&n| n == 1));
+        let film = render_camera(&scene, &cam, &adaptive(0.0), Some(Instant::now())).unwrap();
+        assert!(film.sample_count.iter().all(|&n| n == 1));
+    }
+
+    #[test]
+    fn test_adaptive_resume_of_version_1_checkpoint() {
+        let dir = test_dir("resume_v1");
+        let (scene, cam) = load_scene(&dir);
+        let (width, height) = cam.get_resolution();
+        let radiance_sum = Vector3::splat(50.0 * 8.0);
+        let bytes = crate::checkpoint::version_1_bytes(width, height, 8, 0, radiance_sum);
+        std::fs::write(Checkpoint::path_for(&dir, &cam.image_name), bytes).unwrap();
+
+        let progressive = ProgressiveSettings { output_dir: dir.clone(), resume: true, ..ProgressiveSettings::default() };
+        let settings = RenderSettings { progressive: Some(progressive), ..adaptive(0.05) };
+        let film = render_camera(&scene, &cam, &settings, None).unwrap();
+        // Resumed pixels have no second moment, variance needs two new samples
+        assert_eq!(film.sample_count[0], 10);
+        assert!(film.sample_count.iter().all(|&n| n >= 10));
+        assert!(film.moment_count.iter().zip(&film.sample_count).all(|(&m, &n)| m + 8 == n));
+    }
+
+    #[test]
+    fn test_resume_refuses_checkpoint_of_another_scene_or_camera() {
+        let dir = test_dir("resume_origin");
+        let (scene, cam) = load_scene(&dir);
+        let (width, height) = cam.get_resolution();
+        let progressive = ProgressiveSettings { output_dir: dir.clone(), resume: true, ..ProgressiveSettings::default() };
+        let settings = RenderSettings { progressive: Some(progressive), ..RenderSettings::default() };
+        let resume_from = |origin: CheckpointOrigin| {
+            let checkpoint = Checkpoint { film: Film::new(width, height), passes: 1, seed: 0, origin: Some(origin), spectral: Some(false) };
+            checkpoint.save(&Checkpoint::path_for(&dir, &cam.image_name)).unwrap();
+            render_camera(&scene, &cam, &settings, None)
+        };
+        assert!(resume_from(CheckpointOrigin::new(&dir.join("other.json"), cam._id)).is_err());
+        assert!(resume_from(CheckpointOrigin::new(&scene.path, cam._id + 1)).is_err());
+        let film = resume_from(CheckpointOrigin::new(&scene.path, cam._id)).unwrap();
+        // Resumed film is empty but counts as one pass, three of the four passes are left
+        assert!(film.sample_count.iter().all(|&n| n == 3));
+    }
+
+    #[test]
+    fn test_resume_refuses_checkpoint_of_another_color_mode() {
+        let dir = test_dir("resume_spectral");
+        let (scene, cam) = load_scene(&dir);
+        let (width, height) = cam.get_resolution();
+        let origin = Some(CheckpointOrigin::new(&scene.path, cam._id));
+        let checkpoint = Checkpoint { film: Film::new(width, height), passes: 1, seed: 0, origin, spectral: Some(false) };
+        checkpoint.save(&Checkpoint::path_for(&dir, &cam.image_name)).unwrap();
+        let progressive = ProgressiveSettings { output_dir: dir.clone(), resume: true, ..ProgressiveSettings::default() };
+        let settings = RenderSettings { progressive: Some(progressive), spectral: true, ..RenderSettings::default() };
+        let error = render_camera(&scene, &cam, &settings, None).unwrap_err();
+        assert!(error.to_string().contains("RGB samples but the render is spectral"), "{}", error);
+    }
+}
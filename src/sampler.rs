@@ -0,0 +1,87 @@
+/*
+
+    Random number generation for sampling, based on PCG32
+    (see https://www.pcg-random.org). A Sampler is cheap to
+    create, so every pixel sample gets its own one seeded by
+    (pixel index, sample index). This keeps renders
+    reproducible regardless of thread scheduling and the
+    whole RNG state is recoverable from these two numbers
+    (and the seed of the render).
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use crate::numeric::Float;
+
+const MULTIPLIER: u64 = 6364136223846793005;
+
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    state: u64,
+    increment: u64,
+}
+
+impl Sampler {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut sampler = Self {
+            state: 0,
+            increment: (stream << 1) | 1, // Must be odd
+        };
+        sampler.next_u32();
+        sampler.state = sampler.state.wrapping_add(seed);
+        sampler.next_u32();
+        sampler
+    }
+
+    /// Sampler of a single pixel sample, every (pixel, sample) pair gets an independent sequence.
+    /// All bits of the seed matter, seed and sample index are mixed instead of packed.
+    pub fn for_pixel(seed: u64, pixel_index: usize, sample_index: usize) -> Self {
+        Self::new(splitmix64(splitmix64(seed) ^ sample_index as u64), pixel_index as u64)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    /// Uniform float in [0, 1)
+    pub fn next_float(&mut self) -> Float {
+        // Use 24 bits so that the result is strictly less than 1 for f32 as well
+        (self.next_u32() >> 8) as Float / (1u32 << 24) as Float
+    }
+
+    pub fn next_2d(&mut self) -> (Float, Float) {
+        (self.next_float(), self.next_float())
+    }
+}
+
+// Finalizer of SplitMix64, spreads every input bit over the whole output
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_numbers(mut sampler: Sampler) -> [u32; 4] {
+        [(); 4].map(|_| sampler.next_u32())
+    }
+
+    #[test]
+    fn test_for_pixel_uses_all_seed_bits() {
+        // Seeds that differ only in their high bits, or that swap bits with the sample index
+        let base = first_numbers(Sampler::for_pixel(1, 5, 0));
+        assert_ne!(base, first_numbers(Sampler::for_pixel(1 | (1 << 40), 5, 0)));
+        assert_ne!(base, first_numbers(Sampler::for_pixel(1 | (1 << 63), 5, 0)));
+        assert_ne!(first_numbers(Sampler::for_pixel(0, 5, 1)), first_numbers(Sampler::for_pixel(1, 5, 0)));
+        assert_eq!(base, first_numbers(Sampler::for_pixel(1, 5, 0)));
+    }
+}
@@ -29,20 +29,30 @@
     @author: Bartu
 */
 use std::fs::File;
-use std::error::Error;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use std::fmt;
 use std::sync::Arc;
 use serde_json::{self, Value};
 use serde::{Deserialize};
-use tracing::{warn, error, debug, info};
+use tracing::{warn, debug, info};
 use smart_default::SmartDefault;
 
+use crate::error::{TracerError, TracerResult};
 use crate::geometry::get_tri_normal;
 use crate::json_parser::{deser_string_or_struct};
-use crate::material::{ConductorMaterial, DielectricMaterial, DiffuseMaterial, HeapAllocMaterial, Material, MirrorMaterial};
-use crate::numeric::{Int, Float, Vector3};
-use crate::shapes::{HeapAllocatedShape, Plane, PrimitiveShape, ShapeList, Sphere, Triangle, VertexCache};
+use crate::material::{ConductorMaterial, DielectricMaterial, DiffuseMaterial, GGXMaterial, Material, MaterialKind, MirrorMaterial, SubsurfaceMaterial, VolumeMaterial};
+use crate::brdf::SceneBRDFs;
+use crate::light::AreaLights;
+use crate::ray::{Medium, MediumStack};
+use crate::volume::{DensityGrid, HomogeneousVolume};
+use crate::numeric::{Int, Float, Vector3, DEFAULT_EPSILON};
+use crate::bezier::{BezierMethod, BezierPatch};
+use crate::csg::Csg;
+use crate::sdf::Sdf;
+use crate::displacement::Displacement;
+use crate::subdivision::{triangulate, PolygonMesh, Subdivision, MAX_LEVEL};
+use crate::shapes::{Cone, Cuboid, Cylinder, Disk, HeapAllocatedShape, Plane, PrimitiveShape, ShapeList, Sphere, Torus, Triangle, VertexCache, VoxelVolume};
 use crate::camera::{Cameras};
 use crate::json_parser::*;
 use crate::dataforms::{SingleOrVec, VertexData, DataField};
@@ -76,20 +86,27 @@ pub struct Scene {
     #[serde(skip)]
     pub vertex_cache: HeapAllocatedVerts,
 
+    #[serde(skip)]
+    pub path: PathBuf, // JSON file the scene is loaded from, set by setup_after_json( )
+
     pub cameras: Cameras,
     pub lights: SceneLights,
+    #[serde(rename = "BRDFs")]
+    pub brdfs: SceneBRDFs,
     pub materials: SceneMaterials,
     pub objects: SceneObjects,
+    pub fog: Option<HomogeneousVolume>, // Medium filling the whole scene, see volume.rs
 }
 
 impl Scene {
     //pub fn new() {
     //}
-    pub fn setup_after_json(&mut self, jsonpath: &Path) -> Result<(), Box<dyn Error>>{
+    pub fn setup_after_json(&mut self, jsonpath: &Path) -> TracerResult<()> {
         // Implement required adjustments after loading from a JSON file
+        self.path = jsonpath.to_path_buf();
 
         // 1- Convert materials serde_json values to actual structs
-        self.materials.finalize();
+        self.materials.finalize(&self.brdfs)?;
         for m in &self.materials.materials { // TODO: refactor that ambigious call materials.materials( )
             debug!("Material: {:#?}", m);
         }
@@ -104,20 +121,26 @@ impl Scene {
 
         // 
     // Build shapes and the vertex cache (returned by setup)
-    let cache = self.objects.setup(&mut self.vertex_data,  jsonpath)?; // Appends new vertices if mesh is from PLY
+    let cache = self.objects.setup(&mut self.vertex_data,  jsonpath, self.materials.materials.len())?; // Appends new vertices if mesh is from PLY
     self.vertex_cache = Arc::new(cache);
 
+        // 4- Shapes with emissive materials become area lights
+        self.lights.area_lights = AreaLights::build(&self.objects.all_shapes, &self.materials.materials, &self.vertex_cache);
+        if !self.lights.area_lights.is_empty() {
+            info!("There are {} emissive shape(s) with total area {}.", self.lights.area_lights.len(), self.lights.area_lights.total_area());
+        }
+
        
 
         // TODO: Below is a terrible way to set defaults, if Scene is decoupled from JSON
         // then it can impl Default for Scene and there we can specify default values
         // without secretly changing like we do below:
         if self.shadow_ray_epsilon < 1e-16 {
-            self.shadow_ray_epsilon = 1e-10; 
+            self.shadow_ray_epsilon = DEFAULT_EPSILON;
             warn!("Shadow Ray epsilon found 0, setting it to default: {}.", self.shadow_ray_epsilon);
         }
         if self.intersection_test_epsilon < 1e-16 {
-            self.intersection_test_epsilon = 1e-10;
+            self.intersection_test_epsilon = DEFAULT_EPSILON;
             warn!("Intersection Ray epsilon found 0, setting it to default: {}.", self.intersection_test_epsilon);
         }
 
@@ -128,6 +151,114 @@ impl Scene {
         Ok(())
     }
 
+    pub fn apply_overrides(&mut self, overrides: &RenderOverrides) -> TracerResult<()> {
+        // Overwrite values read from JSON, e.g. by command line arguments.
+        // Expected to be called after setup_after_json( ) since setup fills defaults.
+        if !overrides.camera_ids.is_empty() {
+            self.cameras.select(&overrides.camera_ids);
+            if self.cameras.all().is_empty() {
+                return Err(TracerError::Render(format!("none of the cameras {:?} exist in the scene", overrides.camera_ids)));
+            }
+        }
+        for cam in self.cameras.all_mut() {
+            if let Some(resolution) = overrides.resolution {
+                cam.set_resolution(resolution);
+            }
+            if let Some(num_samples) = overrides.num_samples {
+                cam.num_samples = num_samples;
+            }
+        }
+        if let Some(depth) = overrides.max_recursion_depth {
+            self.max_recursion_depth = depth;
+        }
+        Ok(())
+    }
+
+    /// Media camera rays start in, fog of the scene if there is one
+    pub fn initial_media(&self) -> MediumStack {
+        match &self.fog {
+            Some(fog) => MediumStack::default().entering(fog.medium(0)),
+            None => MediumStack::default(),
+        }
+    }
+
+    /// Grid of the medium if it fills a voxel volume, None if it is homogeneous
+    pub fn density_grid(&self, medium: &Medium) -> Option<&DensityGrid> {
+        medium.grid.and_then(|i| self.objects.density_grids.get(i)).map(|grid| grid.as_ref())
+    }
+
+    pub fn stats(&self) -> SceneStats {
+        SceneStats {
+            cameras: self.cameras.all().len(),
+            point_lights: self.lights.point_lights.len(),
+            area_lights: self.lights.area_lights.len(),
+            materials: self.materials.materials.len(),
+            triangles: self.objects.triangles.len(),
+            spheres: self.objects.spheres.len(),
+            planes: self.objects.planes.len(),
+            meshes: self.objects.meshes.len(),
+            voxel_volumes: self.objects.voxel_volumes.len(),
+            analytic: self.objects.disks.len() + self.objects.cylinders.len() + self.objects.cones.len()
+                + self.objects.boxes.len() + self.objects.tori.len(),
+            csgs: self.objects.csgs.len(),
+            sdfs: self.objects.sdfs.len(),
+            bezier_patches: self.objects.bezier_patches.len(),
+            mesh_faces: self.objects.meshes.all().iter().map(|m| m.faces._data.len() / 3).sum(),
+            vertices: self.vertex_data._data.len().saturating_sub(1), // Excluding dummy vertex
+            shapes: self.objects.all_shapes.len(),
+        }
+    }
+
+}
+
+/// Values to overwrite after a scene is loaded, None (or empty) keeps JSON values
+#[derive(Debug, Clone, Default)]
+pub struct RenderOverrides {
+    pub camera_ids: Vec<Int>,
+    pub resolution: Option<[usize; 2]>,
+    pub num_samples: Option<Int>,
+    pub max_recursion_depth: Option<usize>,
+}
+
+/// Object counts of a scene, see Scene::stats( )
+#[derive(Debug, Clone, Default)]
+pub struct SceneStats {
+    pub cameras: usize,
+    pub point_lights: usize,
+    pub area_lights: usize,
+    pub materials: usize,
+    pub triangles: usize,
+    pub spheres: usize,
+    pub planes: usize,
+    pub meshes: usize,
+    pub voxel_volumes: usize,
+    pub analytic: usize, // Disks, cylinders, cones, boxes and tori
+    pub csgs: usize, // Roots of CSG trees
+    pub sdfs: usize,
+    pub bezier_patches: usize,
+    pub mesh_faces: usize,
+    pub vertices: usize,
+    pub shapes: usize,
+}
+
+impl fmt::Display for SceneStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "  Cameras:      {}", self.cameras)?;
+        writeln!(f, "  Point lights: {}", self.point_lights)?;
+        writeln!(f, "  Area lights:  {}", self.area_lights)?;
+        writeln!(f, "  Materials:    {}", self.materials)?;
+        writeln!(f, "  Triangles:    {}", self.triangles)?;
+        writeln!(f, "  Spheres:      {}", self.spheres)?;
+        writeln!(f, "  Planes:       {}", self.planes)?;
+        writeln!(f, "  Meshes:       {} ({} faces)", self.meshes, self.mesh_faces)?;
+        writeln!(f, "  Volumes:      {}", self.voxel_volumes)?;
+        writeln!(f, "  Analytic:     {}", self.analytic)?;
+        writeln!(f, "  CSG trees:    {}", self.csgs)?;
+        writeln!(f, "  SDFs:         {}", self.sdfs)?;
+        writeln!(f, "  Bézier:       {}", self.bezier_patches)?;
+        writeln!(f, "  Vertices:     {}", self.vertices)?;
+        write!(f, "  Total shapes: {}", self.shapes)
+    }
 }
 
 
@@ -137,8 +268,11 @@ pub struct SceneLights {
     #[serde(rename = "AmbientLight", deserialize_with = "deser_vec3")]
     pub ambient_light: Vector3, // Refers to ambient radience in p.75
 
-    #[serde(rename = "PointLight")]
+    #[serde(rename = "PointLight", default)] // Scenes lit by emissive shapes only may have none
     pub point_lights: SingleOrVec<PointLight>, 
+
+    #[serde(skip)]
+    pub area_lights: AreaLights, // Shapes with emissive materials, collected in Scene::setup_after_json( )
 }
 
 impl Default for SceneLights {
@@ -146,6 +280,7 @@ impl Default for SceneLights {
         Self {
             ambient_light: Vector3::ZERO, // No intensity
             point_lights: SingleOrVec::default(),
+            area_lights: AreaLights::default(),
             }
     }
 }
@@ -170,58 +305,90 @@ pub struct SceneMaterials {
     raw_materials: SingleOrVec<serde_json::Value>, // Parse the json value later separately
 
     #[serde(skip)]
-    pub materials: Vec<HeapAllocMaterial>,
+    pub materials: Vec<MaterialKind>,
 }
 
 impl SceneMaterials {
-    pub fn finalize(&mut self) {
-        self.materials = self.raw_materials
-                        .all()
-                        .into_iter()
-                        .flat_map(parse_material)
-                        .collect();
+    pub fn finalize(&mut self, brdfs: &SceneBRDFs) -> TracerResult<()> {
+        // BRDFs are needed to resolve _BRDF references of materials
+        let json_path = String::from("Scene.Materials.Material");
+        let mut materials = Vec::new();
+        for value in self.raw_materials.all() {
+            materials.extend(parse_material(&json_path, value, brdfs)?);
+        }
+        self.materials = materials;
+        Ok(())
     }
 
-    pub fn all(&mut self) -> &Vec<HeapAllocMaterial> {
-        if self.materials.is_empty() && !self.raw_materials.all().is_empty() {
-            warn!("Calling SceneMaterials.finalize() to fully deserialize materials from JSON file...");
-            self.finalize(); 
-        }
+    /// Parsed materials, empty until finalize( )
+    pub fn all(&self) -> &Vec<MaterialKind> {
         &self.materials
     }
 }
 
 
-fn parse_single_material(value: serde_json::Value) -> HeapAllocMaterial {
+fn parse_single_material(json_path: &str, value: serde_json::Value, brdfs: &SceneBRDFs) -> TracerResult<MaterialKind> {
     
     debug!("Parsing material JSON: {:#?}", value);
 
+    // Read _id beforehand to be able to report it in errors
+    let id = value.get("_id").and_then(|v| match v {
+        Value::String(s) => s.parse::<usize>().ok(),
+        other => other.as_u64().map(|n| n as usize),
+    });
+    let json_error = |source| TracerError::Json { json_path: json_path.to_string(), id, source };
+
     // Check _type field
     let mat_type = value.get("_type").and_then(|v| v.as_str()).unwrap_or("diffuse");
 
-    match mat_type {
-        // TODO: This box will break if you change HeapAllocatedMaterial type! 
-        "diffuse" => Box::new(DiffuseMaterial::new_from(&value)),
-        "mirror" => Box::new(MirrorMaterial::new_from(&value)),
-        "dielectric" => Box::new(DielectricMaterial::new_from(&value)),
-        "conductor" => Box::new(ConductorMaterial::new_from(&value)),
+    let mut material = match mat_type {
+        "diffuse" => MaterialKind::Diffuse(DiffuseMaterial::new_from(&value).map_err(json_error)?),
+        "mirror" => MaterialKind::Mirror(MirrorMaterial::new_from(&value).map_err(json_error)?),
+        "dielectric" => MaterialKind::Dielectric(DielectricMaterial::new_from(&value).map_err(json_error)?),
+        "conductor" => MaterialKind::Conductor(ConductorMaterial::new_from(&value).map_err(json_error)?),
+        "ggx" => MaterialKind::GGX(GGXMaterial::new_from(&value).map_err(json_error)?),
+        "volume" => MaterialKind::Volume(VolumeMaterial::new_from(&value).map_err(json_error)?),
+        "subsurface" => MaterialKind::Subsurface(SubsurfaceMaterial::new_from(&value).map_err(json_error)?),
         // Add more materials here
 
         other => {
-            error!("Unknown material type '{other}', defaulting to DiffuseMaterial");
-            Box::new(DiffuseMaterial::new_from(&value))
+            return Err(TracerError::validation("Material", id.unwrap_or(0), format!("unknown material type '{other}' at {json_path}")));
+        }
+    };
+
+    // Reflectances that are not given are the ones of the material type, not the ones of BRDF::default( )
+    let defaults = material.default_brdf();
+    material.brdf_mut().default_missing(&value, &defaults);
+
+    // Reflection model, Blinn-Phong if there is no _BRDF
+    if let Some(brdf_value) = value.get("_BRDF") {
+        let brdf_id = deser_usize(brdf_value).map_err(json_error)?;
+        let refraction_index = value.get("RefractionIndex").map(deser_float).transpose().map_err(json_error)?.unwrap_or(1.5);
+        let (model, entry) = brdfs.get(brdf_id, refraction_index).ok_or_else(|| {
+            TracerError::validation("Material", id.unwrap_or(0), format!("_BRDF {brdf_id} at {json_path} does not exist"))
+        })?;
+        let brdf = material.brdf_mut();
+        brdf.model = model;
+        if let Some(exponent) = entry.exponent {
+            brdf.phong_exponent = exponent;
         }
     }
+    Ok(material)
 }
 
-fn parse_material(value: serde_json::Value) -> Vec<HeapAllocMaterial> {
+fn parse_material(json_path: &str, value: serde_json::Value, brdfs: &SceneBRDFs) -> TracerResult<Vec<MaterialKind>> {
     match value {
-        Value::Array(arr) => arr.into_iter().map(parse_single_material).collect(),
-        Value::Object(_) => vec![parse_single_material(value)],
-        _ => {
-            error!("Invalid material JSON, expected object or array: {value:?}");
-            vec![]
-        }
+        Value::Array(arr) => arr
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| parse_single_material(&format!("{json_path}[{i}]"), v, brdfs))
+            .collect(),
+        Value::Object(_) => Ok(vec![parse_single_material(json_path, value, brdfs)?]),
+        other => Err(TracerError::Json {
+            json_path: json_path.to_string(),
+            id: None,
+            source: serde::de::Error::custom(format!("expected object or array, found {other}")),
+        }),
     }
 }
 
@@ -242,13 +409,22 @@ pub struct Mesh {
     #[default = "flat"]
     pub _shading_mode: String,
 
+    #[serde(rename = "Subdivision")]
+    pub subdivision: Option<Subdivision>, // Refines faces once they are loaded, see subdivision.rs
+    #[serde(rename = "Displacement")]
+    pub displacement: Option<Displacement>, // Applied after subdivision, see displacement.rs
+
 }
 
 type FaceType = DataField<usize>;
 impl FaceType {
     pub fn len(&self) -> usize {
         debug_assert!(self._type == "triangle"); // Only triangle meshes are supported
-        (self._data.len() as f64 / 3.) as usize
+        self._data.len() / 3
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self._data.is_empty()
     }
 
     pub fn get_indices(&self, i: usize) -> [usize; 3] {
@@ -271,27 +447,48 @@ pub struct SceneObjects {
     pub planes: SingleOrVec<Plane>,
     #[serde(rename = "Mesh")]
     pub meshes: SingleOrVec<Mesh>,
+    #[serde(rename = "VoxelVolume")]
+    pub voxel_volumes: SingleOrVec<VoxelVolume>,
+    #[serde(rename = "Disk")]
+    pub disks: SingleOrVec<Disk>,
+    #[serde(rename = "Cylinder")]
+    pub cylinders: SingleOrVec<Cylinder>,
+    #[serde(rename = "Cone")]
+    pub cones: SingleOrVec<Cone>,
+    #[serde(rename = "Box")]
+    pub boxes: SingleOrVec<Cuboid>,
+    #[serde(rename = "Torus")]
+    pub tori: SingleOrVec<Torus>,
+    #[serde(rename = "CSG")]
+    pub csgs: SingleOrVec<Csg>,
+    #[serde(rename = "SDF")]
+    pub sdfs: SingleOrVec<Sdf>,
+    #[serde(rename = "BezierPatch")]
+    pub bezier_patches: SingleOrVec<BezierPatch>,
 
     #[serde(skip)]
     pub all_shapes: ShapeList,
+    #[serde(skip)]
+    pub density_grids: Vec<Arc<DensityGrid>>, // Grids of voxel volumes, see VoxelVolume::grid_index
 }
 
 impl SceneObjects {
 
-    pub fn setup(&mut self, verts: &mut VertexData, jsonpath: &Path) -> Result<VertexCache, Box<dyn Error>> {
+    pub fn setup(&mut self, verts: &mut VertexData, jsonpath: &Path, n_materials: usize) -> TracerResult<VertexCache> {
         // Return a vector of all shapes in the scene
-        warn!("SceneObjects.all( ) assumes there are only triangles, spheres, planes, and meshes. If there are other Shape trait implementations they are not added yet.");
+        // WARNING: Objects referring to vertices or materials that do not exist are skipped
+        // here so that rendering cannot panic, see validation.rs to report them.
+        warn!("SceneObjects.all( ) assumes there are only triangles, spheres, planes, meshes, voxel volumes, analytic shapes (disks, cylinders, cones, boxes, tori), CSG trees, SDFs, and Bézier patches. If there are other Shape trait implementations they are not added yet.");
         let mut shapes: ShapeList = Vec::new();
-        let mut all_triangles: Vec<Triangle> = self.triangles.all();
-
-        shapes.extend(self.triangles.all().into_iter().map(|t| Arc::new(t) as HeapAllocatedShape));
-        shapes.extend(self.spheres.all().into_iter().map(|s| Arc::new(s) as HeapAllocatedShape));
-        shapes.extend(self.planes.all().into_iter().map(|p| Arc::new(p) as HeapAllocatedShape));
-        //shapes.extend(self.meshes.all().into_iter().map(|m| Rc::new(m) as Rc<dyn Shape>));
+        let mut mesh_shapes: ShapeList = Vec::new();
+        let mut all_triangles: Vec<Triangle> = Vec::new();
 
         // Convert meshes to triangles 
+        let mut meshes = Vec::new();
         for mesh in self.meshes.all() {
             let mut mesh = mesh;
+            // Faces as polygons, vertices of PLY and OBJ files are appended to verts
+            let mut polygons: Option<Vec<Vec<usize>>> = None;
             if !mesh.faces._ply_file.is_empty() { 
                 
                 // Get path containing the JSON (_plyFile in json is relative to that json)
@@ -301,55 +498,156 @@ impl SceneObjects {
                 let ply_file = &mesh.faces._ply_file;
                 let ply_path = json_dir.join(ply_file);
 
-                if ply_path.exists() {
-                    info!("PLY file exists: {:?}", ply_path);
-                } else {
-                    error!("PLY file NOT found at: {:?}", ply_path);
-                }
-
                 info!("Loading mesh {} from PLY file path: {:?}", mesh._id, ply_path);
                 
-                let file = File::open(ply_path)?;
+                let file = File::open(&ply_path).map_err(|e| TracerError::io(&ply_path, e))?;
                 let reader = BufReader::new(file);
-                let plymesh: PlyMesh = serde_ply::from_reader(reader)?;
+                let plymesh: PlyMesh = serde_ply::from_reader(reader)
+                    .map_err(|source| TracerError::Ply { path: ply_path.clone(), source })?;
                 let old_vertex_count = verts._data.len();
                 // Append loaded ply to vertexdata 
                 for v in &plymesh.vertex {
                     verts._data.push(Vector3::new(v.x as Float, v.y as Float, v.z as Float));
                 }
-                // Shift faces._data by offset
-                mesh.faces._type = String::from("triangle");
+                // Shift faces by offset
                 if let Some(faces) = &plymesh.face {
-                    mesh.faces._data = faces
+                    if let Some((i, f)) = faces.iter().enumerate().find(|(_, f)| f.vertex_indices.len() < 3) {
+                        return Err(TracerError::validation("Mesh", mesh._id, format!(
+                            "face {} of {:?} has {} vertices, expected at least 3", i, ply_path, f.vertex_indices.len()
+                        )));
+                    }
+                    polygons = Some(faces
                         .iter()
-                        .flat_map(|f| f.vertex_indices.clone()) // each face is a list of 3 indices
-                        .map(|idx| idx + old_vertex_count)      // shift by existing vertices
-                        .collect();
-                    info!(">> Mesh {} has {} faces.", mesh._id, mesh.faces._data.len());
+                        .map(|f| f.vertex_indices.iter().map(|idx| idx + old_vertex_count).collect()) // shift by existing vertices
+                        .collect());
+                    info!(">> Mesh {} has {} faces.", mesh._id, faces.len());
                 } 
                 else {
                     warn!("PLY mesh {} has no face data!", mesh._id);
                 }
             }
+            else if !mesh.faces._obj_file.is_empty() {
+                let obj_path = jsonpath.parent().unwrap_or(Path::new(".")).join(&mesh.faces._obj_file);
+                info!("Loading mesh {} from OBJ file path: {:?}", mesh._id, obj_path);
+                let objmesh = PolygonMesh::load_obj(&obj_path)?;
+                info!(">> Mesh {} has {} faces.", mesh._id, objmesh.faces.len());
+                polygons = Some(append_polygon_mesh(verts, objmesh));
+            }
+            else if mesh.faces._type == "quad" || (mesh.faces._type == "triangle" && (mesh.subdivision.is_some() || mesh.displacement.is_some())) {
+                let sides = if mesh.faces._type == "quad" { 4 } else { 3 };
+                polygons = Some(mesh.faces._data.chunks_exact(sides).map(|f| f.to_vec()).collect());
+            }
+
+            if let Some(mut polygons) = polygons {
+                if let Some(subdivision) = mesh.subdivision {
+                    if subdivision.level > MAX_LEVEL {
+                        return Err(TracerError::validation("Mesh", mesh._id, format!(
+                            "subdivision level {} is above the maximum of {}", subdivision.level, MAX_LEVEL
+                        )));
+                    }
+                    if polygons.iter().flatten().all(|&idx| idx < verts._data.len()) { // Otherwise reported by validation
+                        let refined = PolygonMesh::from_shared(&verts._data, &polygons).subdivide(subdivision)
+                            .map_err(|problem| TracerError::validation("Mesh", mesh._id, format!("subdivision {}", problem)))?;
+                        polygons = append_polygon_mesh(verts, refined);
+                        info!(">> Mesh {} has {} faces after subdivision.", mesh._id, polygons.len());
+                    }
+                }
+                if let Some(displacement) = &mesh.displacement {
+                    if let Some(problem) = displacement.problem() {
+                        return Err(TracerError::validation("Mesh", mesh._id, format!("displacement {}", problem)));
+                    }
+                    if polygons.iter().flatten().all(|&idx| idx < verts._data.len()) {
+                        let heights = displacement.heights(jsonpath.parent().unwrap_or(Path::new(".")))?;
+                        let displaced = displacement.apply(&PolygonMesh::from_shared(&verts._data, &polygons), &heights)
+                            .map_err(|problem| TracerError::validation("Mesh", mesh._id, format!("displacement {}", problem)))?;
+                        polygons = append_polygon_mesh(verts, displaced);
+                        info!(">> Mesh {} has {} faces after displacement.", mesh._id, polygons.len());
+                    }
+                }
+                mesh.faces._type = String::from("triangle");
+                mesh.faces._data = triangulate(&polygons).into_iter().flatten().collect();
+            }
             let offset = verts._data.len();
-            let triangles: Vec<Triangle> = mesh_to_triangles(&mesh, verts, offset);
+            let triangles: Vec<Triangle> = mesh_to_triangles(&mesh, verts, offset)?;
             all_triangles.extend(triangles.iter().cloned());
-            shapes.extend(triangles.into_iter().map(|t| Arc::new(t) as HeapAllocatedShape));
+            mesh_shapes.extend(triangles.into_iter().map(|t| Arc::new(t) as HeapAllocatedShape));
+            meshes.push(mesh);
+        }
+        self.meshes = SingleOrVec::Multiple(meshes); // Keep faces loaded from PLY for later inspection
+
+        // Tessellate Bézier patches into triangles with analytic vertex normals, others are intersected directly
+        let mut patch_normals = Vec::new();
+        for patch in self.bezier_patches.all() {
+            let is_valid = patch.control_points.len() == 16 && patch.control_points.iter().all(|&i| i < verts._data.len()); // Otherwise reported by validation
+            if is_valid && patch.method == BezierMethod::Tessellate && patch.tolerance > 0.0 {
+                let (triangles, normals) = patch.to_triangles(verts);
+                patch_normals.extend(normals);
+                all_triangles.extend(triangles.iter().cloned());
+                mesh_shapes.extend(triangles.into_iter().map(|t| Arc::new(t) as HeapAllocatedShape));
+            }
         }
+
+        // Vertices are complete at this point, so references can be checked 
+        let n_verts = verts._data.len();
+        let triangles: Vec<Triangle> = self.triangles.all().into_iter().filter(|t| t.indices.iter().all(|&i| i < n_verts)).collect();
+        all_triangles.extend(triangles.iter().cloned());
+        shapes.extend(triangles.into_iter().map(|t| Arc::new(t) as HeapAllocatedShape));
+        shapes.extend(self.spheres.all().into_iter().filter(|s| s.center_idx < n_verts).map(|s| Arc::new(s) as HeapAllocatedShape));
+        shapes.extend(self.planes.all().into_iter().filter(|p| p.point_idx < n_verts).map(|p| Arc::new(p) as HeapAllocatedShape));
+        shapes.extend(self.disks.all().into_iter().filter(|d| d.center_idx < n_verts).map(|d| Arc::new(d) as HeapAllocatedShape));
+        shapes.extend(self.cylinders.all().into_iter().filter(|c| c.base_idx < n_verts).map(|c| Arc::new(c) as HeapAllocatedShape));
+        shapes.extend(self.cones.all().into_iter().filter(|c| c.base_idx < n_verts).map(|c| Arc::new(c) as HeapAllocatedShape));
+        shapes.extend(self.boxes.all().into_iter().filter(|b| b.min_idx < n_verts && b.max_idx < n_verts).map(|b| Arc::new(b) as HeapAllocatedShape));
+        shapes.extend(self.tori.all().into_iter().filter(|t| t.center_idx < n_verts).map(|t| Arc::new(t) as HeapAllocatedShape));
+        shapes.extend(self.csgs.all().into_iter().filter(|c| c.indices().iter().all(|&i| i < n_verts)).map(|c| Arc::new(c) as HeapAllocatedShape));
+        shapes.extend(self.sdfs.all().into_iter().filter(|s| s.center_idx < n_verts).map(|s| Arc::new(s) as HeapAllocatedShape));
+        for patch in self.bezier_patches.all_mut() {
+            let is_valid = patch.control_points.len() == 16 && patch.control_points.iter().all(|&i| i < n_verts);
+            if is_valid && patch.method == BezierMethod::Newton {
+                // Surface and the grid Newton's method starts from are the same for every ray
+                patch.surface = Some(Arc::new(patch.surface_of(verts)));
+                shapes.push(Arc::new(patch.clone()) as HeapAllocatedShape);
+            }
+        }
+        shapes.extend(mesh_shapes);
+
+        // Load grids of voxel volumes (_gridFile is relative to the JSON as well)
+        let json_dir = jsonpath.parent().unwrap_or(Path::new("."));
+        self.density_grids.clear();
+        for volume in self.voxel_volumes.all_mut() {
+            let grid_path = json_dir.join(&volume.grid_file);
+            info!("Loading voxel volume {} from grid file path: {:?}", volume._id, grid_path);
+            volume.grid = Arc::new(DensityGrid::load(&grid_path)?);
+            info!(">> Voxel volume {} has {:?} voxels with maximum density {}.", volume._id, volume.grid.resolution, volume.grid.max_density);
+            volume.grid_index = self.density_grids.len();
+            self.density_grids.push(volume.grid.clone());
+        }
+        shapes.extend(self.voxel_volumes.all().into_iter().map(|v| Arc::new(v) as HeapAllocatedShape));
+
         info!(">> There are {} vertices in the scene.", verts._data.len());
+        // Shapes with materials that do not exist are reported by validation, rendering skips them
+        shapes.retain(|shape| shape.materials().iter().all(|m| (1..=n_materials).contains(m)));
         self.all_shapes = shapes;
-        let cache = VertexCache::build(&verts, &all_triangles);   
+        let mut cache = VertexCache::build(verts, &all_triangles);   
+        cache.set_normals(patch_normals);
         Ok(cache)
     }
 
 }
 
 
+// Helper function to append vertices of a mesh to VertexData, returns its faces referring to them
+fn append_polygon_mesh(verts: &mut VertexData, mesh: PolygonMesh) -> Vec<Vec<usize>> {
+    let old_vertex_count = verts._data.len();
+    verts._data.extend(mesh.positions);
+    mesh.faces.into_iter().map(|f| f.into_iter().map(|idx| idx + old_vertex_count).collect()).collect()
+}
+
 // Helper function to convert a Mesh into individual Triangles
-fn mesh_to_triangles(mesh: &Mesh, verts: &VertexData, id_offset: usize) -> Vec<Triangle> {
+fn mesh_to_triangles(mesh: &Mesh, verts: &VertexData, id_offset: usize) -> TracerResult<Vec<Triangle>> {
     
     if mesh.faces._type != "triangle" {
-        panic!(">> Expected triangle faces in mesh_to_triangles, got '{}'.", mesh.faces._type);
+        return Err(TracerError::validation("Mesh", mesh._id, format!("expected triangle faces, got '{}'", mesh.faces._type)));
     }
     
     let n_faces = mesh.faces.len();
@@ -357,18 +655,21 @@ fn mesh_to_triangles(mesh: &Mesh, verts: &VertexData, id_offset: usize) -> Vec<T
     
     for i in 0..n_faces {
         let indices = mesh.faces.get_indices(i);
+        if indices.iter().any(|&idx| idx >= verts._data.len()) {
+            continue; // Reported by validation
+        }
         let [v1, v2, v3] = indices.map(|i| verts[i]);
         triangles.push(Triangle {
             _id: id_offset + i, 
             indices,
             material_idx: mesh.material_idx,
-            is_smooth: mesh._shading_mode.to_ascii_lowercase() == "smooth",
+            is_smooth: mesh._shading_mode.eq_ignore_ascii_case("smooth"),
             normal: get_tri_normal(&v1, &v2, &v3),
             //cache: None, // TODO: Fill cache
         });
     }
     
-    triangles
+    Ok(triangles)
 }
  
 #[derive(Deserialize)]
@@ -0,0 +1,317 @@
+/*
+
+    Signed distance field shapes, rendered by sphere tracing
+    (Hart 1996). A shape is a small expression tree of nodes,
+    distance functions follow Inigo Quilez's articles:
+        - Sphere { Center, Radius }
+        - Box { Center, HalfSize }
+        - Torus { Center, MajorRadius, MinorRadius }, ring in the xz plane
+        - Capsule { A, B, Radius }, segment AB thickened by Radius
+        - SmoothUnion { Smoothness, Left, Right }
+        - Subtraction { Smoothness, Left, Right }, Left minus Right
+        - Repetition { Period, Node }, infinitely repeats Node in cells of
+          Period centered on the origin, 0 on an axis not to repeat along it
+        - Twist { Rate, Node }, rotates Node around y by Rate radians per unit
+    Smoothness defaults to 0 (sharp). Coordinates of nodes are relative
+    to Center of the shape, which refers to VertexData, e.g.
+        "SDF": {
+            "_id": "1", "Material": "1", "Center": "1",
+            "Bounds": "-2 -1 -2 2 1 2",
+            "Node": { "SmoothUnion": { "Smoothness": "0.3",
+                "Left": { "Sphere": { "Center": "-0.5 0 0", "Radius": "0.6" } },
+                "Right": { "Capsule": { "A": "0 -0.5 0", "B": "0.8 0.5 0", "Radius": "0.3" } } } }
+        }
+    Rays are only traced inside Bounds (min and max corners, relative
+    to Center), which must contain the shape. Repetition is infinite, so
+    Bounds decides how many copies are seen.
+
+    Twisting stretches distances, steps are scaled down by a bound of
+    the gradient of the field (see SdfNode::lipschitz( )) so that the
+    tracer does not step over the surface.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use serde::Deserialize;
+
+use crate::geometry::ray_box_overlap;
+use crate::interval::Interval;
+use crate::json_parser::*;
+use crate::numeric::{Float, Vector3};
+use crate::ray::{HitRecord, Ray};
+use crate::shapes::{HeapAllocatedVerts, PrimitiveShape};
+
+const MAX_STEPS: usize = 512;
+const HIT_DISTANCE: Float = 1e-4; // Rays closer than this to the surface hit it
+const NORMAL_OFFSET: Float = 1e-4; // Of finite differences
+
+#[derive(Debug, Deserialize, Clone)]
+pub enum SdfNode {
+    Sphere {
+        #[serde(rename = "Center", default, deserialize_with = "deser_vec3")]
+        center: Vector3,
+        #[serde(rename = "Radius", deserialize_with = "deser_float")]
+        radius: Float,
+    },
+    #[serde(rename = "Box")]
+    Cuboid {
+        #[serde(rename = "Center", default, deserialize_with = "deser_vec3")]
+        center: Vector3,
+        #[serde(rename = "HalfSize", deserialize_with = "deser_vec3")]
+        half_size: Vector3,
+    },
+    Torus {
+        #[serde(rename = "Center", default, deserialize_with = "deser_vec3")]
+        center: Vector3,
+        #[serde(rename = "MajorRadius", deserialize_with = "deser_float")]
+        major_radius: Float,
+        #[serde(rename = "MinorRadius", deserialize_with = "deser_float")]
+        minor_radius: Float,
+    },
+    Capsule {
+        #[serde(rename = "A", deserialize_with = "deser_vec3")]
+        a: Vector3,
+        #[serde(rename = "B", deserialize_with = "deser_vec3")]
+        b: Vector3,
+        #[serde(rename = "Radius", deserialize_with = "deser_float")]
+        radius: Float,
+    },
+    SmoothUnion {
+        #[serde(rename = "Smoothness", default, deserialize_with = "deser_float")]
+        smoothness: Float,
+        #[serde(rename = "Left")]
+        left: Box<SdfNode>,
+        #[serde(rename = "Right")]
+        right: Box<SdfNode>,
+    },
+    Subtraction {
+        #[serde(rename = "Smoothness", default, deserialize_with = "deser_float")]
+        smoothness: Float,
+        #[serde(rename = "Left")]
+        left: Box<SdfNode>,
+        #[serde(rename = "Right")]
+        right: Box<SdfNode>,
+    },
+    Repetition {
+        #[serde(rename = "Period", deserialize_with = "deser_vec3")]
+        period: Vector3,
+        #[serde(rename = "Node")]
+        node: Box<SdfNode>,
+    },
+    Twist {
+        #[serde(rename = "Rate", deserialize_with = "deser_float")]
+        rate: Float,
+        #[serde(rename = "Node")]
+        node: Box<SdfNode>,
+    },
+}
+
+impl SdfNode {
+    /// Signed distance from p to the surface, negative inside
+    pub fn distance(&self, p: Vector3) -> Float {
+        match self {
+            SdfNode::Sphere { center, radius } => (p - center).length() - radius,
+            SdfNode::Cuboid { center, half_size } => {
+                let q = (p - center).abs() - half_size;
+                q.max(Vector3::ZERO).length() + q.max_element().min(0.0)
+            }
+            SdfNode::Torus { center, major_radius, minor_radius } => {
+                let q = p - center;
+                (q.x.hypot(q.z) - major_radius).hypot(q.y) - minor_radius
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - a, b - a);
+                let h = (pa.dot(ba) / ba.dot(ba)).clamp(0.0, 1.0);
+                (pa - ba * h).length() - radius
+            }
+            SdfNode::SmoothUnion { smoothness, left, right } => {
+                let (d1, d2) = (left.distance(p), right.distance(p));
+                if *smoothness <= 0.0 {
+                    return d1.min(d2);
+                }
+                let h = (0.5 + 0.5 * (d2 - d1) / smoothness).clamp(0.0, 1.0);
+                d2 + (d1 - d2) * h - smoothness * h * (1.0 - h)
+            }
+            SdfNode::Subtraction { smoothness, left, right } => {
+                let (d1, d2) = (left.distance(p), right.distance(p));
+                if *smoothness <= 0.0 {
+                    return d1.max(-d2);
+                }
+                let h = (0.5 - 0.5 * (d1 + d2) / smoothness).clamp(0.0, 1.0);
+                d1 + (-d2 - d1) * h + smoothness * h * (1.0 - h)
+            }
+            SdfNode::Repetition { period, node } => {
+                // Position in the cell around the origin, axes with zero period are kept
+                let cell = Vector3::select(period.cmpgt(Vector3::ZERO), (p / *period).round(), Vector3::ZERO);
+                node.distance(p - *period * cell)
+            }
+            SdfNode::Twist { rate, node } => {
+                let (sin, cos) = (rate * p.y).sin_cos();
+                node.distance(Vector3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z))
+            }
+        }
+    }
+
+    /// Upper bound of the gradient length of the field within given distance to the
+    /// origin. Distances of a twist are too long by up to sqrt(1 + (rate r)^2)
+    /// at radius r, others are exact or underestimate.
+    pub fn lipschitz(&self, extent: Float) -> Float {
+        match self {
+            SdfNode::SmoothUnion { left, right, .. } | SdfNode::Subtraction { left, right, .. } => {
+                left.lipschitz(extent).max(right.lipschitz(extent))
+            }
+            SdfNode::Repetition { node, .. } => node.lipschitz(extent),
+            SdfNode::Twist { rate, node } => node.lipschitz(extent) * (1.0 + (rate * extent).powi(2)).sqrt(),
+            _ => 1.0,
+        }
+    }
+
+    pub fn children(&self) -> Vec<&SdfNode> {
+        match self {
+            SdfNode::SmoothUnion { left, right, .. } | SdfNode::Subtraction { left, right, .. } => vec![left, right],
+            SdfNode::Repetition { node, .. } | SdfNode::Twist { node, .. } => vec![node],
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Sdf {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+    #[serde(rename = "Center", deserialize_with = "deser_usize")]
+    pub center_idx: usize, // Refers to VertexData
+    #[serde(rename = "Bounds", deserialize_with = "deser_vertex_data")]
+    pub bounds: Vec<Vector3>, // Min and max corners, relative to the center
+    #[serde(rename = "Node")]
+    pub node: SdfNode,
+}
+
+impl Sdf {
+    fn normal(&self, p: Vector3) -> Vector3 {
+        // Gradient by central differences, normalized
+        let h = NORMAL_OFFSET;
+        let (dx, dy, dz) = (Vector3::X * h, Vector3::Y * h, Vector3::Z * h);
+        Vector3::new(
+            self.node.distance(p + dx) - self.node.distance(p - dx),
+            self.node.distance(p + dy) - self.node.distance(p - dy),
+            self.node.distance(p + dz) - self.node.distance(p - dz),
+        ).normalize_or_zero()
+    }
+
+    /// t of the first surface crossing within the interval, ray origin relative to the center
+    fn trace(&self, origin: Vector3, direction: Vector3, t_interval: &Interval) -> Option<Float> {
+        let [min, max] = self.bounds[..] else {
+            return None; // Reported by validation
+        };
+        let (t_enter, t_exit) = ray_box_overlap(origin, direction, min, max)?;
+        let t_end = t_exit.min(t_interval.max);
+        let mut t = t_enter.max(t_interval.min);
+        let step_scale = 1.0 / self.node.lipschitz(min.length().max(max.length()));
+
+        // Secondary rays start on the surface they leave, so the shell around
+        // the surface they start in is skipped before looking for hits
+        let mut leaving = self.node.distance(origin + direction * t).abs() < HIT_DISTANCE;
+        for _ in 0..MAX_STEPS {
+            if t > t_end {
+                return None;
+            }
+            let distance = self.node.distance(origin + direction * t).abs() * step_scale;
+            if distance < HIT_DISTANCE {
+                if !leaving {
+                    return Some(t);
+                }
+                t += HIT_DISTANCE;
+            } else {
+                leaving = false;
+                t += distance;
+            }
+        }
+        None
+    }
+}
+
+impl PrimitiveShape for Sdf {
+
+    fn indices(&self) -> Vec<usize> {
+        [self.center_idx].to_vec()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        let center = vertex_cache.vertex_data[self.center_idx];
+        let t = self.trace(ray.origin - center, ray.direction, t_interval)?;
+        let point = ray.at(t);
+        let normal = self.normal(point - center);
+
+        let is_front_face = ray.is_front_face(normal);
+        let normal = if is_front_face { normal } else { -normal };
+        Some(HitRecord::new(point, normal, t, self.material_idx, is_front_face))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(json: &str) -> SdfNode {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn sdf(node: SdfNode, bounds: Float) -> Sdf {
+        Sdf { _id: 1, material_idx: 1, center_idx: 1, bounds: vec![Vector3::splat(-bounds), Vector3::splat(bounds)], node }
+    }
+
+    #[test]
+    fn test_distances() {
+        let p = Vector3::new(2.0, 0.0, 0.0);
+        assert!((node(r#"{ "Sphere": { "Radius": "1" } }"#).distance(p) - 1.0).abs() < 1e-6);
+        assert!((node(r#"{ "Box": { "Center": "0 0 1", "HalfSize": "1 1 0.5" } }"#).distance(p) - (1.25 as Float).sqrt()).abs() < 1e-6);
+        assert!((node(r#"{ "Torus": { "MajorRadius": "2", "MinorRadius": "0.5" } }"#).distance(p) + 0.5).abs() < 1e-6);
+        assert!((node(r#"{ "Capsule": { "A": "0 -1 0", "B": "0 1 0", "Radius": "0.5" } }"#).distance(p) - 1.5).abs() < 1e-6);
+
+        // Sharp operations, then smooth ones blend by at most a quarter of the smoothness
+        let union = node(r#"{ "SmoothUnion": { "Left": { "Sphere": { "Radius": "1" } }, "Right": { "Sphere": { "Center": "3 0 0", "Radius": "1" } } } }"#);
+        assert!((union.distance(Vector3::new(1.5, 0.0, 0.0)) - 0.5).abs() < 1e-6);
+        let smooth = node(r#"{ "SmoothUnion": { "Smoothness": "1", "Left": { "Sphere": { "Radius": "1" } }, "Right": { "Sphere": { "Center": "3 0 0", "Radius": "1" } } } }"#);
+        assert!((smooth.distance(Vector3::new(1.5, 0.0, 0.0)) - 0.25).abs() < 1e-6);
+        let subtraction = node(r#"{ "Subtraction": { "Left": { "Sphere": { "Radius": "1" } }, "Right": { "Sphere": { "Center": "1 0 0", "Radius": "0.5" } } } }"#);
+        assert!((subtraction.distance(Vector3::new(0.75, 0.0, 0.0)) - 0.25).abs() < 1e-6);
+        let smooth = node(r#"{ "Subtraction": { "Smoothness": "0.1", "Left": { "Sphere": { "Radius": "1" } }, "Right": { "Sphere": { "Center": "1 0 0", "Radius": "0.5" } } } }"#);
+        assert!((smooth.distance(Vector3::new(-0.5, 0.0, 0.0)) + 0.5).abs() < 1e-6);
+        assert!((smooth.distance(Vector3::new(0.75, 0.0, 0.0)) - 0.25).abs() < 0.1 / 4.0 + 1e-6);
+
+        let repeated = node(r#"{ "Repetition": { "Period": "4 0 0", "Node": { "Sphere": { "Radius": "1" } } } }"#);
+        assert!((repeated.distance(Vector3::new(8.5, 0.0, 0.0)) + 0.5).abs() < 1e-6);
+        assert!((repeated.distance(Vector3::new(8.0, 3.0, 0.0)) - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sphere_tracing() {
+        let interval = Interval::positive(1e-6);
+        let sphere = sdf(node(r#"{ "Sphere": { "Radius": "1" } }"#), 2.0);
+        let t = sphere.trace(Vector3::new(0.3, 0.0, 5.0), -Vector3::Z, &interval).unwrap();
+        assert!((t - (5.0 - (0.91 as Float).sqrt())).abs() < 1e-3);
+        assert!(sphere.normal(Vector3::new(0.0, 1.0, 0.0)).abs_diff_eq(Vector3::Y, 1e-4));
+        // Leaving from the surface finds the other side, not the starting point
+        let exit = sphere.trace(Vector3::new(0.0, 0.0, 1.0), -Vector3::Z, &interval).unwrap();
+        assert!((exit - 2.0).abs() < 1e-3);
+        assert!(sphere.trace(Vector3::new(1.5, 0.0, 5.0), -Vector3::Z, &interval).is_none());
+
+        // Twisted box must not be overshot, compare with a brute force search
+        let twisted = sdf(node(r#"{ "Twist": { "Rate": "2", "Node": { "Box": { "HalfSize": "1 1 0.2" } } } }"#), 1.5);
+        let (origin, direction) = (Vector3::new(0.6, 0.3, 5.0), -Vector3::Z);
+        let t = twisted.trace(origin, direction, &interval).unwrap();
+        let first_inside = (0..100000).map(|i| i as Float * 1e-4)
+            .find(|&t| twisted.node.distance(origin + direction * t) < 0.0)
+            .unwrap();
+        assert!((t - first_inside).abs() < 1e-2, "{} {}", t, first_inside);
+    }
+}
@@ -1,6 +1,11 @@
 /*
 
-    Declare primitives: Triangle, Sphere, Plane
+    Declare primitives: Triangle, Sphere, Plane,
+    VoxelVolume (box of a heterogeneous medium), and
+    analytic Disk, Cylinder, Cone, Box (Cuboid) and Torus
+    having uv coordinates on their surfaces. Closed ones
+    (spheres, analytic shapes except disks) report spans
+    of rays inside them to be used in CSG (see csg.rs).
     
 
     @date: Oct, 2025
@@ -13,12 +18,14 @@ use std::fmt::Debug;
 use bevy_math::NormedVectorSpace;
 use serde::{Deserialize};
 use smart_default::SmartDefault;
-use tracing::{info, error};
-use crate::geometry::get_tri_normal;
+use crate::csg::{Span, SurfaceHit};
+use crate::geometry::{get_tri_normal, polynomial_roots, ray_box_overlap, solve_quadratic, vertex_normals, Frame};
 use crate::json_parser::*;
-use crate::interval::{Interval};
+use crate::interval::{FloatConst, Interval};
+use crate::light::Emitter;
 use crate::dataforms::{VertexData};
-use crate::numeric::{approx_zero, Float, Matrix3, Vector3};
+use crate::numeric::{Float, Matrix3, Vector3};
+use crate::volume::DensityGrid;
 use crate::ray::{Ray, HitRecord}; // TODO: Can we create a small crate for gathering shapes.rs, ray.rs?
 
 pub type HeapAllocatedShape = Arc<dyn PrimitiveShape>;
@@ -27,60 +34,35 @@ pub type HeapAllocatedVerts = Arc<VertexCache>;
 
 //#[derive(Default)]
 #[derive(Debug, Clone)]
+#[derive(Default)]
 pub struct VertexCache {
-    vertex_data: VertexData,
+    pub(crate) vertex_data: VertexData,
     vertex_normals: Vec<Vector3>,
 }
 
-impl Default for VertexCache {
-    fn default() -> Self {
-        Self {
-            vertex_data: VertexData::default(),
-            vertex_normals: Vec::new(),
-        }
-    }
-}
 
 impl VertexCache {
     
-    pub fn build(verts: &VertexData, triangles: &Vec<Triangle>) -> VertexCache {
+    pub fn build(verts: &VertexData, triangles: &[Triangle]) -> VertexCache {
         // Computes per-vertex normals by averaging adjacent triangle normals
 
         let vertex_data = verts.clone();
-        let mut vertex_normals: Vec<Vector3> = vec![Vector3::ZERO; vertex_data._data.len()];
-        for tri in triangles.iter() {
-            let indices = tri.indices;
-            // Check if indices are in bounds of vertex_data
-            if indices.iter().any(|&i| i >= vertex_data._data.len()) {
-                continue;
-            }
-            let v1 = vertex_data._data[indices[0]];
-            let v2 = vertex_data._data[indices[1]];
-            let v3 = vertex_data._data[indices[2]];
-            let edge_ab = v2 - v1;
-            let edge_ac = v3 - v1;
-            let face_n = edge_ab.cross(edge_ac); // Be careful, not normalized yet!
-
-            // Add the area-weighted face normal to each vertex normal
-            for &idx in &indices {
-                if idx < vertex_normals.len() {
-                    vertex_normals[idx] += face_n;
-                }
-            }
-        }
-
-        // Normalize accumulated normals
-        for n in vertex_normals.iter_mut() {
-            if n.norm_squared() > 0.0 { 
-                *n = n.normalize();
-            }
-        }
+        let vertex_normals = vertex_normals(&vertex_data._data, triangles.iter().map(|tri| tri.indices));
 
         VertexCache {
             vertex_data,
             vertex_normals,
         }
     }
+
+    /// Replace normals of some vertices, e.g. by the analytic normals of the surface they are sampled from
+    pub fn set_normals(&mut self, normals: impl IntoIterator<Item = (usize, Vector3)>) {
+        for (idx, normal) in normals {
+            if idx < self.vertex_normals.len() {
+                self.vertex_normals[idx] = normal;
+            }
+        }
+    }
 }
 
 pub trait PrimitiveShape : Debug + Send + Sync  {
@@ -88,7 +70,24 @@ pub trait PrimitiveShape : Debug + Send + Sync  {
     //    None
     //}
     fn indices(&self) -> Vec<usize>;
+    fn material_idx(&self) -> usize;
     fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord>;
+
+    /// Materials hits with the shape can have, e.g. all operands of a CSG tree
+    fn materials(&self) -> Vec<usize> {
+        vec![self.material_idx()]
+    }
+
+    /// Surface to sample if the material of the shape is emissive (see light.rs), None if it can not be sampled
+    fn as_emitter(&self, _: &HeapAllocatedVerts) -> Option<Emitter> {
+        None
+    }
+
+    /// Parts of the ray inside the shape sorted by t, over the whole line (t can be negative)
+    /// so that CSG can combine them (see csg.rs). None if the shape is not closed.
+    fn spans(&self, _: &Ray, _: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        None
+    }
 }
 
 // Raw data deserialized from .JSON file
@@ -116,6 +115,14 @@ impl PrimitiveShape for Triangle {
         self.indices.to_vec()
     }
 
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn as_emitter(&self, vertex_cache: &HeapAllocatedVerts) -> Option<Emitter> {
+        Some(Emitter::Triangle(self.indices.map(|i| vertex_cache.vertex_data[i])))
+    }
+
     fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
 
         // TODO: cache vertex / face normals
@@ -298,7 +305,7 @@ fn moller_trumbore_intersection(ray: &Ray, t_interval: &Interval, tri_indices: [
         let dist = ray.origin - tri_pivot;
 
         let barycentric_u = dist.dot(perp) * inverse_determinant;
-        if (barycentric_u < 0.0) || (barycentric_u > 1.0) {
+        if !(0.0..=1.0).contains(&barycentric_u) {
             return None;
         }
 
@@ -335,6 +342,27 @@ impl PrimitiveShape for Sphere {
         [self.center_idx].to_vec()
     }
 
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn as_emitter(&self, vertex_cache: &HeapAllocatedVerts) -> Option<Emitter> {
+        Some(Emitter::Sphere { center: vertex_cache.vertex_data[self.center_idx], radius: self.radius })
+    }
+
+    fn spans(&self, ray: &Ray, vertex_cache: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        let center = vertex_cache.vertex_data[self.center_idx];
+        let o_minus_c = ray.origin - center;
+        let roots = solve_quadratic(ray.direction.dot(ray.direction), 2.0 * ray.direction.dot(o_minus_c), o_minus_c.dot(o_minus_c) - self.radius.powi(2));
+        let crossings = roots.into_iter().map(|t| SurfaceHit {
+            t,
+            normal: (ray.at(t) - center) / self.radius,
+            material: self.material_idx,
+            uv: None,
+        });
+        Some(Span::from_crossings(crossings.collect()))
+    }
+
     fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
         
         // Based on Slides 01_B, p.11, Ray-Sphere Intersection 
@@ -389,6 +417,10 @@ impl PrimitiveShape for Plane {
     fn indices(&self) -> Vec<usize> {
         [self.point_idx].to_vec()
     }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
     
     fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
        // Based on Slides 01_B, p.9, Ray-Plane Intersection 
@@ -409,3 +441,485 @@ impl PrimitiveShape for Plane {
         }
     }
 }
+
+// Axis-aligned box of a heterogeneous medium, bounds come from its grid file (see volume.rs)
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct VoxelVolume {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+    #[serde(rename = "_gridFile")]
+    pub grid_file: String, // Relative to the JSON file
+    #[serde(skip)]
+    pub grid: Arc<DensityGrid>, // Loaded in SceneObjects::setup( )
+    #[serde(skip)]
+    pub grid_index: usize, // Of grid in SceneObjects::density_grids
+}
+
+impl PrimitiveShape for VoxelVolume {
+
+    fn indices(&self) -> Vec<usize> {
+        Vec::new()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, _: &HeapAllocatedVerts) -> Option<HitRecord> {
+        let (min, max) = (self.grid.min, self.grid.max);
+        let (t_enter, t_exit) = self.grid.ray_overlap(ray)?;
+        let t = if t_interval.contains(t_enter) { t_enter } else if t_interval.contains(t_exit) { t_exit } else { return None };
+
+        // Normal of the face hit is along the axis where the point is farthest from the center (relative to size)
+        let point = ray.at(t);
+        let local = (point - (min + max) * 0.5) / (max - min);
+        let axis = if local.x.abs() >= local.y.abs() && local.x.abs() >= local.z.abs() { 0 }
+            else if local.y.abs() >= local.z.abs() { 1 }
+            else { 2 };
+        let mut normal = Vector3::ZERO;
+        normal[axis] = local[axis].signum();
+
+        let is_front_face = ray.is_front_face(normal);
+        let normal = if is_front_face { normal } else { -normal };
+        Some(HitRecord::new(point, normal, t, self.material_idx, is_front_face).with_grid(self.grid_index))
+    }
+}
+
+
+// Analytic shapes below are intersected in their own frame (see geometry::Frame)
+// where they are symmetric around +z or axis-aligned, hits are then mapped back.
+
+// Hit of an analytic shape in its local frame, normal points outwards (not normalized)
+struct LocalHit {
+    t: Float,
+    normal: Vector3,
+    uv: [Float; 2],
+}
+
+trait AnalyticShape {
+    fn frame(&self, verts: &VertexData) -> Frame;
+    /// All hits along the (local) ray regardless of t, unordered
+    fn local_hits(&self, verts: &VertexData, origin: Vector3, direction: Vector3) -> Vec<LocalHit>;
+}
+
+fn analytic_crossings(shape: &impl AnalyticShape, ray: &Ray, vertex_cache: &HeapAllocatedVerts, material_idx: usize) -> Vec<SurfaceHit> {
+    let verts = &vertex_cache.vertex_data;
+    let frame = shape.frame(verts);
+    let hits = shape.local_hits(verts, frame.point_to_local(ray.origin), frame.dir_to_local(ray.direction));
+    hits.into_iter().map(|hit| SurfaceHit {
+        t: hit.t,
+        normal: frame.dir_to_world(hit.normal).normalize(),
+        material: material_idx,
+        uv: Some(hit.uv),
+    }).collect()
+}
+
+fn intersect_analytic(shape: &impl AnalyticShape, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts, material_idx: usize) -> Option<HitRecord> {
+    analytic_crossings(shape, ray, vertex_cache, material_idx).into_iter()
+        .filter(|h| t_interval.contains(h.t))
+        .min_by(|a, b| a.t.total_cmp(&b.t))
+        .map(|h| h.to_record(ray))
+}
+
+fn analytic_spans(shape: &impl AnalyticShape, ray: &Ray, vertex_cache: &HeapAllocatedVerts, material_idx: usize) -> Option<Vec<Span>> {
+    Some(Span::from_crossings(analytic_crossings(shape, ray, vertex_cache, material_idx)))
+}
+
+fn azimuth(x: Float, y: Float) -> Float {
+    // Angle of (x, y) around the origin mapped to [0, 1)
+    let u = y.atan2(x) / (2.0 * Float::PI);
+    if u < 0.0 { u + 1.0 } else { u }
+}
+
+fn cap_hit(origin: Vector3, direction: Vector3, z: Float, radius: Float, normal: Vector3) -> Option<LocalHit> {
+    // Disk of given radius at height z, centered on the z axis
+    if direction.z == 0.0 {
+        return None;
+    }
+    let t = (z - origin.z) / direction.z;
+    let p = origin + direction * t;
+    let rho = p.x.hypot(p.y);
+    (rho <= radius).then(|| LocalHit { t, normal, uv: [azimuth(p.x, p.y), rho / radius] })
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Disk {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Center", deserialize_with = "deser_usize")]
+    pub center_idx: usize, // Refers to VertexData
+    #[serde(rename = "Normal", deserialize_with = "deser_vec3")]
+    pub normal: Vector3,
+    #[serde(rename = "Radius", deserialize_with = "deser_float")]
+    pub radius: Float,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+}
+
+impl AnalyticShape for Disk {
+    fn frame(&self, verts: &VertexData) -> Frame {
+        Frame::from_axis(verts[self.center_idx], self.normal)
+    }
+
+    fn local_hits(&self, _: &VertexData, origin: Vector3, direction: Vector3) -> Vec<LocalHit> {
+        cap_hit(origin, direction, 0.0, self.radius, Vector3::Z).into_iter().collect()
+    }
+}
+
+impl PrimitiveShape for Disk {
+
+    fn indices(&self) -> Vec<usize> {
+        [self.center_idx].to_vec()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        intersect_analytic(self, ray, t_interval, vertex_cache, self.material_idx)
+    }
+}
+
+// Closed cylinder standing on its base disk, extending along Axis by Height
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Cylinder {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Base", deserialize_with = "deser_usize")]
+    pub base_idx: usize, // Center of the bottom cap, refers to VertexData
+    #[serde(rename = "Axis", deserialize_with = "deser_vec3")]
+    pub axis: Vector3,
+    #[serde(rename = "Radius", deserialize_with = "deser_float")]
+    pub radius: Float,
+    #[serde(rename = "Height", deserialize_with = "deser_float")]
+    pub height: Float,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+}
+
+impl AnalyticShape for Cylinder {
+    fn frame(&self, verts: &VertexData) -> Frame {
+        Frame::from_axis(verts[self.base_idx], self.axis)
+    }
+
+    fn local_hits(&self, _: &VertexData, o: Vector3, d: Vector3) -> Vec<LocalHit> {
+        // Side is x^2 + y^2 = r^2 for 0 <= z <= h
+        let (r, h) = (self.radius, self.height);
+        let mut hits: Vec<LocalHit> = solve_quadratic(d.x * d.x + d.y * d.y, 2.0 * (o.x * d.x + o.y * d.y), o.x * o.x + o.y * o.y - r * r)
+            .into_iter()
+            .filter_map(|t| {
+                let p = o + d * t;
+                (0.0..=h).contains(&p.z).then(|| LocalHit { t, normal: Vector3::new(p.x, p.y, 0.0), uv: [azimuth(p.x, p.y), p.z / h] })
+            })
+            .collect();
+        hits.extend(cap_hit(o, d, 0.0, r, -Vector3::Z));
+        hits.extend(cap_hit(o, d, h, r, Vector3::Z));
+        hits
+    }
+}
+
+impl PrimitiveShape for Cylinder {
+
+    fn indices(&self) -> Vec<usize> {
+        [self.base_idx].to_vec()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn spans(&self, ray: &Ray, vertex_cache: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        analytic_spans(self, ray, vertex_cache, self.material_idx)
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        intersect_analytic(self, ray, t_interval, vertex_cache, self.material_idx)
+    }
+}
+
+// Closed cone on its base disk, apex is at Height along Axis
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Cone {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Base", deserialize_with = "deser_usize")]
+    pub base_idx: usize, // Center of the base, refers to VertexData
+    #[serde(rename = "Axis", deserialize_with = "deser_vec3")]
+    pub axis: Vector3,
+    #[serde(rename = "Radius", deserialize_with = "deser_float")]
+    pub radius: Float, // Of the base
+    #[serde(rename = "Height", deserialize_with = "deser_float")]
+    pub height: Float,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+}
+
+impl AnalyticShape for Cone {
+    fn frame(&self, verts: &VertexData) -> Frame {
+        Frame::from_axis(verts[self.base_idx], self.axis)
+    }
+
+    fn local_hits(&self, _: &VertexData, o: Vector3, d: Vector3) -> Vec<LocalHit> {
+        // Side is x^2 + y^2 = k^2 (h - z)^2 for 0 <= z <= h where k = r / h,
+        // the quadratic also has the mirrored cone above the apex, hence the range check
+        let (r, h) = (self.radius, self.height);
+        let k2 = (r / h).powi(2);
+        let to_apex = h - o.z;
+        let a = d.x * d.x + d.y * d.y - k2 * d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.y * d.y + k2 * to_apex * d.z);
+        let c = o.x * o.x + o.y * o.y - k2 * to_apex * to_apex;
+        let mut hits: Vec<LocalHit> = solve_quadratic(a, b, c)
+            .into_iter()
+            .filter_map(|t| {
+                let p = o + d * t;
+                if !(0.0..=h).contains(&p.z) {
+                    return None;
+                }
+                let normal = Vector3::new(p.x, p.y, k2 * (h - p.z)); // Gradient, vanishes at the apex
+                let normal = if normal.norm_squared() > 0.0 { normal } else { Vector3::Z };
+                Some(LocalHit { t, normal, uv: [azimuth(p.x, p.y), p.z / h] })
+            })
+            .collect();
+        hits.extend(cap_hit(o, d, 0.0, r, -Vector3::Z));
+        hits
+    }
+}
+
+impl PrimitiveShape for Cone {
+
+    fn indices(&self) -> Vec<usize> {
+        [self.base_idx].to_vec()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn spans(&self, ray: &Ray, vertex_cache: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        analytic_spans(self, ray, vertex_cache, self.material_idx)
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        intersect_analytic(self, ray, t_interval, vertex_cache, self.material_idx)
+    }
+}
+
+// "Box" in JSON, given by its corners before an optional rotation around its center.
+// Named Cuboid to not shadow std Box.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Cuboid {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Min", deserialize_with = "deser_usize")]
+    pub min_idx: usize, // Refers to VertexData
+    #[serde(rename = "Max", deserialize_with = "deser_usize")]
+    pub max_idx: usize,
+    #[serde(rename = "Rotation", default, deserialize_with = "deser_rotation")]
+    pub rotation: Matrix3, // Identity (axis-aligned) if not given
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+}
+
+impl Cuboid {
+    fn half_size(&self, verts: &VertexData) -> Vector3 {
+        (verts[self.max_idx] - verts[self.min_idx]) * 0.5
+    }
+}
+
+impl AnalyticShape for Cuboid {
+    fn frame(&self, verts: &VertexData) -> Frame {
+        Frame::new((verts[self.min_idx] + verts[self.max_idx]) * 0.5, self.rotation)
+    }
+
+    fn local_hits(&self, verts: &VertexData, o: Vector3, d: Vector3) -> Vec<LocalHit> {
+        // Slab method, the box is [-half, half] in its frame
+        let half = self.half_size(verts);
+        let Some((t_enter, t_exit)) = ray_box_overlap(o, d, -half, half) else {
+            return Vec::new();
+        };
+
+        [t_enter, t_exit].into_iter().map(|t| {
+            // Face hit is along the axis where the point is farthest from the center (relative to size),
+            // uv are the other two coordinates
+            let local = (o + d * t) / half;
+            let axis = if local.x.abs() >= local.y.abs() && local.x.abs() >= local.z.abs() { 0 }
+                else if local.y.abs() >= local.z.abs() { 1 }
+                else { 2 };
+            let mut normal = Vector3::ZERO;
+            normal[axis] = local[axis].signum();
+            let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+            LocalHit { t, normal, uv: [(local[i] + 1.0) * 0.5, (local[j] + 1.0) * 0.5] }
+        }).collect()
+    }
+}
+
+impl PrimitiveShape for Cuboid {
+
+    fn indices(&self) -> Vec<usize> {
+        [self.min_idx, self.max_idx].to_vec()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn spans(&self, ray: &Ray, vertex_cache: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        analytic_spans(self, ray, vertex_cache, self.material_idx)
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        intersect_analytic(self, ray, t_interval, vertex_cache, self.material_idx)
+    }
+}
+
+// Ring of radius MajorRadius around Axis, with a tube of radius MinorRadius
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct Torus {
+    #[serde(deserialize_with = "deser_usize")]
+    pub _id: usize,
+    #[serde(rename = "Center", deserialize_with = "deser_usize")]
+    pub center_idx: usize, // Refers to VertexData
+    #[serde(rename = "Axis", deserialize_with = "deser_vec3")]
+    pub axis: Vector3,
+    #[serde(rename = "MajorRadius", deserialize_with = "deser_float")]
+    pub major_radius: Float,
+    #[serde(rename = "MinorRadius", deserialize_with = "deser_float")]
+    pub minor_radius: Float,
+    #[serde(rename = "Material", deserialize_with = "deser_usize")]
+    pub material_idx: usize,
+}
+
+impl AnalyticShape for Torus {
+    fn frame(&self, verts: &VertexData) -> Frame {
+        Frame::from_axis(verts[self.center_idx], self.axis)
+    }
+
+    fn local_hits(&self, _: &VertexData, origin: Vector3, d: Vector3) -> Vec<LocalHit> {
+        // Torus is (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2), a quartic in t.
+        // It is solved from where the ray enters the bounding sphere since
+        // the quartic loses precision far from the torus.
+        let (big_r, small_r) = (self.major_radius, self.minor_radius);
+        let dd = d.dot(d);
+        let bounds = solve_quadratic(dd, 2.0 * origin.dot(d), origin.dot(origin) - (big_r + small_r).powi(2));
+        let [t_enter, t_exit] = bounds[..] else {
+            return Vec::new();
+        };
+
+        let o = origin + d * t_enter;
+        let m = o.dot(o) + big_r * big_r - small_r * small_r;
+        let n = o.dot(d);
+        let four_r2 = 4.0 * big_r * big_r;
+        let coefficients = [
+            dd * dd,
+            4.0 * dd * n,
+            4.0 * n * n + 2.0 * dd * m - four_r2 * (d.x * d.x + d.y * d.y),
+            4.0 * n * m - 2.0 * four_r2 * (o.x * d.x + o.y * d.y),
+            m * m - four_r2 * (o.x * o.x + o.y * o.y),
+        ];
+
+        polynomial_roots(&coefficients, 0.0, t_exit - t_enter).into_iter().map(|s| {
+            let p = o + d * s;
+            let rho = p.x.hypot(p.y);
+            let ring = Vector3::new(p.x, p.y, 0.0) * (big_r / rho); // Closest point on the ring
+            LocalHit { t: t_enter + s, normal: p - ring, uv: [azimuth(p.x, p.y), azimuth(rho - big_r, p.z)] }
+        }).collect()
+    }
+}
+
+impl PrimitiveShape for Torus {
+
+    fn indices(&self) -> Vec<usize> {
+        [self.center_idx].to_vec()
+    }
+
+    fn material_idx(&self) -> usize {
+        self.material_idx
+    }
+
+    fn spans(&self, ray: &Ray, vertex_cache: &HeapAllocatedVerts) -> Option<Vec<Span>> {
+        analytic_spans(self, ray, vertex_cache, self.material_idx)
+    }
+
+    fn intersects_with(&self, ray: &Ray, t_interval: &Interval, vertex_cache: &HeapAllocatedVerts) -> Option<HitRecord> {
+        intersect_analytic(self, ray, t_interval, vertex_cache, self.material_idx)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(shape: &dyn PrimitiveShape, verts: &str, origin: Vector3, direction: Vector3) -> Option<HitRecord> {
+        let mut verts: VertexData = verts.parse().unwrap();
+        verts.insert_dummy_at_the_beginning(); // Vertex ids start from 1
+        let cache = Arc::new(VertexCache::build(&verts, &[]));
+        shape.intersects_with(&Ray::new(origin, direction.normalize()), &Interval::positive(1e-6), &cache)
+    }
+
+    fn close(a: Float, b: Float) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_disk_and_cylinder() {
+        let disk = Disk { center_idx: 1, normal: Vector3::Y, radius: 1.0, ..Default::default() };
+        let h = hit(&disk, "0 0 0", Vector3::new(0.5, 5.0, 0.0), -Vector3::Y).unwrap();
+        assert!(close(h.ray_t, 5.0) && h.normal == Vector3::Y && h.is_front_face);
+        assert!(close(h.uv.unwrap()[1], 0.5));
+        assert!(hit(&disk, "0 0 0", Vector3::new(1.5, 5.0, 0.0), -Vector3::Y).is_none());
+
+        let cylinder = Cylinder { base_idx: 1, axis: Vector3::Z, radius: 1.0, height: 2.0, ..Default::default() };
+        let side = hit(&cylinder, "0 0 0", Vector3::new(5.0, 0.0, 1.0), -Vector3::X).unwrap();
+        assert!(close(side.ray_t, 4.0) && side.normal.abs_diff_eq(Vector3::X, 1e-6));
+        let [u, v] = side.uv.unwrap();
+        assert!(close(u, 0.0) && close(v, 0.5));
+        let cap = hit(&cylinder, "0 0 0", Vector3::new(0.5, 0.0, 5.0), -Vector3::Z).unwrap();
+        assert!(close(cap.ray_t, 3.0) && cap.normal.abs_diff_eq(Vector3::Z, 1e-6));
+        // Leaving from inside hits a back face, normal faces the ray
+        let inside = hit(&cylinder, "0 0 0", Vector3::new(0.0, 0.0, 1.0), Vector3::X).unwrap();
+        assert!(close(inside.ray_t, 1.0) && !inside.is_front_face && inside.normal.abs_diff_eq(-Vector3::X, 1e-6));
+        assert!(hit(&cylinder, "0 0 0", Vector3::new(5.0, 0.0, 3.0), -Vector3::X).is_none());
+    }
+
+    #[test]
+    fn test_cone_and_box() {
+        let cone = Cone { base_idx: 1, axis: Vector3::Z, radius: 1.0, height: 1.0, ..Default::default() };
+        let apex = hit(&cone, "0 0 0", Vector3::new(0.0, 0.0, 5.0), -Vector3::Z).unwrap();
+        assert!(close(apex.ray_t, 4.0));
+        let side = hit(&cone, "0 0 0", Vector3::new(5.0, 0.0, 0.5), -Vector3::X).unwrap();
+        assert!(close(side.ray_t, 4.5) && side.normal.abs_diff_eq(Vector3::new(1.0, 0.0, 1.0).normalize(), 1e-6));
+        // Mirrored cone above the apex is not part of the shape
+        assert!(hit(&cone, "0 0 0", Vector3::new(5.0, 0.0, 1.5), -Vector3::X).is_none());
+
+        let aligned = Cuboid { min_idx: 1, max_idx: 2, rotation: Matrix3::IDENTITY, ..Default::default() };
+        let face = hit(&aligned, "-1 -1 -1 1 1 1", Vector3::new(5.0, 0.5, 0.0), -Vector3::X).unwrap();
+        assert!(close(face.ray_t, 4.0) && face.normal == Vector3::X);
+        let [u, v] = face.uv.unwrap();
+        assert!(close(u, 0.75) && close(v, 0.5));
+
+        // Rotated by 45 degrees around z, the ray hits an edge
+        let rotated = Cuboid { rotation: Matrix3::from_rotation_z(Float::PI / 4.0), ..aligned };
+        let edge = hit(&rotated, "-1 -1 -1 1 1 1", Vector3::new(5.0, 0.0, 0.0), -Vector3::X).unwrap();
+        assert!(close(edge.ray_t, 5.0 - (2.0 as Float).sqrt()));
+    }
+
+    #[test]
+    fn test_torus() {
+        let torus = Torus { center_idx: 1, axis: Vector3::Z, major_radius: 2.0, minor_radius: 0.5, ..Default::default() };
+        let outer = hit(&torus, "0 0 0", Vector3::new(-5.0, 0.0, 0.0), Vector3::X).unwrap();
+        assert!(close(outer.ray_t, 2.5) && outer.normal.abs_diff_eq(-Vector3::X, 1e-4));
+        let top = hit(&torus, "0 0 0", Vector3::new(2.0, 0.0, 5.0), -Vector3::Z).unwrap();
+        assert!(close(top.ray_t, 4.5) && top.normal.abs_diff_eq(Vector3::Z, 1e-4));
+        let [u, v] = top.uv.unwrap();
+        assert!(close(u, 0.0) && close(v, 0.25));
+        // Through the hole
+        assert!(hit(&torus, "0 0 0", Vector3::new(0.0, 0.0, 5.0), -Vector3::Z).is_none());
+        // Starting inside the tube
+        let inside = hit(&torus, "0 0 0", Vector3::new(2.0, 0.0, 0.0), Vector3::X).unwrap();
+        assert!(close(inside.ray_t, 0.5) && !inside.is_front_face);
+    }
+}
@@ -0,0 +1,168 @@
+/*
+
+    Spectral rendering helpers. In spectral mode (see
+    RenderSettings::spectral) every pixel sample traces a
+    single wavelength so that refraction indices can vary
+    with it, e.g. dispersion of a prism or a diamond:
+        - Dielectrics with CauchyCoefficients "A B [C]" or
+          SellmeierCoefficients "B1 B2 B3 C1 C2 C3" (micrometers)
+        - Conductors with NKSpectrum "wavelength n k ..." (nanometers)
+
+    Materials and lights are still given in RGB. The RGB
+    radiance of a path is lifted to a spectrum with sRGB
+    color matching functions as basis, so that converting
+    it back to sRGB through the CIE XYZ color matching
+    functions gives the same color on average. Scenes
+    without dispersion render the same as in RGB mode, only
+    with some color noise.
+
+    CIE 1931 color matching functions are the multi-lobe
+    Gaussian fit of Wyman, Sloan and Shirley (JCGT 2013).
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+#![cfg_attr(feature = "f32", allow(clippy::excessive_precision))] // Constants are written for f64
+
+use std::sync::OnceLock;
+
+use crate::numeric::{Float, Matrix3, Vector3};
+
+pub const LAMBDA_MIN: Float = 360.0; // nm
+pub const LAMBDA_MAX: Float = 830.0;
+pub const LAMBDA_D: Float = 589.3; // Sodium D line, refraction indices are usually given for it
+
+// Linear sRGB (D65) from CIE XYZ, written row by row
+const XYZ_TO_SRGB_ROWS: [[Float; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+fn piecewise_gaussian(x: Float, mu: Float, sigma_left: Float, sigma_right: Float) -> Float {
+    let t = (x - mu) / if x < mu { sigma_left } else { sigma_right };
+    (-0.5 * t * t).exp()
+}
+
+/// CIE 1931 2° color matching functions (x, y, z) at wavelength in nm
+pub fn cie_xyz(wavelength: Float) -> Vector3 {
+    let l = wavelength;
+    let x = 1.056 * piecewise_gaussian(l, 599.8, 37.9, 31.0) + 0.362 * piecewise_gaussian(l, 442.0, 16.0, 26.7)
+        - 0.065 * piecewise_gaussian(l, 501.1, 20.4, 26.2);
+    let y = 0.821 * piecewise_gaussian(l, 568.8, 46.9, 40.5) + 0.286 * piecewise_gaussian(l, 530.9, 16.3, 31.1);
+    let z = 1.217 * piecewise_gaussian(l, 437.0, 11.8, 36.0) + 0.681 * piecewise_gaussian(l, 459.0, 26.0, 13.8);
+    Vector3::new(x, y, z)
+}
+
+fn xyz_to_srgb() -> Matrix3 {
+    Matrix3::from_cols_array_2d(&XYZ_TO_SRGB_ROWS).transpose()
+}
+
+/// Color matching functions of linear sRGB primaries
+pub fn srgb_matching(wavelength: Float) -> Vector3 {
+    xyz_to_srgb() * cie_xyz(wavelength)
+}
+
+fn outer(a: Vector3, b: Vector3) -> Matrix3 {
+    Matrix3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+fn inverse_gram() -> Matrix3 {
+    // G = integral of r(l) r(l)^T over visible wavelengths, r being srgb_matching( )
+    static INVERSE_GRAM: OnceLock<Matrix3> = OnceLock::new();
+    *INVERSE_GRAM.get_or_init(|| {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let mut gram = Matrix3::ZERO;
+        for i in 0..steps {
+            let r = srgb_matching(LAMBDA_MIN + i as Float + 0.5);
+            gram += outer(r, r);
+        }
+        gram.inverse()
+    })
+}
+
+/// Wavelength in [LAMBDA_MIN, LAMBDA_MAX] and its pdf. Visible wavelengths are sampled
+/// more often, pdf is proportional to 1 / cosh^2(0.0072 (l - 538)) (see PBRT 4th ed. 4.6.6)
+pub fn sample_wavelength(u: Float) -> (Float, Float) {
+    let wavelength = 538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh();
+    let pdf = 0.0039398042 / (0.0072 * (wavelength - 538.0)).cosh().powi(2);
+    (wavelength, pdf)
+}
+
+/// Maps the RGB radiance of a path traced at a single wavelength to its sRGB
+/// contribution: radiance at that wavelength is r(l)^T G^-1 rgb, integrated
+/// against r(l) / pdf. Expected value of the matrix over wavelengths is identity.
+pub fn spectral_weight(wavelength: Float, pdf: Float) -> Matrix3 {
+    let r = srgb_matching(wavelength);
+    outer(r, r) * inverse_gram() / pdf
+}
+
+/// Refraction index n = A + B / l^2 + C / l^4, l in micrometers
+pub fn cauchy(coefficients: &[Float], wavelength: Float) -> Float {
+    let l2 = (wavelength * 1e-3).powi(2);
+    coefficients.iter().enumerate().map(|(i, c)| c / l2.powi(i as i32)).sum()
+}
+
+/// Refraction index n^2 = 1 + sum of B_i l^2 / (l^2 - C_i), l in micrometers
+pub fn sellmeier(coefficients: &[Float], wavelength: Float) -> Float {
+    let l2 = (wavelength * 1e-3).powi(2);
+    let (b, c) = coefficients.split_at(coefficients.len() / 2);
+    (1.0 + b.iter().zip(c).map(|(b, c)| b * l2 / (l2 - c)).sum::<Float>()).sqrt()
+}
+
+/// Linearly interpolated (n, k) of a table of (wavelength, n, k) triples sorted by wavelength,
+/// clamped to the first and last entries
+pub fn interpolate_nk(table: &[Float], wavelength: Float) -> Option<(Float, Float)> {
+    let entries: Vec<&[Float]> = table.chunks_exact(3).collect();
+    let first = entries.first()?;
+    let last = entries.last()?;
+    if wavelength <= first[0] {
+        return Some((first[1], first[2]));
+    }
+    if wavelength >= last[0] {
+        return Some((last[1], last[2]));
+    }
+    let i = entries.partition_point(|e| e[0] <= wavelength);
+    let (lo, hi) = (entries[i - 1], entries[i]);
+    let t = (wavelength - lo[0]) / (hi[0] - lo[0]);
+    Some((lo[1] + t * (hi[1] - lo[1]), lo[2] + t * (hi[2] - lo[2])))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spectral_weight_preserves_rgb() {
+        // Stratified average of weights over wavelengths is identity,
+        // so colors without dispersion are unchanged in spectral mode
+        // (stratified in primary sample space, so this checks the pdf as well)
+        let steps = 20000;
+        let mut sum = Matrix3::ZERO;
+        for i in 0..steps {
+            let (l, pdf) = sample_wavelength((i as Float + 0.5) / steps as Float);
+            sum += spectral_weight(l, pdf);
+        }
+        let mean = sum / steps as Float;
+        assert!(mean.abs_diff_eq(Matrix3::IDENTITY, 1e-3), "{}", mean);
+    }
+
+    #[test]
+    fn test_dispersion_formulas() {
+        // BK7 glass is 1.5168 at the D line
+        let sellmeier_bk7 = [1.03961212, 0.231792344, 1.01046945, 0.00600069867, 0.0200179144, 103.560653];
+        assert!((sellmeier(&sellmeier_bk7, LAMBDA_D) - 1.5168).abs() < 1e-3);
+        let cauchy_bk7 = [1.5046, 0.00420];
+        assert!((cauchy(&cauchy_bk7, LAMBDA_D) - 1.5167).abs() < 1e-3);
+        // Normal dispersion, blue bends more than red
+        assert!(sellmeier(&sellmeier_bk7, 450.0) > sellmeier(&sellmeier_bk7, 650.0));
+
+        let gold = [400.0, 1.66, 1.96, 500.0, 0.97, 1.87, 600.0, 0.25, 2.98];
+        let (n, k) = interpolate_nk(&gold, 550.0).unwrap();
+        assert!((n - 0.61).abs() < 1e-6 && (k - 2.425).abs() < 1e-6);
+        assert_eq!(interpolate_nk(&gold, 300.0), Some((1.66, 1.96)));
+        assert_eq!(interpolate_nk(&gold, 800.0), Some((0.25, 2.98)));
+        assert_eq!(interpolate_nk(&[], 500.0), None);
+    }
+}
@@ -0,0 +1,399 @@
+/*
+
+    Subdivision surfaces for meshes. A mesh is refined once
+    at load time, before it is split into triangles (see
+    SceneObjects::setup( )), e.g.
+        "Mesh": {
+            "_id": "1", "Material": "1", "_shadingMode": "smooth",
+            "Faces": { "_plyFile": "cube.ply" },
+            "Subdivision": { "Scheme": "catmull-clark", "Level": "3" }
+        }
+    Schemes are crease-free, boundaries of open meshes are
+    refined as cubic B-spline curves:
+        - loop: Loop (1987) for triangle meshes, other
+          polygons are split into triangles first
+        - catmull-clark: Catmull and Clark (1978) for any
+          polygons, gives quads after the first level
+    Every level multiplies the number of faces by 4 (by the
+    number of sides of a polygon for Catmull-Clark), so
+    levels above MAX_LEVEL are rejected, as are levels
+    that would give a mesh more than MAX_FACES faces.
+
+    Faces with more than 3 vertices come from PLY files or
+    Wavefront OBJ files ("_objFile" instead of "_plyFile",
+    only v and f lines are read), or from JSON faces of
+    _type "quad".
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{TracerError, TracerResult};
+use crate::json_parser::deser_usize;
+use crate::numeric::{Float, Vector3};
+
+pub const MAX_LEVEL: usize = 6;
+// Limit of a refined mesh, a low level of a large mesh would otherwise use up all memory
+pub const MAX_FACES: usize = 4_000_000;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SubdivisionScheme {
+    #[serde(rename = "loop")]
+    Loop,
+    #[serde(rename = "catmull-clark", alias = "catmullclark")]
+    CatmullClark,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+pub struct Subdivision {
+    #[serde(rename = "Scheme")]
+    pub scheme: SubdivisionScheme,
+    #[serde(rename = "Level", deserialize_with = "deser_usize")]
+    pub level: usize,
+}
+
+/// Mesh with vertices of its own, faces are polygons of vertex indices in counter clockwise order
+#[derive(Debug, Clone, Default)]
+pub struct PolygonMesh {
+    pub positions: Vec<Vector3>,
+    pub faces: Vec<Vec<usize>>,
+}
+
+// Edges of a mesh and the faces around them, an edge is shared by two
+// faces inside the mesh and belongs to a single one on its boundary
+struct Edges {
+    index: HashMap<(usize, usize), usize>,
+    vertices: Vec<(usize, usize)>,
+    faces: Vec<Vec<usize>>,
+}
+
+impl Edges {
+    fn build(faces: &[Vec<usize>]) -> Self {
+        let mut edges = Edges { index: HashMap::new(), vertices: Vec::new(), faces: Vec::new() };
+        for (f, face) in faces.iter().enumerate() {
+            for i in 0..face.len() {
+                let (a, b) = (face[i], face[(i + 1) % face.len()]);
+                let next = edges.vertices.len();
+                let e = *edges.index.entry((a.min(b), a.max(b))).or_insert(next);
+                if e == next {
+                    edges.vertices.push((a, b));
+                    edges.faces.push(Vec::new());
+                }
+                edges.faces[e].push(f);
+            }
+        }
+        edges
+    }
+
+    fn get(&self, a: usize, b: usize) -> usize {
+        self.index[&(a.min(b), a.max(b))]
+    }
+
+    fn is_boundary(&self, e: usize) -> bool {
+        self.faces[e].len() != 2
+    }
+
+    /// Neighbors of every vertex, and the ones along boundary edges
+    fn neighbors(&self, n_vertices: usize) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+        let mut neighbors = vec![Vec::new(); n_vertices];
+        let mut boundary = vec![Vec::new(); n_vertices];
+        for (e, &(a, b)) in self.vertices.iter().enumerate() {
+            neighbors[a].push(b);
+            neighbors[b].push(a);
+            if self.is_boundary(e) {
+                boundary[a].push(b);
+                boundary[b].push(a);
+            }
+        }
+        (neighbors, boundary)
+    }
+}
+
+/// Polygons split into triangles as fans around their first vertex
+pub fn triangulate(faces: &[Vec<usize>]) -> Vec<[usize; 3]> {
+    faces.iter()
+        .flat_map(|face| (1..face.len().saturating_sub(1)).map(|i| [face[0], face[i], face[i + 1]]))
+        .collect()
+}
+
+fn average(points: impl Iterator<Item = Vector3>) -> Vector3 {
+    let (sum, count) = points.fold((Vector3::ZERO, 0), |(sum, count), p| (sum + p, count + 1));
+    sum / count.max(1) as Float
+}
+
+impl PolygonMesh {
+    /// Mesh of the given faces referring to a shared vertex list (e.g. VertexData),
+    /// only vertices used by the faces are copied
+    pub fn from_shared(vertices: &[Vector3], faces: &[Vec<usize>]) -> Self {
+        let mut local: HashMap<usize, usize> = HashMap::new();
+        let mut positions = Vec::new();
+        let faces = faces.iter().map(|face| {
+            face.iter().map(|&v| *local.entry(v).or_insert_with(|| {
+                positions.push(vertices[v]);
+                positions.len() - 1
+            })).collect()
+        }).collect();
+        Self { positions, faces }
+    }
+
+    pub fn load_obj(path: &Path) -> TracerResult<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| TracerError::io(path, e))?;
+        Self::from_obj(&text).map_err(|message| TracerError::Obj { path: path.to_path_buf(), message })
+    }
+
+    /// Parse vertices and faces of a Wavefront OBJ file, other statements are ignored
+    pub fn from_obj(text: &str) -> Result<Self, String> {
+        let mut mesh = Self::default();
+        for (line_idx, line) in text.lines().enumerate() {
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => {
+                    let coords = tokens.take(3).map(|t| t.parse::<Float>()).collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| format!("line {}: {}", line_idx + 1, e))?;
+                    let [x, y, z] = coords[..] else {
+                        return Err(format!("line {}: vertex has {} coordinates, expected 3", line_idx + 1, coords.len()));
+                    };
+                    mesh.positions.push(Vector3::new(x, y, z));
+                }
+                Some("f") => {
+                    // Vertex references are v, v/vt, v//vn or v/vt/vn, starting from 1 or negative if relative to the end
+                    let face = tokens.map(|token| {
+                        let v: isize = token.split('/').next().unwrap_or("").parse().map_err(|e| format!("line {}: {}", line_idx + 1, e))?;
+                        let idx = if v < 0 { mesh.positions.len() as isize + v } else { v - 1 };
+                        if idx < 0 || idx as usize >= mesh.positions.len() {
+                            return Err(format!("line {}: vertex {} is not defined", line_idx + 1, v));
+                        }
+                        Ok(idx as usize)
+                    }).collect::<Result<Vec<_>, _>>()?;
+                    if face.len() < 3 {
+                        return Err(format!("line {}: face has {} vertices, expected at least 3", line_idx + 1, face.len()));
+                    }
+                    mesh.faces.push(face);
+                }
+                _ => {}
+            }
+        }
+        Ok(mesh)
+    }
+
+    /// Faces split into triangles as fans around their first vertex
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        triangulate(&self.faces)
+    }
+
+    /// Refine the mesh level times. Fails if the result would have more than MAX_FACES faces.
+    pub fn subdivide(&self, subdivision: Subdivision) -> Result<Self, String> {
+        let mut mesh = match subdivision.scheme {
+            SubdivisionScheme::Loop => Self { positions: self.positions.clone(), faces: self.triangles().iter().map(|t| t.to_vec()).collect() },
+            SubdivisionScheme::CatmullClark => self.clone(),
+        };
+        // Faces after the first level (a quad per side for Catmull-Clark), every other level multiplies them by 4
+        let first_level = match subdivision.scheme {
+            SubdivisionScheme::Loop => mesh.faces.len().saturating_mul(4),
+            SubdivisionScheme::CatmullClark => mesh.faces.iter().map(Vec::len).sum(),
+        };
+        let num_faces = match subdivision.level {
+            0 => mesh.faces.len(),
+            level => 4usize.checked_pow(level as u32 - 1).map_or(usize::MAX, |n| n.saturating_mul(first_level)),
+        };
+        if num_faces > MAX_FACES {
+            return Err(format!("level {} gives {} faces, more than the maximum of {}", subdivision.level, num_faces, MAX_FACES));
+        }
+        for _ in 0..subdivision.level {
+            mesh = match subdivision.scheme {
+                SubdivisionScheme::Loop => mesh.loop_step(),
+                SubdivisionScheme::CatmullClark => mesh.catmull_clark_step(),
+            };
+        }
+        Ok(mesh)
+    }
+
+    fn boundary_vertex(&self, v: usize, boundary: &[usize]) -> Vector3 {
+        // Vertices on a boundary follow it, corners (and non-manifold vertices) stay
+        match boundary {
+            [a, b] => self.positions[v] * 0.75 + (self.positions[*a] + self.positions[*b]) * 0.125,
+            _ => self.positions[v],
+        }
+    }
+
+    fn loop_step(&self) -> Self {
+        let n = self.positions.len();
+        let edges = Edges::build(&self.faces);
+        let (neighbors, boundary) = edges.neighbors(n);
+
+        // Old vertices are moved towards their neighbors (weights of Warren 1995)
+        let mut positions: Vec<Vector3> = (0..n).map(|v| {
+            let valence = neighbors[v].len();
+            if !boundary[v].is_empty() || valence == 0 {
+                return self.boundary_vertex(v, &boundary[v]);
+            }
+            let beta = if valence == 3 { 3.0 / 16.0 } else { 3.0 / (8.0 * valence as Float) };
+            let sum: Vector3 = neighbors[v].iter().map(|&u| self.positions[u]).sum();
+            self.positions[v] * (1.0 - valence as Float * beta) + sum * beta
+        }).collect();
+
+        // New vertex on every edge, weighted by the vertices opposite to it
+        for (e, &(a, b)) in edges.vertices.iter().enumerate() {
+            let (pa, pb) = (self.positions[a], self.positions[b]);
+            if edges.is_boundary(e) {
+                positions.push((pa + pb) * 0.5);
+                continue;
+            }
+            let opposite: Vector3 = edges.faces[e].iter()
+                .map(|&f| self.faces[f].iter().copied().find(|&v| v != a && v != b).unwrap_or(a))
+                .map(|v| self.positions[v])
+                .sum();
+            positions.push((pa + pb) * 0.375 + opposite * 0.125);
+        }
+
+        let faces = self.faces.iter().flat_map(|face| {
+            let [a, b, c] = [face[0], face[1], face[2]];
+            let [ab, bc, ca] = [(a, b), (b, c), (c, a)].map(|(u, v)| n + edges.get(u, v));
+            [vec![a, ab, ca], vec![ab, b, bc], vec![ca, bc, c], vec![ab, bc, ca]]
+        }).collect();
+        Self { positions, faces }
+    }
+
+    fn catmull_clark_step(&self) -> Self {
+        let n = self.positions.len();
+        let edges = Edges::build(&self.faces);
+        let (neighbors, boundary) = edges.neighbors(n);
+        let face_points: Vec<Vector3> = self.faces.iter()
+            .map(|face| average(face.iter().map(|&v| self.positions[v])))
+            .collect();
+        let mut vertex_faces = vec![Vec::new(); n];
+        for (f, face) in self.faces.iter().enumerate() {
+            for &v in face {
+                vertex_faces[v].push(f);
+            }
+        }
+
+        // Old vertices: (F + 2R + (k - 3) P) / k, F and R being averages of the
+        // face points around and of the midpoints of the edges, k the valence
+        let mut positions: Vec<Vector3> = (0..n).map(|v| {
+            let valence = neighbors[v].len();
+            if !boundary[v].is_empty() || valence == 0 {
+                return self.boundary_vertex(v, &boundary[v]);
+            }
+            let p = self.positions[v];
+            let f = average(vertex_faces[v].iter().map(|&f| face_points[f]));
+            let r = average(neighbors[v].iter().map(|&u| (p + self.positions[u]) * 0.5));
+            let k = valence as Float;
+            (f + r * 2.0 + p * (k - 3.0)) / k
+        }).collect();
+
+        // Edge points average the edge with the face points on both sides
+        for (e, &(a, b)) in edges.vertices.iter().enumerate() {
+            let (pa, pb) = (self.positions[a], self.positions[b]);
+            if edges.is_boundary(e) {
+                positions.push((pa + pb) * 0.5);
+            } else {
+                positions.push((pa + pb + face_points[edges.faces[e][0]] + face_points[edges.faces[e][1]]) * 0.25);
+            }
+        }
+        let face_offset = positions.len();
+        positions.extend(face_points);
+
+        // A quad for every corner of a face
+        let faces = self.faces.iter().enumerate().flat_map(|(f, face)| {
+            let k = face.len();
+            let edges = &edges;
+            (0..k).map(move |i| {
+                let (previous, v, next) = (face[(i + k - 1) % k], face[i], face[(i + 1) % k]);
+                vec![v, n + edges.get(v, next), face_offset + f, n + edges.get(previous, v)]
+            })
+        }).collect();
+        Self { positions, faces }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cube() -> PolygonMesh {
+        let positions = (0..8).map(|i| Vector3::new((i & 1) as Float, ((i >> 1) & 1) as Float, ((i >> 2) & 1) as Float) * 2.0 - 1.0).collect();
+        let faces = [[0, 2, 3, 1], [4, 5, 7, 6], [0, 1, 5, 4], [2, 6, 7, 3], [0, 4, 6, 2], [1, 3, 7, 5]];
+        PolygonMesh { positions, faces: faces.iter().map(|f| f.to_vec()).collect() }
+    }
+
+    #[test]
+    fn test_catmull_clark_cube() {
+        let level = |level| Subdivision { scheme: SubdivisionScheme::CatmullClark, level };
+        let once = cube().subdivide(level(1)).unwrap();
+        // V - E + F = 2 for a closed genus 0 mesh
+        assert_eq!((once.positions.len(), once.faces.len()), (8 + 12 + 6, 24));
+        // Known position of a corner after one step: (F + 2R + P) / 3
+        let corner = once.positions[7];
+        assert!(corner.abs_diff_eq(Vector3::splat(5.0 / 9.0), 1e-6), "{}", corner);
+
+        // Converges towards a rounded cube, staying inside the control cube
+        let thrice = cube().subdivide(level(3)).unwrap();
+        assert_eq!(thrice.faces.len(), 6 * 64);
+        assert!(thrice.positions.iter().all(|p| p.abs().max_element() <= 1.0));
+        let corner = thrice.positions[7].length();
+        assert!(corner > 0.8 && corner < 1.0, "{}", corner);
+    }
+
+    #[test]
+    fn test_catmull_clark_limit_of_cube() {
+        // Vertices of quads converge to (n^2 P + 4 sum(E) + sum(F)) / (n (n + 5)) over the n edge and n
+        // diagonal neighbors. Corners of the cube (n = 3) go to 1/2, centers of faces and of edges (n = 4
+        // after the first step, with corners at 5/9) to 68/81 and 395/648 along their directions
+        let refined = cube().subdivide(Subdivision { scheme: SubdivisionScheme::CatmullClark, level: 6 }).unwrap();
+        let along = |direction: Vector3| *refined.positions.iter()
+            .max_by(|a, b| a.normalize().dot(direction).total_cmp(&b.normalize().dot(direction)))
+            .unwrap();
+        assert!(along(Vector3::ONE).abs_diff_eq(Vector3::splat(0.5), 5e-4), "{}", along(Vector3::ONE));
+        assert!(along(Vector3::Z).abs_diff_eq(Vector3::Z * 68.0 / 81.0, 5e-4), "{}", along(Vector3::Z));
+        let edge = Vector3::new(1.0, 0.0, 1.0);
+        assert!(along(edge).abs_diff_eq(edge * 395.0 / 648.0, 5e-4), "{}", along(edge));
+    }
+
+    #[test]
+    fn test_face_budget() {
+        // 1000 quads give 4000 quads after one Catmull-Clark level and 4000 * 4^5 > MAX_FACES after six
+        let grid = PolygonMesh { positions: cube().positions, faces: vec![vec![0, 2, 3, 1]; 1000] };
+        for scheme in [SubdivisionScheme::CatmullClark, SubdivisionScheme::Loop] {
+            let problem = grid.subdivide(Subdivision { scheme, level: 6 }).unwrap_err();
+            assert!(problem.contains("more than the maximum"), "{}", problem);
+        }
+        assert_eq!(grid.subdivide(Subdivision { scheme: SubdivisionScheme::CatmullClark, level: 1 }).unwrap().faces.len(), 4000);
+    }
+
+    #[test]
+    fn test_loop_tetrahedron_and_boundary() {
+        let tetrahedron = PolygonMesh {
+            positions: vec![Vector3::new(1.0, 1.0, 1.0), Vector3::new(1.0, -1.0, -1.0), Vector3::new(-1.0, 1.0, -1.0), Vector3::new(-1.0, -1.0, 1.0)],
+            faces: vec![vec![0, 1, 2], vec![0, 3, 1], vec![0, 2, 3], vec![1, 3, 2]],
+        };
+        let refined = tetrahedron.subdivide(Subdivision { scheme: SubdivisionScheme::Loop, level: 2 }).unwrap();
+        assert_eq!((refined.positions.len(), refined.faces.len()), (34, 64));
+        // Symmetric, so vertices stay centered and are pulled inwards
+        assert!(average(refined.positions.iter().copied()).length() < 1e-9);
+        assert!(refined.positions.iter().all(|p| p.length() < (3.0 as Float).sqrt()));
+
+        // A flat open quad made of two triangles stays flat, boundary edges are split in the middle
+        let quad = PolygonMesh { positions: cube().positions[..4].to_vec(), faces: vec![vec![0, 1, 3, 2]] };
+        let refined = quad.subdivide(Subdivision { scheme: SubdivisionScheme::Loop, level: 1 }).unwrap();
+        assert_eq!(refined.faces.len(), 8);
+        assert!(refined.positions.iter().all(|p| p.z == -1.0));
+        assert!(refined.positions.contains(&Vector3::new(0.0, -1.0, -1.0)));
+    }
+
+    #[test]
+    fn test_obj() {
+        let obj = "# quad and a triangle\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\nf -4/1 -2/1 -1/1\n";
+        let mesh = PolygonMesh::from_obj(obj).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.faces, vec![vec![0, 1, 2, 3], vec![0, 2, 3]]);
+        assert_eq!(mesh.triangles(), vec![[0, 1, 2], [0, 2, 3], [0, 2, 3]]);
+        assert!(PolygonMesh::from_obj("v 0 0 0\nf 1 2 3").unwrap_err().starts_with("line 2"));
+    }
+}
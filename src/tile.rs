@@ -0,0 +1,149 @@
+/*
+
+    Split an image into rectangular tiles to be rendered
+    independently. Tiles can be visited in different orders:
+        - Scanline: row by row, from top-left
+        - Spiral: from the image center outwards, useful to see
+          the interesting part of the image first
+        - Hilbert: along a Hilbert curve, consecutive tiles are
+          close to each other which is friendlier to caches
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize, // Column of top-left pixel
+    pub y: usize, // Row of top-left pixel
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    pub fn pixel_count(&self) -> usize {
+        self.width * self.height
+    }
+
+    /// (col, row) of every pixel in the tile, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y + self.height).flat_map(move |row| (self.x..self.x + self.width).map(move |col| (col, row)))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileOrder {
+    Scanline,
+    #[default]
+    Spiral,
+    Hilbert,
+}
+
+impl FromStr for TileOrder {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "scanline" => Ok(TileOrder::Scanline),
+            "spiral" => Ok(TileOrder::Spiral),
+            "hilbert" => Ok(TileOrder::Hilbert),
+            other => Err(format!("Unknown tile order '{}', expected scanline, spiral or hilbert", other)),
+        }
+    }
+}
+
+/// Cover a width x height image with tiles of at most tile_size x tile_size pixels
+pub fn make_tiles(width: usize, height: usize, tile_size: usize, order: TileOrder) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let nx = width.div_ceil(tile_size);
+    let ny = height.div_ceil(tile_size);
+
+    let tile_at = |(tx, ty): (usize, usize)| {
+        let x = tx * tile_size;
+        let y = ty * tile_size;
+        Tile { x, y, width: tile_size.min(width - x), height: tile_size.min(height - y) }
+    };
+
+    let coords: Vec<(usize, usize)> = match order {
+        TileOrder::Scanline => (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect(),
+        TileOrder::Spiral => spiral_order(nx, ny),
+        TileOrder::Hilbert => {
+            let n = nx.max(ny).next_power_of_two();
+            let mut coords: Vec<(usize, usize)> = (0..ny).flat_map(|ty| (0..nx).map(move |tx| (tx, ty))).collect();
+            coords.sort_by_key(|&(tx, ty)| hilbert_index(n, tx, ty));
+            coords
+        }
+    };
+    coords.into_iter().map(tile_at).collect()
+}
+
+fn spiral_order(nx: usize, ny: usize) -> Vec<(usize, usize)> {
+    // Walk right, down, left, up with step lengths 1, 1, 2, 2, 3, 3, ...
+    // starting from the center tile, keep the ones inside the grid.
+    let total = nx * ny;
+    let mut coords = Vec::with_capacity(total);
+    let (mut x, mut y) = ((nx as i64 - 1) / 2, (ny as i64 - 1) / 2);
+    let directions = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+    let mut step = 1;
+    let mut dir = 0;
+    while coords.len() < total {
+        for _ in 0..2 {
+            let (dx, dy) = directions[dir % 4];
+            for _ in 0..step {
+                if x >= 0 && y >= 0 && (x as usize) < nx && (y as usize) < ny {
+                    coords.push((x as usize, y as usize));
+                }
+                x += dx;
+                y += dy;
+            }
+            dir += 1;
+        }
+        step += 1;
+    }
+    coords
+}
+
+fn hilbert_index(n: usize, x: usize, y: usize) -> usize {
+    // Distance of (x, y) along the Hilbert curve filling an n x n grid (n is a power of 2)
+    // DISCLAIMER: Based on xy2d in https://en.wikipedia.org/wiki/Hilbert_curve
+    let (mut x, mut y) = (x, y);
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = usize::from(x & s > 0);
+        let ry = usize::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        // Rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    d
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tiles_cover_image_once() {
+        for order in [TileOrder::Scanline, TileOrder::Spiral, TileOrder::Hilbert] {
+            let (width, height) = (70, 45);
+            let tiles = make_tiles(width, height, 16, order);
+            let mut covered = vec![0; width * height];
+            for tile in tiles.iter() {
+                for (col, row) in tile.pixels() {
+                    covered[row * width + col] += 1;
+                }
+            }
+            assert!(covered.iter().all(|&c| c == 1), "{:?} does not cover the image exactly once", order);
+        }
+    }
+}
@@ -0,0 +1,402 @@
+/*
+
+    Validate a Scene after it is loaded and set up, e.g.
+        - objects referring to materials that do not exist
+        - triangles, spheres, planes referring to vertices
+          that do not exist (vertex ids start from 1)
+        - degenerate triangles, zero-length plane normals,
+          non-positive sphere radii
+        - analytic shapes with non-positive sizes, zero-length
+          axes, or boxes whose Min is not below Max
+        - any of the above in operands of CSG trees
+        - SDFs without min and max corners of their bounds, or
+          with non-positive sizes, negative smoothness or periods
+        - Bézier patches without 16 control points or with a
+          non-positive tessellation tolerance
+        - cameras that cannot produce an image
+        - malformed dispersion coefficients or n/k spectra
+        - negative volume coefficients, anisotropy out of (-1, 1)
+        - voxel volumes without a volume material of their own
+        - subsurface albedo out of [0, 1], non-positive mean free path
+
+    Every problem found is reported with the object type and
+    its _id. In Lenient mode problems are only logged and the
+    renderer skips what it cannot handle; Strict mode refuses
+    to render a scene having any problem.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fmt;
+use tracing::warn;
+
+use crate::error::{TracerError, TracerResult};
+use crate::csg::{Csg, CsgOperand};
+use crate::geometry::triangle_area;
+use crate::material::MaterialKind;
+use crate::numeric::{approx_zero, Float, Vector3};
+use crate::scene::Scene;
+use crate::sdf::SdfNode;
+use crate::shapes::{Cuboid, PrimitiveShape};
+use crate::volume::HomogeneousVolume;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValidationMode {
+    #[default]
+    Lenient,
+    Strict,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub object: String,
+    pub id: usize,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(object: &str, id: usize, message: impl Into<String>) -> Self {
+        Self { object: object.to_string(), id, message: message.into() }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (_id {}): {}", self.object, self.id, self.message)
+    }
+}
+
+/// Log every problem of the scene, fail if mode is Strict and any problem is found
+pub fn check(scene: &Scene, mode: ValidationMode) -> TracerResult<Vec<Diagnostic>> {
+    let diagnostics = scene.validate();
+    for d in diagnostics.iter() {
+        warn!("Validation: {}", d);
+    }
+    if mode == ValidationMode::Strict && !diagnostics.is_empty() {
+        return Err(TracerError::InvalidScene(diagnostics));
+    }
+    Ok(diagnostics)
+}
+
+impl Scene {
+    /// Returns every problem found in the scene, empty if scene is valid.
+    /// WARNING: Expects setup_after_json( ) to be called beforehand.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        let checker = Checker { scene: self };
+
+        for cam in self.cameras.all() {
+            let id = cam._id as usize;
+            let [width, height] = cam.image_resolution;
+            if width == 0 || height == 0 {
+                diagnostics.push(Diagnostic::new("Camera", id, format!("image resolution {}x{} is empty", width, height)));
+            }
+            if cam.num_samples < 1 {
+                diagnostics.push(Diagnostic::new("Camera", id, format!("NumSamples is {}, expected at least 1", cam.num_samples)));
+            }
+            if cam.near_distance <= 0.0 {
+                diagnostics.push(Diagnostic::new("Camera", id, format!("NearDistance is {}, expected a positive value", cam.near_distance)));
+            }
+            let gaze = if cam._type == "lookAt" { cam.gaze_point - cam.position } else { cam.gaze_dir };
+            if approx_zero(gaze.length()) {
+                diagnostics.push(Diagnostic::new("Camera", id, "gaze direction has zero length"));
+            } else if approx_zero(gaze.normalize().cross(cam.up).length()) {
+                diagnostics.push(Diagnostic::new("Camera", id, "up vector is zero or parallel to gaze direction"));
+            }
+        }
+
+        for (i, mat) in self.materials.materials.iter().enumerate() {
+            let id = i + 1; // Objects refer to materials by position, ids start from 1
+            match mat {
+                MaterialKind::Dielectric(m) if m.sellmeier.len() % 2 != 0 => {
+                    diagnostics.push(Diagnostic::new("Material", id, format!(
+                        "SellmeierCoefficients has {} values, expected pairs of B and C (e.g. B1 B2 B3 C1 C2 C3)", m.sellmeier.len()
+                    )));
+                }
+                MaterialKind::Conductor(m) if m.nk_spectrum.len() % 3 != 0 => {
+                    diagnostics.push(Diagnostic::new("Material", id, format!(
+                        "NKSpectrum has {} values, expected (wavelength, n, k) triples", m.nk_spectrum.len()
+                    )));
+                }
+                MaterialKind::Conductor(m) if !m.nk_spectrum.chunks_exact(3).map(|e| e[0]).is_sorted_by(|a, b| a < b) => {
+                    diagnostics.push(Diagnostic::new("Material", id, "wavelengths of NKSpectrum are not increasing"));
+                }
+                MaterialKind::Subsurface(m) if m.mean_free_path.min_element() <= 0.0 => {
+                    diagnostics.push(Diagnostic::new("Material", id, format!("MeanFreePath is {}, expected positive values", m.mean_free_path)));
+                }
+                MaterialKind::Subsurface(m) if m.albedo.min_element() < 0.0 || m.albedo.max_element() > 1.0 => {
+                    diagnostics.push(Diagnostic::new("Material", id, format!("Albedo is {}, expected values in [0, 1]", m.albedo)));
+                }
+                MaterialKind::Volume(m) => {
+                    if let Some(problem) = volume_problem(&m.volume) {
+                        diagnostics.push(Diagnostic::new("Material", id, problem));
+                    }
+                }
+                _ => {}
+            }
+        }
+        if let Some(problem) = self.fog.as_ref().and_then(volume_problem) {
+            diagnostics.push(Diagnostic::new("Fog", 0, problem));
+        }
+
+        for tri in self.objects.triangles.all() {
+            checker.material("Triangle", tri._id, tri.material_idx, &mut diagnostics);
+            if checker.vertices("Triangle", tri._id, &tri.indices, &mut diagnostics) && checker.is_degenerate(tri.indices) {
+                diagnostics.push(Diagnostic::new("Triangle", tri._id, "triangle is degenerate (zero area)"));
+            }
+        }
+
+        for sphere in self.objects.spheres.all() {
+            checker.material("Sphere", sphere._id, sphere.material_idx, &mut diagnostics);
+            checker.vertices("Sphere", sphere._id, &[sphere.center_idx], &mut diagnostics);
+            if sphere.radius <= 0.0 {
+                diagnostics.push(Diagnostic::new("Sphere", sphere._id, format!("radius is {}, expected a positive value", sphere.radius)));
+            }
+        }
+
+        for plane in self.objects.planes.all() {
+            checker.material("Plane", plane._id, plane.material_idx, &mut diagnostics);
+            checker.vertices("Plane", plane._id, &[plane.point_idx], &mut diagnostics);
+            if approx_zero(plane.normal.length()) {
+                diagnostics.push(Diagnostic::new("Plane", plane._id, "normal has zero length"));
+            }
+        }
+
+        for disk in self.objects.disks.all() {
+            checker.material("Disk", disk._id, disk.material_idx, &mut diagnostics);
+            checker.vertices("Disk", disk._id, &[disk.center_idx], &mut diagnostics);
+            diagnostics.extend(axis_problem(disk.normal, "normal").map(|p| Diagnostic::new("Disk", disk._id, p)));
+            diagnostics.extend(size_problem(&[("radius", disk.radius)]).map(|p| Diagnostic::new("Disk", disk._id, p)));
+        }
+
+        for cylinder in self.objects.cylinders.all() {
+            checker.material("Cylinder", cylinder._id, cylinder.material_idx, &mut diagnostics);
+            checker.vertices("Cylinder", cylinder._id, &[cylinder.base_idx], &mut diagnostics);
+            diagnostics.extend(axis_problem(cylinder.axis, "axis").map(|p| Diagnostic::new("Cylinder", cylinder._id, p)));
+            diagnostics.extend(size_problem(&[("radius", cylinder.radius), ("height", cylinder.height)]).map(|p| Diagnostic::new("Cylinder", cylinder._id, p)));
+        }
+
+        for cone in self.objects.cones.all() {
+            checker.material("Cone", cone._id, cone.material_idx, &mut diagnostics);
+            checker.vertices("Cone", cone._id, &[cone.base_idx], &mut diagnostics);
+            diagnostics.extend(axis_problem(cone.axis, "axis").map(|p| Diagnostic::new("Cone", cone._id, p)));
+            diagnostics.extend(size_problem(&[("radius", cone.radius), ("height", cone.height)]).map(|p| Diagnostic::new("Cone", cone._id, p)));
+        }
+
+        for cuboid in self.objects.boxes.all() {
+            checker.material("Box", cuboid._id, cuboid.material_idx, &mut diagnostics);
+            if checker.vertices("Box", cuboid._id, &[cuboid.min_idx, cuboid.max_idx], &mut diagnostics) {
+                diagnostics.extend(checker.box_problem(&cuboid).map(|p| Diagnostic::new("Box", cuboid._id, p)));
+            }
+        }
+
+        for torus in self.objects.tori.all() {
+            checker.material("Torus", torus._id, torus.material_idx, &mut diagnostics);
+            checker.vertices("Torus", torus._id, &[torus.center_idx], &mut diagnostics);
+            diagnostics.extend(axis_problem(torus.axis, "axis").map(|p| Diagnostic::new("Torus", torus._id, p)));
+            diagnostics.extend(size_problem(&[("major radius", torus.major_radius), ("minor radius", torus.minor_radius)]).map(|p| Diagnostic::new("Torus", torus._id, p)));
+        }
+
+        // Problems in operands are reported under the root of their tree
+        for csg in self.objects.csgs.all() {
+            checker.csg(&csg, csg._id, false, &mut diagnostics);
+        }
+
+        for sdf in self.objects.sdfs.all() {
+            checker.material("SDF", sdf._id, sdf.material_idx, &mut diagnostics);
+            checker.vertices("SDF", sdf._id, &[sdf.center_idx], &mut diagnostics);
+            match sdf.bounds[..] {
+                [min, max] if (max - min).min_element() > 0.0 => {}
+                _ => diagnostics.push(Diagnostic::new("SDF", sdf._id, "Bounds must be min and max corners, min below max on every axis")),
+            }
+            diagnostics.extend(sdf_problem(&sdf.node).map(|p| Diagnostic::new("SDF", sdf._id, p)));
+        }
+
+        for patch in self.objects.bezier_patches.all() {
+            checker.material("BezierPatch", patch._id, patch.material_idx, &mut diagnostics);
+            checker.vertices("BezierPatch", patch._id, &patch.control_points, &mut diagnostics);
+            if patch.control_points.len() != 16 {
+                diagnostics.push(Diagnostic::new("BezierPatch", patch._id, format!("has {} control points, expected 16", patch.control_points.len())));
+            }
+            if patch.tolerance.is_nan() || patch.tolerance <= 0.0 {
+                diagnostics.push(Diagnostic::new("BezierPatch", patch._id, format!("Tolerance must be positive, got {}", patch.tolerance)));
+            }
+        }
+
+        for mesh in self.objects.meshes.all() {
+            checker.material("Mesh", mesh._id, mesh.material_idx, &mut diagnostics);
+            if mesh.faces._type != "triangle" {
+                continue; // Already rejected during setup
+            }
+            if mesh.faces._data.len() % 3 != 0 {
+                diagnostics.push(Diagnostic::new("Mesh", mesh._id, format!("has {} face indices, expected a multiple of 3", mesh.faces._data.len())));
+            }
+            // Report per mesh rather than per face, PLY meshes can have thousands of them 
+            let mut invalid_faces = Vec::new();
+            let mut degenerate_faces = Vec::new();
+            for i in 0..mesh.faces.len() {
+                let indices = mesh.faces.get_indices(i);
+                if !indices.iter().all(|&v| checker.vertex_exists(v)) {
+                    invalid_faces.push(i);
+                } else if checker.is_degenerate(indices) {
+                    degenerate_faces.push(i);
+                }
+            }
+            if !invalid_faces.is_empty() {
+                diagnostics.push(Diagnostic::new("Mesh", mesh._id, format!(
+                    "{} face(s) refer to vertices that do not exist (first is face {}, there are {} vertices)",
+                    invalid_faces.len(), invalid_faces[0], checker.vertex_count()
+                )));
+            }
+            if !degenerate_faces.is_empty() {
+                diagnostics.push(Diagnostic::new("Mesh", mesh._id, format!(
+                    "{} face(s) are degenerate (first is face {})", degenerate_faces.len(), degenerate_faces[0]
+                )));
+            }
+        }
+
+        // Media of voxel volumes are found by material, so it must be a volume of its own
+        let volumes = self.objects.voxel_volumes.all();
+        for volume in volumes.iter() {
+            checker.material("VoxelVolume", volume._id, volume.material_idx, &mut diagnostics);
+            let material = volume.material_idx.checked_sub(1).and_then(|i| self.materials.materials.get(i));
+            if material.is_some_and(|m| !matches!(m, MaterialKind::Volume(_))) {
+                diagnostics.push(Diagnostic::new("VoxelVolume", volume._id, format!("material {} is not of _type volume", volume.material_idx)));
+            }
+            // Shapes include the volume itself
+            let users = self.objects.all_shapes.iter().filter(|s| s.material_idx() == volume.material_idx).count();
+            if users > 1 {
+                diagnostics.push(Diagnostic::new("VoxelVolume", volume._id, format!(
+                    "material {} is used by other objects as well, they would share the density grid", volume.material_idx
+                )));
+            }
+        }
+
+        diagnostics
+    }
+}
+
+fn axis_problem(axis: Vector3, name: &str) -> Option<String> {
+    approx_zero(axis.length()).then(|| format!("{} has zero length", name))
+}
+
+fn size_problem(sizes: &[(&str, Float)]) -> Option<String> {
+    sizes.iter()
+        .find(|(_, size)| *size <= 0.0)
+        .map(|(name, size)| format!("{} is {}, expected a positive value", name, size))
+}
+
+fn sdf_problem(node: &SdfNode) -> Option<String> {
+    let problem = match node {
+        SdfNode::Sphere { radius, .. } => size_problem(&[("sphere radius", *radius)]),
+        SdfNode::Cuboid { half_size, .. } => size_problem(&[("box half size", half_size.min_element())]),
+        SdfNode::Torus { major_radius, minor_radius, .. } => size_problem(&[("torus major radius", *major_radius), ("torus minor radius", *minor_radius)]),
+        SdfNode::Capsule { radius, .. } => size_problem(&[("capsule radius", *radius)]),
+        SdfNode::SmoothUnion { smoothness, .. } | SdfNode::Subtraction { smoothness, .. } if *smoothness < 0.0 => {
+            Some(format!("Smoothness is {}, expected a non-negative value", smoothness))
+        }
+        SdfNode::Repetition { period, .. } if period.min_element() < 0.0 => {
+            Some(format!("Period is {}, expected non-negative values", period))
+        }
+        _ => None,
+    };
+    problem.or_else(|| node.children().into_iter().find_map(sdf_problem))
+}
+
+fn volume_problem(volume: &HomogeneousVolume) -> Option<String> {
+    if volume.absorption_coeff.min_element() < 0.0 || volume.scattering_coeff.min_element() < 0.0 {
+        Some("AbsorptionCoefficient and ScatteringCoefficient must not be negative".to_string())
+    } else if volume.anisotropy.abs() >= 1.0 {
+        Some(format!("Anisotropy is {}, expected a value in (-1, 1)", volume.anisotropy))
+    } else {
+        None
+    }
+}
+
+// Helper to share lookups between object types
+struct Checker<'a> {
+    scene: &'a Scene,
+}
+
+impl Checker<'_> {
+    fn vertex_count(&self) -> usize {
+        // Index 0 is the dummy vertex inserted during setup
+        self.scene.vertex_data._data.len().saturating_sub(1)
+    }
+
+    fn vertex_exists(&self, idx: usize) -> bool {
+        idx >= 1 && idx < self.scene.vertex_data._data.len()
+    }
+
+    fn vertices(&self, object: &str, id: usize, indices: &[usize], diagnostics: &mut Vec<Diagnostic>) -> bool {
+        let mut valid = true;
+        for &idx in indices {
+            if !self.vertex_exists(idx) {
+                diagnostics.push(Diagnostic::new(object, id, format!(
+                    "refers to vertex {} but vertex ids are in [1, {}]", idx, self.vertex_count()
+                )));
+                valid = false;
+            }
+        }
+        valid
+    }
+
+    fn material(&self, object: &str, id: usize, material_idx: usize, diagnostics: &mut Vec<Diagnostic>) {
+        let n_materials = self.scene.materials.materials.len();
+        if material_idx == 0 || material_idx > n_materials {
+            diagnostics.push(Diagnostic::new(object, id, format!(
+                "refers to material {} but material ids are in [1, {}]", material_idx, n_materials
+            )));
+        }
+    }
+
+    fn box_problem(&self, cuboid: &Cuboid) -> Option<String> {
+        let size = self.scene.vertex_data[cuboid.max_idx] - self.scene.vertex_data[cuboid.min_idx];
+        (size.min_element() <= 0.0).then(|| format!("size is {}, Min must be below Max on every axis", size))
+    }
+
+    fn csg(&self, node: &Csg, root_id: usize, overridden: bool, diagnostics: &mut Vec<Diagnostic>) {
+        // Materials of operands are not used if an ancestor overrides them
+        if let Some(material_idx) = node.material_idx.filter(|_| !overridden) {
+            self.material("CSG", root_id, material_idx, diagnostics);
+        }
+        let overridden = overridden || node.material_idx.is_some();
+        for operand in [&node.left, &node.right] {
+            let (name, shape) = match operand {
+                CsgOperand::Csg(inner) => {
+                    self.csg(inner, root_id, overridden, diagnostics);
+                    continue;
+                }
+                CsgOperand::Sphere(s) => ("Sphere", s as &dyn PrimitiveShape),
+                CsgOperand::Cylinder(c) => ("Cylinder", c as &dyn PrimitiveShape),
+                CsgOperand::Cone(c) => ("Cone", c as &dyn PrimitiveShape),
+                CsgOperand::Cuboid(b) => ("Box", b as &dyn PrimitiveShape),
+                CsgOperand::Torus(t) => ("Torus", t as &dyn PrimitiveShape),
+            };
+            if !overridden {
+                self.material("CSG", root_id, shape.material_idx(), diagnostics);
+            }
+            if !self.vertices("CSG", root_id, &shape.indices(), diagnostics) {
+                continue;
+            }
+            let problem = match operand {
+                CsgOperand::Sphere(s) => size_problem(&[("radius", s.radius)]),
+                CsgOperand::Cylinder(c) => axis_problem(c.axis, "axis").or_else(|| size_problem(&[("radius", c.radius), ("height", c.height)])),
+                CsgOperand::Cone(c) => axis_problem(c.axis, "axis").or_else(|| size_problem(&[("radius", c.radius), ("height", c.height)])),
+                CsgOperand::Cuboid(b) => self.box_problem(b),
+                CsgOperand::Torus(t) => axis_problem(t.axis, "axis")
+                    .or_else(|| size_problem(&[("major radius", t.major_radius), ("minor radius", t.minor_radius)])),
+                CsgOperand::Csg(_) => None,
+            };
+            if let Some(problem) = problem {
+                diagnostics.push(Diagnostic::new("CSG", root_id, format!("{} operand of node {}: {}", name, node._id, problem)));
+            }
+        }
+    }
+
+    fn is_degenerate(&self, indices: [usize; 3]) -> bool {
+        let [a, b, c]: [Vector3; 3] = indices.map(|i| self.scene.vertex_data[i]);
+        triangle_area(&a, &b, &c) < Float::EPSILON
+    }
+}
@@ -0,0 +1,397 @@
+/*
+
+    Homogeneous participating media, e.g. fog or smoke.
+    A medium absorbs and scatters light with constant
+    coefficients (per unit distance, per RGB channel) and
+    scatters it by the Henyey-Greenstein phase function
+    where Anisotropy g in (-1, 1) is negative for back,
+    zero for isotropic and positive for forward scattering.
+
+    Volumes are either bounded by a closed mesh or a sphere
+    whose material is of _type "volume", e.g.
+        "Material": { "_id": "3", "_type": "volume",
+                      "AbsorptionCoefficient": "0.05 0.05 0.05",
+                      "ScatteringCoefficient": "0.5 0.5 0.5",
+                      "Anisotropy": "0.3" }
+    surfaces of which are invisible and only let the ray
+    enter or leave the medium (see MediumStack in ray.rs),
+    or they fill the whole scene as fog:
+        "Fog": { "ScatteringCoefficient": "0.02 0.02 0.02" }
+
+    Renderer samples a free-flight distance along rays in
+    scattering media, scatters there with light sampling
+    (shadow rays are attenuated by the media they cross)
+    and continues along a direction sampled from the phase
+    function, see renderer::in_scattered( ).
+
+    Heterogeneous volumes, e.g. smoke caches of simulations,
+    are VoxelVolume objects whose density varies in a box:
+        "VoxelVolume": { "_id": "1", "Material": "3", "_gridFile": "smoke.vol" }
+    Coefficients of the material are then per unit density.
+    Grid files are dense grids in Mitsuba's .vol format, a
+    header (little endian)
+        "VOL", version 3 (u8), encoding 1 (i32, float32),
+        resolution x y z (i32), channels (i32),
+        bounding box min x y z, max x y z (f32)
+    followed by resolution x * y * z * channels f32 values,
+    x changing fastest. Channels are averaged. Free-flight
+    distances are sampled by delta tracking and shadow rays
+    are attenuated by ratio tracking against the majorant
+    (maximum extinction) of the grid.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::path::Path;
+use serde::Deserialize;
+
+use crate::error::{TracerError, TracerResult};
+use crate::geometry::ray_box_overlap;
+use crate::interval::FloatConst;
+use crate::json_parser::{deser_float, deser_vec3};
+use crate::numeric::{Float, Vector3};
+use crate::ray::{Medium, Ray};
+use crate::sampler::Sampler;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HomogeneousVolume {
+    #[serde(rename = "AbsorptionCoefficient", deserialize_with = "deser_vec3")]
+    pub absorption_coeff: Vector3,
+    #[serde(rename = "ScatteringCoefficient", deserialize_with = "deser_vec3")]
+    pub scattering_coeff: Vector3,
+    #[serde(rename = "Anisotropy", deserialize_with = "deser_float")]
+    pub anisotropy: Float,
+}
+
+impl HomogeneousVolume {
+    /// Medium of the volume, material is the id its boundaries are hit with (0 for fog)
+    pub fn medium(&self, material: usize) -> Medium {
+        Medium {
+            material,
+            absorption_coeff: self.absorption_coeff,
+            scattering_coeff: self.scattering_coeff,
+            anisotropy: self.anisotropy,
+            ..Medium::AIR // Index matched, volumes do not refract
+        }
+    }
+}
+
+/// Henyey-Greenstein phase function, cos_theta is between the propagation
+/// directions before and after scattering, integrates to 1 over the sphere
+pub fn henyey_greenstein(cos_theta: Float, g: Float) -> Float {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * Float::PI * denom * denom.sqrt())
+}
+
+/// Direction scattered from a ray travelling along direction, pdf is henyey_greenstein( )
+pub fn sample_henyey_greenstein(direction: Vector3, g: Float, u: (Float, Float)) -> Vector3 {
+    // Inverse of the CDF of cos_theta
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * u.0
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u.0);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * Float::PI * u.1;
+    let (t, b) = direction.any_orthonormal_pair();
+    (t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + direction * cos_theta).normalize()
+}
+
+/// Outcome of sampling how far a ray travels in a medium before it scatters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FreeFlight {
+    /// Scattered at t, weight is transmittance * scattering coefficient / pdf
+    Scatter { t: Float, weight: Vector3 },
+    /// Reached t_max (the closest surface), weight is transmittance / probability
+    Pass { weight: Vector3 },
+    /// Absorbed on the way, nothing reaches the ray origin (only in heterogeneous media)
+    Absorbed,
+}
+
+/// Free-flight distance sampled proportional to the transmittance of a single channel, pdf is
+/// the average of the ones of all channels (one-sample MIS). Channels are picked proportional
+/// to the throughput of the path so far, which keeps weights of long paths (e.g. random walks
+/// of SubsurfaceMaterial) bounded when channels differ a lot.
+pub fn sample_free_flight(medium: &Medium, t_max: Float, u: Float, throughput: Vector3) -> FreeFlight {
+    let extinction = medium.extinction_coeff();
+    let channel_pdf = if throughput.element_sum() > 0.0 { throughput / throughput.element_sum() } else { Vector3::splat(1.0 / 3.0) };
+    let channel = if u < channel_pdf.x { 0 } else if u < channel_pdf.x + channel_pdf.y { 1 } else { 2 };
+    let cdf_below = channel_pdf.to_array()[..channel].iter().sum::<Float>();
+    let u = ((u - cdf_below) / channel_pdf[channel]).clamp(0.0, 1.0 - Float::EPSILON); // Reuse the rest of u
+    let t = -(1.0 - u).ln() / extinction[channel];
+    if t < t_max {
+        let transmittance = medium.beers_law_attenuation(t);
+        let pdf = (channel_pdf * extinction * transmittance).element_sum();
+        FreeFlight::Scatter { t, weight: transmittance * medium.scattering_coeff / pdf }
+    } else {
+        // Channels that are not attenuated at all pass through to infinity as well
+        let transmittance = Vector3::select(extinction.cmpeq(Vector3::ZERO), Vector3::ONE, medium.beers_law_attenuation(t_max));
+        let probability = (channel_pdf * transmittance).element_sum();
+        FreeFlight::Pass { weight: transmittance / probability }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DensityGrid {
+    pub resolution: [usize; 3],
+    pub min: Vector3, // Bounding box in world space
+    pub max: Vector3,
+    pub max_density: Float,
+    densities: Vec<f32>, // x changes fastest, then y, then z
+}
+
+impl DensityGrid {
+    pub fn new(resolution: [usize; 3], min: Vector3, max: Vector3, densities: Vec<f32>) -> Self {
+        debug_assert_eq!(densities.len(), resolution.iter().product::<usize>());
+        let max_density = densities.iter().fold(0.0, |m: Float, &d| m.max(d as Float));
+        Self { resolution, min, max, max_density, densities }
+    }
+
+    pub fn load(path: &Path) -> TracerResult<Self> {
+        let bytes = std::fs::read(path).map_err(|e| TracerError::io(path, e))?;
+        Self::from_vol(&bytes).map_err(|message| TracerError::Grid { path: path.to_path_buf(), message })
+    }
+
+    /// Parse a grid in Mitsuba's .vol format (see the top of this file)
+    pub fn from_vol(bytes: &[u8]) -> Result<Self, String> {
+        const HEADER_SIZE: usize = 48;
+        if bytes.len() < HEADER_SIZE || &bytes[0..3] != b"VOL" {
+            return Err("not a .vol file, expected it to start with 'VOL'".to_string());
+        }
+        if bytes[3] != 3 {
+            return Err(format!("version {} is not supported, expected 3", bytes[3]));
+        }
+        let word = |i: usize| -> [u8; 4] { bytes[4 + 4 * i..8 + 4 * i].try_into().unwrap() };
+        let int = |i: usize| i32::from_le_bytes(word(i));
+        let float = |i: usize| f32::from_le_bytes(word(i)) as Float;
+        if int(0) != 1 {
+            return Err(format!("encoding {} is not supported, expected 1 (float32)", int(0)));
+        }
+        let (res, channels) = ([int(1), int(2), int(3)], int(4));
+        if res.iter().any(|&r| r < 1) || channels < 1 {
+            return Err(format!("resolution {:?} with {} channel(s) is empty", res, channels));
+        }
+        let resolution = res.map(|r| r as usize);
+        let channels = channels as usize;
+        let count = resolution.iter().product::<usize>();
+        let data = &bytes[HEADER_SIZE..];
+        if data.len() != count * channels * 4 {
+            return Err(format!("expected {} values after the header, found {} bytes", count * channels, data.len()));
+        }
+        let densities = data
+            .chunks_exact(4 * channels)
+            .map(|voxel| voxel.chunks_exact(4).map(|c| f32::from_le_bytes(c.try_into().unwrap())).sum::<f32>() / channels as f32)
+            .collect();
+        let min = Vector3::new(float(5), float(6), float(7));
+        let max = Vector3::new(float(8), float(9), float(10));
+        if !(min.cmplt(max).all()) {
+            return Err(format!("bounding box from {} to {} is empty", min, max));
+        }
+        Ok(Self::new(resolution, min, max, densities))
+    }
+
+    fn voxel(&self, x: usize, y: usize, z: usize) -> Float {
+        let [nx, ny, _] = self.resolution;
+        self.densities[(z * ny + y) * nx + x] as Float
+    }
+
+    /// Trilinearly interpolated density at a world space point, voxel values are at voxel centers
+    pub fn density(&self, point: Vector3) -> Float {
+        let local = (point - self.min) / (self.max - self.min);
+        if local.cmplt(Vector3::ZERO).any() || local.cmpgt(Vector3::ONE).any() {
+            return 0.0;
+        }
+        let res = Vector3::new(self.resolution[0] as Float, self.resolution[1] as Float, self.resolution[2] as Float);
+        let p = (local * res - 0.5).clamp(Vector3::ZERO, res - 1.0);
+        let lo = p.floor();
+        let f = p - lo;
+        let [x0, y0, z0] = [lo.x as usize, lo.y as usize, lo.z as usize];
+        let [x1, y1, z1] = [0, 1, 2].map(|i| ([x0, y0, z0][i] + 1).min(self.resolution[i] - 1));
+        let lerp = |a: Float, b: Float, t: Float| a + (b - a) * t;
+        let c00 = lerp(self.voxel(x0, y0, z0), self.voxel(x1, y0, z0), f.x);
+        let c10 = lerp(self.voxel(x0, y1, z0), self.voxel(x1, y1, z0), f.x);
+        let c01 = lerp(self.voxel(x0, y0, z1), self.voxel(x1, y0, z1), f.x);
+        let c11 = lerp(self.voxel(x0, y1, z1), self.voxel(x1, y1, z1), f.x);
+        lerp(lerp(c00, c10, f.y), lerp(c01, c11, f.y), f.z)
+    }
+
+    /// Distances (entry, exit) of the ray to the bounding box by the slab method, None if it misses.
+    /// Entry is the latest of the slab entries and exit the earliest of the exits.
+    pub fn ray_overlap(&self, ray: &Ray) -> Option<(Float, Float)> {
+        ray_box_overlap(ray.origin, ray.direction, self.min, self.max)
+    }
+
+    /// Extinction bounding the one of every point in the grid
+    fn majorant(&self, medium: &Medium) -> Float {
+        self.max_density * medium.extinction_coeff().max_element()
+    }
+
+    /// Free-flight sampling by delta tracking (spectral tracking of Kutz et al. 2017
+    /// for RGB), coefficients of the medium are scaled by the density along the ray
+    pub fn sample_free_flight(&self, medium: &Medium, ray: &Ray, t_max: Float, sampler: &mut Sampler) -> FreeFlight {
+        let majorant = self.majorant(medium);
+        // Density is zero out of the box, which also ends tracking if the ray missed leaving it
+        let Some((t_enter, t_exit)) = self.ray_overlap(ray).filter(|_| majorant > 0.0) else {
+            return FreeFlight::Pass { weight: Vector3::ONE };
+        };
+        let t_end = t_max.min(t_exit);
+        let mut weight = Vector3::ONE;
+        let mut t = t_enter.max(0.0);
+        loop {
+            t -= (1.0 - sampler.next_float()).ln() / majorant;
+            if t >= t_end {
+                return FreeFlight::Pass { weight };
+            }
+            // Tentative collision is real scattering, absorption or a null collision
+            // with probabilities of mean coefficients, weights correct the channels
+            let density = self.density(ray.at(t));
+            let scattering = medium.scattering_coeff * density;
+            let extinction = medium.extinction_coeff() * density;
+            let p_scatter = scattering.element_sum() / (3.0 * majorant);
+            let p_null = 1.0 - extinction.element_sum() / (3.0 * majorant);
+            let u = sampler.next_float();
+            if u < p_scatter {
+                return FreeFlight::Scatter { t, weight: weight * scattering / (majorant * p_scatter) };
+            } else if u < p_scatter + p_null {
+                weight *= (Vector3::splat(majorant) - extinction) / (majorant * p_null);
+            } else {
+                return FreeFlight::Absorbed;
+            }
+        }
+    }
+
+    /// Transmittance from the ray origin to distance t_max by ratio tracking
+    pub fn transmittance(&self, medium: &Medium, ray: &Ray, t_max: Float, sampler: &mut Sampler) -> Vector3 {
+        let majorant = self.majorant(medium);
+        let Some((t_enter, t_exit)) = self.ray_overlap(ray).filter(|_| majorant > 0.0) else {
+            return Vector3::ONE;
+        };
+        let t_end = t_max.min(t_exit);
+        let mut transmittance = Vector3::ONE;
+        let mut t = t_enter.max(0.0);
+        loop {
+            t -= (1.0 - sampler.next_float()).ln() / majorant;
+            if t >= t_end || transmittance.max_element() <= 0.0 {
+                return transmittance;
+            }
+            let extinction = medium.extinction_coeff() * self.density(ray.at(t));
+            transmittance *= Vector3::ONE - extinction / majorant;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn test_henyey_greenstein_sampling() {
+        let d = Vector3::new(1.0, 2.0, 2.0) / 3.0;
+        let mut sampler = Sampler::new(5, 7);
+        for g in [-0.6, 0.0, 0.3, 0.9] {
+            // Integrates to 1 over the sphere (midpoint rule in cos_theta)
+            let steps = 100_000;
+            let integral: Float = (0..steps)
+                .map(|i| henyey_greenstein(-1.0 + 2.0 * (i as Float + 0.5) / steps as Float, g) * 2.0 / steps as Float)
+                .sum::<Float>() * 2.0 * Float::PI;
+            assert!((integral - 1.0).abs() < 1e-3, "g = {}: {}", g, integral);
+
+            // Mean cosine of sampled directions is g
+            let num_samples = 50_000;
+            let mean_cos = (0..num_samples)
+                .map(|_| sample_henyey_greenstein(d, g, sampler.next_2d()).dot(d))
+                .sum::<Float>() / num_samples as Float;
+            assert!((mean_cos - g).abs() < 0.01, "g = {}: {}", g, mean_cos);
+        }
+    }
+
+    #[test]
+    fn test_free_flight_is_unbiased() {
+        // Expected weight of passing equals transmittance, and expected scattering weight
+        // equals the integral of transmittance * scattering coefficient up to t_max
+        let medium = HomogeneousVolume {
+            absorption_coeff: Vector3::new(0.1, 0.2, 0.0),
+            scattering_coeff: Vector3::new(0.5, 0.3, 0.9),
+            anisotropy: 0.0,
+        }.medium(1);
+        let t_max = 2.0;
+        let num_samples = 100_000;
+        let (mut passed, mut scattered) = (Vector3::ZERO, Vector3::ZERO);
+        for i in 0..num_samples {
+            match sample_free_flight(&medium, t_max, (i as Float + 0.5) / num_samples as Float, Vector3::ONE) {
+                FreeFlight::Scatter { t, weight } => {
+                    assert!(t < t_max);
+                    scattered += weight;
+                }
+                FreeFlight::Pass { weight } => passed += weight,
+                FreeFlight::Absorbed => panic!("homogeneous media only attenuate by weights"),
+            }
+        }
+        let extinction = medium.extinction_coeff();
+        let transmittance = medium.beers_law_attenuation(t_max);
+        let expected = medium.scattering_coeff / extinction * (Vector3::ONE - transmittance);
+        assert!((passed / num_samples as Float).abs_diff_eq(transmittance, 1e-3), "{}", passed / num_samples as Float);
+        assert!((scattered / num_samples as Float).abs_diff_eq(expected, 1e-3), "{}", scattered / num_samples as Float);
+    }
+
+    fn vol_bytes(resolution: [i32; 3], channels: i32, bounds: [f32; 6], values: &[f32]) -> Vec<u8> {
+        let mut bytes = b"VOL".to_vec();
+        bytes.push(3);
+        for i in [1, resolution[0], resolution[1], resolution[2], channels] {
+            bytes.extend(i.to_le_bytes());
+        }
+        for f in bounds.iter().chain(values) {
+            bytes.extend(f.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_load_vol_and_interpolate() {
+        // 2x1x1 grid with two channels, averaged to 1 and 3
+        let bytes = vol_bytes([2, 1, 1], 2, [0.0, 0.0, 0.0, 2.0, 1.0, 1.0], &[0.5, 1.5, 2.0, 4.0]);
+        let grid = DensityGrid::from_vol(&bytes).unwrap();
+        assert_eq!(grid.resolution, [2, 1, 1]);
+        assert_eq!(grid.max_density, 3.0);
+        // Values are at voxel centers, clamped towards the faces and zero outside
+        assert!((grid.density(Vector3::new(0.5, 0.5, 0.5)) - 1.0).abs() < 1e-6);
+        assert!((grid.density(Vector3::new(1.0, 0.2, 0.9)) - 2.0).abs() < 1e-6);
+        assert!((grid.density(Vector3::new(1.9, 0.5, 0.5)) - 3.0).abs() < 1e-6);
+        assert_eq!(grid.density(Vector3::new(2.1, 0.5, 0.5)), 0.0);
+
+        assert!(DensityGrid::from_vol(&bytes[..bytes.len() - 4]).is_err());
+        assert!(DensityGrid::from_vol(b"PLY").is_err());
+    }
+
+    #[test]
+    fn test_tracking_matches_optical_depth() {
+        // Density increases linearly from 0 to 3 between voxel centers along x, so
+        // optical depth through the box is (0 + 4.5 + 1.5) * extinction coefficient
+        let grid = DensityGrid::from_vol(&vol_bytes([4, 1, 1], 1, [0.0, 0.0, 0.0, 4.0, 1.0, 1.0], &[0.0, 1.0, 2.0, 3.0])).unwrap();
+        let medium = HomogeneousVolume {
+            absorption_coeff: Vector3::new(0.05, 0.1, 0.0),
+            scattering_coeff: Vector3::new(0.05, 0.1, 0.3),
+            anisotropy: 0.0,
+        }.medium(1);
+        let expected = (-6.0 * medium.extinction_coeff()).exp();
+        let ray = Ray::new(Vector3::new(0.0, 0.5, 0.5), Vector3::X);
+
+        let mut sampler = Sampler::new(3, 4);
+        let num_samples = 40_000;
+        let (mut ratio, mut delta) = (Vector3::ZERO, Vector3::ZERO);
+        for _ in 0..num_samples {
+            ratio += grid.transmittance(&medium, &ray, 4.0, &mut sampler);
+            if let FreeFlight::Pass { weight } = grid.sample_free_flight(&medium, &ray, 4.0, &mut sampler) {
+                delta += weight;
+            }
+        }
+        let (ratio, delta) = (ratio / num_samples as Float, delta / num_samples as Float);
+        assert!(ratio.abs_diff_eq(expected, 0.01), "{} vs {}", ratio, expected);
+        assert!(delta.abs_diff_eq(expected, 0.01), "{} vs {}", delta, expected);
+    }
+}
@@ -0,0 +1,472 @@
+/*
+
+    Renders of small scenes checked against closed-form
+    values (point light shading, furnace tests, Beer's law,
+    exact silhouettes, ...) rather than reference images,
+    which would only lock in what the code rendered once.
+
+    Cameras look down -z from (0, 0, 5). A single pixel of
+    a tiny near plane sees the origin head-on, wider near
+    planes are used to compare every pixel of a silhouette.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fs;
+use std::path::PathBuf;
+
+use furry_tracer::interval::FloatConst;
+use furry_tracer::numeric::{Float, Matrix3, Vector3};
+use furry_tracer::ray::Ray;
+use furry_tracer::{parse_json795, render, Camera};
+
+/// Camera with a square image of `resolution` pixels, near plane spans [-extent, extent] at distance 1
+fn camera(resolution: usize, extent: Float, samples: usize) -> String {
+    format!(r#""Cameras": {{ "Camera": {{
+        "_id": "1", "Position": "0 0 5", "Gaze": "0 0 -1", "Up": "0 1 0",
+        "NearPlane": "{lo} {hi} {lo} {hi}", "NearDistance": "1",
+        "ImageResolution": "{resolution} {resolution}", "NumSamples": "{samples}", "ImageName": "analytic.png"
+    }} }}"#, lo = -extent, hi = extent)
+}
+
+/// Camera whose single pixel sees the origin head-on
+fn pixel_camera(samples: usize) -> String {
+    camera(1, 1e-4, samples)
+}
+
+/// Scene JSON around the given camera, Lights, Materials, VertexData and Objects sections
+fn scene(camera: &str, max_depth: usize, background: Float, sections: &str) -> String {
+    format!(r#"{{ "Scene": {{
+        "MaxRecursionDepth": "{max_depth}",
+        "BackgroundColor": "{background} {background} {background}",
+        "ShadowRayEpsilon": "1e-4",
+        "IntersectionTestEpsilon": "1e-6",
+        {camera},
+        {sections}
+    }} }}"#)
+}
+
+/// Directory scenes are written to, files they refer to go there as well
+fn scene_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join("furry_tracer_analytic_tests");
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Linear colors of the rendered pixels, with the camera that rendered them
+fn render_scene(name: &str, json: &str) -> (Vec<Vector3>, Camera) {
+    let path = scene_dir().join(name).with_extension("json");
+    fs::write(&path, json).unwrap();
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+    let images = render(&root.scene).unwrap();
+    let mut cam = root.scene.cameras.all().remove(0);
+    cam.setup();
+    (images[0].pixel_colors().to_vec(), cam)
+}
+
+fn assert_close(actual: Float, expected: Float, relative: Float) {
+    assert!((actual - expected).abs() <= relative * expected.abs(), "expected {} got {}", expected, actual);
+}
+
+#[test]
+fn point_light_shading_of_brdf_models() {
+    // Plane z = 0 seen head-on at the origin, lit from (3, 0, 4): r^2 = 25 and w_i = (0.6, 0, 0.8)
+    let (kd, ks, p, intensity): (Float, Float, Float, Float) = (0.5, 0.3, 10.0, 1000.0);
+    let (cos_i, cos_r): (Float, Float) = (0.8, 0.8); // w_o = n, perfect reflection of w_i is (-0.6, 0, 0.8)
+    let cos_h: Float = 1.8 / Float::sqrt(3.6); // Half vector is (0.6, 0, 1.8) normalized
+    let r0: Float = 0.04; // Schlick's Fresnel of refraction index 1.5
+    let fresnel = r0 + (1.0 - r0) * (1.0 - cos_h).powi(5);
+    let cases = [
+        ("", kd * cos_i + ks * cos_h.powf(p)),
+        (r#""OriginalPhong": { "_id": "1", "Exponent": "10" }"#, kd * cos_i + ks * cos_r.powf(p)),
+        (r#""ModifiedPhong": { "_id": "1", "_normalized": "true", "Exponent": "10" }"#,
+            (kd / Float::PI + ks * (p + 2.0) / (2.0 * Float::PI) * cos_r.powf(p)) * cos_i),
+        (r#""ModifiedBlinnPhong": { "_id": "1", "_normalized": "true", "Exponent": "10" }"#,
+            (kd / Float::PI + ks * (p + 8.0) / (8.0 * Float::PI) * cos_h.powf(p)) * cos_i),
+        // Geometry term is 1 and cos_o = 1 here
+        (r#""TorranceSparrow": { "_id": "1", "Exponent": "10" }"#,
+            (kd / Float::PI + ks * (p + 2.0) / (2.0 * Float::PI) * cos_h.powf(p) * fresnel / (4.0 * cos_i)) * cos_i),
+    ];
+    for (brdf, expected) in cases {
+        let brdf_ref = if brdf.is_empty() { "" } else { r#""_BRDF": "1","# };
+        let json = scene(&pixel_camera(1), 1, 0.0, &format!(r#"
+            "Lights": {{ "AmbientLight": "0 0 0", "PointLight": {{ "_id": "1", "Position": "3 0 4", "Intensity": "{intensity} {intensity} {intensity}" }} }},
+            "BRDFs": {{ {brdf} }},
+            "Materials": {{ "Material": {{ "_id": "1", {brdf_ref} "AmbientReflectance": "0 0 0", "DiffuseReflectance": "{kd} {kd} {kd}",
+                "SpecularReflectance": "{ks} {ks} {ks}", "PhongExponent": "10", "RefractionIndex": "1.5" }} }},
+            "VertexData": "0 0 0",
+            "Objects": {{ "Plane": {{ "_id": "1", "Material": "1", "Point": "1", "Normal": "0 0 1" }} }}
+        "#));
+        let (colors, _) = render_scene("brdf_models", &json);
+        assert_close(colors[0].x, expected * intensity / 25.0, 1e-4);
+    }
+}
+
+#[test]
+fn ggx_white_furnace() {
+    // White metal under a uniform background reflects the background times its directional albedo,
+    // which seen head-on is the integral of D(cos(theta / 2)) G1(cos(theta)) / 4 over the hemisphere
+    let background = 100.0;
+    for roughness in [0.2, 0.6, 1.0] {
+        let a2 = Float::powi(roughness * roughness, 2);
+        let d = |c: Float| a2 / (Float::PI * (c * c * (a2 - 1.0) + 1.0).powi(2));
+        let g1 = |c: Float| 2.0 * c / (c + (a2 + (1.0 - a2) * c * c).sqrt());
+        let steps = 100_000;
+        let albedo: Float = (0..steps).map(|k| {
+            let theta = (k as Float + 0.5) / steps as Float * Float::PI / 2.0;
+            2.0 * Float::PI * theta.sin() * d((theta / 2.0).cos()) * g1(theta.cos()) / 4.0 * (Float::PI / 2.0 / steps as Float)
+        }).sum();
+
+        let json = scene(&pixel_camera(4096), 2, background, &format!(r#"
+            "Lights": {{ "AmbientLight": "0 0 0" }},
+            "Materials": {{ "Material": {{ "_id": "1", "_type": "ggx", "AmbientReflectance": "0 0 0",
+                "BaseColor": "1 1 1", "Roughness": "{roughness}", "Metallic": "1" }} }},
+            "VertexData": "0 0 0",
+            "Objects": {{ "Plane": {{ "_id": "1", "Material": "1", "Point": "1", "Normal": "0 0 1" }} }}
+        "#));
+        let (colors, _) = render_scene("ggx_furnace", &json);
+        assert!(albedo <= 1.0);
+        assert_close(colors[0].x, background * albedo, 0.02);
+    }
+}
+
+/// Irradiance at the origin on the plane z = 0 from a polygon of radiance 1 (Lambert's formula)
+fn polygon_irradiance(vertices: &[Vector3]) -> Float {
+    let n = vertices.len();
+    let sum: Float = (0..n).map(|i| {
+        let (a, b) = (vertices[i].normalize(), vertices[(i + 1) % n].normalize());
+        a.angle_between(b) * a.cross(b).normalize().z
+    }).sum();
+    sum.abs() / 2.0
+}
+
+#[test]
+fn irradiance_from_area_lights() {
+    // Lambertian plane under an emitter that is entirely above it, Blinn-Phong diffuse is kd cos(theta)
+    let (kd, radiance) = (0.5, 1000.0);
+    // Sphere of radius r at distance d subtends pi (r / d)^2 cos(theta) of projected solid angle
+    let sphere = (r#""Sphere": { "_id": "1", "Material": "2", "Center": "2", "Radius": "0.5" }"#,
+        Float::PI * (0.25 / 8.0) * Float::sqrt(0.5));
+    let quad = [Vector3::new(1.0, -0.5, 2.0), Vector3::new(2.0, -0.5, 2.0), Vector3::new(2.0, 0.5, 2.0), Vector3::new(1.0, 0.5, 2.0)];
+    let quad = (r#""Mesh": { "_id": "1", "Material": "2", "Faces": { "_data": "3 4 5  3 5 6", "_type": "triangle" } }"#,
+        polygon_irradiance(&quad));
+    for (emitter, projected_solid_angle) in [sphere, quad] {
+        let json = scene(&pixel_camera(65536), 1, 0.0, &format!(r#"
+            "Lights": {{ "AmbientLight": "0 0 0" }},
+            "Materials": {{ "Material": [
+                {{ "_id": "1", "AmbientReflectance": "0 0 0", "DiffuseReflectance": "{kd} {kd} {kd}", "SpecularReflectance": "0 0 0" }},
+                {{ "_id": "2", "DiffuseReflectance": "0 0 0", "Emission": "{radiance} {radiance} {radiance}" }}
+            ] }},
+            "VertexData": "0 0 0  2 0 2  1 -0.5 2  2 -0.5 2  2 0.5 2  1 0.5 2",
+            "Objects": {{ "Plane": {{ "_id": "1", "Material": "1", "Point": "1", "Normal": "0 0 1" }}, {emitter} }}
+        "#));
+        let (colors, _) = render_scene("area_lights", &json);
+        assert_close(colors[0].x, kd * radiance * projected_solid_angle, 0.03);
+    }
+}
+
+#[test]
+fn beers_law_through_nested_dielectrics() {
+    // Concentric spheres of radius 2 and 1 with refraction index 1 neither reflect nor bend rays, the one
+    // through the center travels 2 units in the outer medium only and 2 units in the inner one
+    let background = 100.0;
+    let (outer, inner) = (Vector3::new(0.1, 0.2, 0.3), Vector3::new(0.5, 0.25, 0.0));
+    let dielectric = |id: usize, absorption: Vector3| format!(r#"{{ "_id": "{id}", "_type": "dielectric", "AmbientReflectance": "0 0 0",
+        "DiffuseReflectance": "0 0 0", "SpecularReflectance": "0 0 0", "MirrorReflectance": "1 1 1",
+        "AbsorptionCoefficient": "{} {} {}", "RefractionIndex": "1" }}"#, absorption.x, absorption.y, absorption.z);
+    let json = scene(&pixel_camera(1), 6, background, &format!(r#"
+        "Lights": {{ "AmbientLight": "0 0 0" }},
+        "Materials": {{ "Material": [ {}, {} ] }},
+        "VertexData": "0 0 0",
+        "Objects": {{ "Sphere": [
+            {{ "_id": "1", "Material": "1", "Center": "1", "Radius": "2" }},
+            {{ "_id": "2", "Material": "2", "Center": "1", "Radius": "1" }}
+        ] }}
+    "#, dielectric(1, outer), dielectric(2, inner)));
+    let (colors, _) = render_scene("nested_dielectrics", &json);
+    let expected = background * (-2.0 * (outer + inner)).exp();
+    for c in 0..3 {
+        assert_close(colors[0][c], expected[c], 1e-4);
+    }
+}
+
+#[test]
+fn transmittance_of_absorbing_fog() {
+    // Plane at distance 5 lit by ambient light only, seen through fog that absorbs but does not scatter
+    let (ambient, absorption) = (100.0, Vector3::new(0.05, 0.1, 0.2));
+    let json = scene(&pixel_camera(1), 1, 0.0, &format!(r#"
+        "Lights": {{ "AmbientLight": "{ambient} {ambient} {ambient}" }},
+        "Fog": {{ "AbsorptionCoefficient": "{} {} {}" }},
+        "Materials": {{ "Material": {{ "_id": "1", "AmbientReflectance": "1 1 1", "DiffuseReflectance": "0 0 0", "SpecularReflectance": "0 0 0" }} }},
+        "VertexData": "0 0 0",
+        "Objects": {{ "Plane": {{ "_id": "1", "Material": "1", "Point": "1", "Normal": "0 0 1" }} }}
+    "#, absorption.x, absorption.y, absorption.z));
+    let (colors, _) = render_scene("absorbing_fog", &json);
+    let expected = ambient * (-5.0 * absorption).exp();
+    for c in 0..3 {
+        assert_close(colors[0][c], expected[c], 1e-4);
+    }
+}
+
+#[test]
+fn scattering_volume_furnace() {
+    // Medium that scatters but does not absorb, in a uniform background, neither adds nor loses light
+    // whatever the phase function or the (per channel different) coefficients are
+    let background = 100.0;
+    let json = scene(&pixel_camera(16384), 64, background, r#"
+        "Lights": { "AmbientLight": "0 0 0" },
+        "Materials": { "Material": { "_id": "1", "_type": "volume", "AbsorptionCoefficient": "0 0 0",
+            "ScatteringCoefficient": "0.5 1 2", "Anisotropy": "0.5" } },
+        "VertexData": "0 0 0",
+        "Objects": { "Sphere": { "_id": "1", "Material": "1", "Center": "1", "Radius": "1" } }
+    "#);
+    let (colors, _) = render_scene("volume_furnace", &json);
+    for channel in colors[0].to_array() {
+        assert_close(channel, background, 0.03);
+    }
+}
+
+/// Single channel density grid in Mitsuba's .vol format over the box [-1, 1]^3
+fn write_vol(name: &str, resolution: [i32; 3], values: &[f32]) {
+    write_vol_in(name, [-1.0, -1.0, -1.0, 1.0, 1.0, 1.0], resolution, values);
+}
+
+/// Single channel density grid over the box of given min and max corners
+fn write_vol_in(name: &str, bounds: [f32; 6], resolution: [i32; 3], values: &[f32]) {
+    let mut bytes = b"VOL".to_vec();
+    bytes.push(3);
+    for i in [1, resolution[0], resolution[1], resolution[2], 1] {
+        bytes.extend(i.to_le_bytes());
+    }
+    for f in bounds.iter().chain(values) {
+        bytes.extend(f.to_le_bytes());
+    }
+    fs::write(scene_dir().join(name), bytes).unwrap();
+}
+
+#[test]
+fn voxel_grid_transmittance_and_furnace() {
+    // Densities 0.5 and 1.5 at the voxel centers z = -0.5 and 0.5 vary linearly between them and are
+    // constant towards the faces, so the optical depth along the z axis is 0.5 + 1.5 per unit coefficient
+    write_vol("linear.vol", [1, 1, 2], &[0.5, 1.5]);
+    let background = 100.0;
+    let absorption = Vector3::new(0.2, 0.5, 1.0);
+    let cases = [
+        // Delta tracking of a medium that only absorbs, the estimate of transmittance is 0 or 1 per sample
+        (format!("{} {} {}", absorption.x, absorption.y, absorption.z), "0 0 0", background * (-2.0 * absorption).exp()),
+        // Furnace: medium that only scatters neither adds nor loses light
+        (String::from("0 0 0"), "0.8 1 1.25", Vector3::splat(background)),
+    ];
+    for (absorption, scattering, expected) in cases {
+        let json = scene(&pixel_camera(16384), 64, background, &format!(r#"
+            "Lights": {{ "AmbientLight": "0 0 0" }},
+            "Materials": {{ "Material": {{ "_id": "1", "_type": "volume", "AbsorptionCoefficient": "{absorption}",
+                "ScatteringCoefficient": "{scattering}", "Anisotropy": "-0.3" }} }},
+            "VertexData": "0 0 0",
+            "Objects": {{ "VoxelVolume": {{ "_id": "1", "Material": "1", "_gridFile": "linear.vol" }} }}
+        "#));
+        let (colors, _) = render_scene("voxel_grid", &json);
+        for c in 0..3 {
+            assert_close(colors[0][c], expected[c], 0.03);
+        }
+    }
+}
+
+#[test]
+fn voxel_volumes_sharing_a_material() {
+    // Empty grid off to the side comes first, the pixel sees the background through the unit density box
+    // around the origin only, which is attenuated by its own grid and not by the first one of the material
+    write_vol_in("empty.vol", [3.0, -1.0, -1.0, 5.0, 1.0, 1.0], [1, 1, 1], &[0.0]);
+    write_vol("unit.vol", [1, 1, 1], &[1.0]);
+    let (background, absorption) = (100.0, 0.5);
+    let json = scene(&pixel_camera(16384), 4, background, &format!(r#"
+        "Lights": {{ "AmbientLight": "0 0 0" }},
+        "Materials": {{ "Material": {{ "_id": "1", "_type": "volume", "AbsorptionCoefficient": "{absorption} {absorption} {absorption}" }} }},
+        "VertexData": "0 0 0",
+        "Objects": {{ "VoxelVolume": [
+            {{ "_id": "1", "Material": "1", "_gridFile": "empty.vol" }},
+            {{ "_id": "2", "Material": "1", "_gridFile": "unit.vol" }}
+        ] }}
+    "#));
+    let (colors, _) = render_scene("shared_volume_material", &json);
+    assert_close(colors[0].x, background * Float::exp(-2.0 * absorption), 0.03);
+}
+
+#[test]
+fn subsurface_reflectance_under_distant_light() {
+    // Top of a large sphere seen head-on is a flat half-space with light from straight above: whatever
+    // point near it a walk leaves from sees the same irradiance at cos = 1, so the pixel is the coat
+    // reflecting the background plus the transmitted part of albedo times irradiance. Albedo 0 absorbs
+    // every walk and leaves only the coat, the fit of SubsurfaceMaterial::medium( ) keeps the others close
+    let (background, irradiance) = (50.0, 100.0);
+    let albedo = Vector3::new(0.0, 0.4, 0.8);
+    let r0: Float = Float::powi(0.4 / 2.4, 2); // Schlick's Fresnel of refraction index 1.4
+    let json = scene(&pixel_camera(16384), 4, background, &format!(r#"
+        "Lights": {{ "AmbientLight": "0 0 0", "PointLight": {{ "_id": "1", "Position": "0 0 1000", "Intensity": "{i} {i} {i}" }} }},
+        "Materials": {{ "Material": {{ "_id": "1", "_type": "subsurface", "AmbientReflectance": "0 0 0",
+            "Albedo": "{} {} {}", "MeanFreePath": "0.01 0.01 0.01", "RefractionIndex": "1.4" }} }},
+        "VertexData": "0 0 -100",
+        "Objects": {{ "Sphere": {{ "_id": "1", "Material": "1", "Center": "1", "Radius": "100" }} }}
+    "#, albedo.x, albedo.y, albedo.z, i = irradiance * 1e6));
+    let (colors, _) = render_scene("subsurface", &json);
+    let expected = r0 * background + (1.0 - r0) * irradiance * albedo;
+    assert_close(colors[0].x, expected.x, 1e-3);
+    for c in 1..3 {
+        assert_close(colors[0][c], expected[c], 0.03);
+    }
+}
+
+/// Which pixels of a head-on image of the given objects are covered, each lit by ambient light only
+/// against a black background and sampled once through its center, with the camera that rendered them
+fn silhouette(name: &str, vertex_data: &str, objects: &str) -> (Vec<bool>, Camera) {
+    let json = scene(&camera(24, 0.5, 1), 1, 0.0, &format!(r#"
+        "Lights": {{ "AmbientLight": "100 100 100" }},
+        "Materials": {{ "Material": {{ "_id": "1", "AmbientReflectance": "1 1 1", "DiffuseReflectance": "0 0 0", "SpecularReflectance": "0 0 0" }} }},
+        "VertexData": "{vertex_data}",
+        "Objects": {{ {objects} }}
+    "#));
+    let (colors, cam) = render_scene(name, &json);
+    (colors.iter().map(|color| color.x > 50.0).collect(), cam)
+}
+
+/// Whether the ray passes through a point inside the solid, stepping along it around the origin
+fn marches_through(ray: &Ray, inside: impl Fn(Vector3) -> bool) -> bool {
+    let steps = 30_000;
+    (0..steps).any(|k| inside(ray.origin + ray.direction * (2.0 + 6.0 * k as Float / steps as Float)))
+}
+
+/// Compare every pixel of the silhouette against whether the ray through its center hits the shape
+fn assert_silhouette(name: &str, (covered, cam): (Vec<bool>, Camera), hits: impl Fn(&Ray) -> bool) {
+    let width = cam.get_resolution().0;
+    let mismatches: Vec<(usize, usize)> = (0..covered.len())
+        .map(|i| (i % width, i / width))
+        .filter(|&(col, row)| covered[row * width + col] != hits(&cam.generate_ray(col, row, (0.5, 0.5))))
+        .collect();
+    assert!(covered.iter().any(|&c| c) && !covered.iter().all(|&c| c), "{} silhouette is empty or fills the image", name);
+    assert!(mismatches.is_empty(), "{} silhouette differs at pixels {:?}", name, mismatches);
+}
+
+#[test]
+fn silhouettes_of_analytic_primitives() {
+    // Tilted primitives seen through pixel centers cover exactly the pixels whose rays meet them:
+    // the disk is its plane within the radius, the solids are marched with their inside tests
+    let axis = Vector3::new(0.0, 1.0, 1.0).normalize();
+    let radial = |p: Vector3| (p - axis * p.dot(axis)).length(); // Distance to the axis through the origin
+    let disk_normal = Vector3::new(1.0, 0.0, 2.0).normalize();
+    assert_silhouette("disk", silhouette("disk", "0 0 0",
+        r#""Disk": { "_id": "1", "Material": "1", "Center": "1", "Normal": "1 0 2", "Radius": "1.5" }"#),
+        |ray| {
+            let t = -ray.origin.dot(disk_normal) / ray.direction.dot(disk_normal);
+            t > 0.0 && ray.at(t).length() < 1.5
+        });
+    assert_silhouette("cylinder", silhouette("cylinder", "0 0 0",
+        r#""Cylinder": { "_id": "1", "Material": "1", "Base": "1", "Axis": "0 1 1", "Radius": "0.8", "Height": "1.5" }"#),
+        |ray| marches_through(ray, |p| radial(p) < 0.8 && (0.0..1.5).contains(&p.dot(axis))));
+    assert_silhouette("cone", silhouette("cone", "0 0 0",
+        r#""Cone": { "_id": "1", "Material": "1", "Base": "1", "Axis": "0 1 1", "Radius": "1.2", "Height": "2" }"#),
+        |ray| marches_through(ray, |p| (0.0..2.0).contains(&p.dot(axis)) && radial(p) < 1.2 * (1.0 - p.dot(axis) / 2.0)));
+    let rotation = Matrix3::from_axis_angle(Vector3::new(1.0, 1.0, 0.0).normalize(), Float::to_radians(30.0));
+    let half = Vector3::new(1.2, 0.8, 0.5);
+    assert_silhouette("box", silhouette("box", "-1.2 -0.8 -0.5  1.2 0.8 0.5",
+        r#""Box": { "_id": "1", "Material": "1", "Min": "1", "Max": "2", "Rotation": "30 1 1 0" }"#),
+        |ray| marches_through(ray, |p| (rotation.transpose() * p).abs().cmplt(half).all()));
+    assert_silhouette("torus", silhouette("torus", "0 0 0",
+        r#""Torus": { "_id": "1", "Material": "1", "Center": "1", "Axis": "0 1 1", "MajorRadius": "1.3", "MinorRadius": "0.4" }"#),
+        |ray| marches_through(ray, |p| (radial(p) - 1.3).powi(2) + p.dot(axis).powi(2) < 0.16));
+}
+
+#[test]
+fn silhouettes_of_csg_operations() {
+    // Sphere of radius 1 at the origin with a sphere of radius 0.7 at (0.8, 0, 0): rays through the
+    // crescent of the difference only meet it where their chord of the first is not inside the second
+    let in_a = |p: Vector3| p.length() < 1.0;
+    let in_b = |p: Vector3| (p - Vector3::new(0.8, 0.0, 0.0)).length() < 0.7;
+    let csg = |operation: &str| format!(r#""CSG": {{ "_id": "1", "Operation": "{operation}",
+        "Left": {{ "Sphere": {{ "_id": "1", "Material": "1", "Center": "1", "Radius": "1" }} }},
+        "Right": {{ "Sphere": {{ "_id": "2", "Material": "1", "Center": "2", "Radius": "0.7" }} }} }}"#);
+    let vertex_data = "0 0 0  0.8 0 0";
+    assert_silhouette("union", silhouette("csg_union", vertex_data, &csg("union")),
+        |ray| marches_through(ray, |p| in_a(p) || in_b(p)));
+    assert_silhouette("intersection", silhouette("csg_intersection", vertex_data, &csg("intersection")),
+        |ray| marches_through(ray, |p| in_a(p) && in_b(p)));
+    assert_silhouette("difference", silhouette("csg_difference", vertex_data, &csg("difference")),
+        |ray| marches_through(ray, |p| in_a(p) && !in_b(p)));
+}
+
+#[test]
+fn silhouettes_of_signed_distance_fields() {
+    // Sphere traced shapes of exact (or, twisted, bounded) distance fields, placed at Center (0.3, -0.2, 0),
+    // cover the pixels whose rays pass through the solids. Sharp subtraction cuts a corner off the box
+    let center = Vector3::new(0.3, -0.2, 0.0);
+    let sdf = |node: &str| format!(r#""SDF": {{ "_id": "1", "Material": "1", "Center": "1",
+        "Bounds": "-1.6 -1.6 -1.6 1.6 1.6 1.6", "Node": {node} }}"#);
+    let segment_distance = |p: Vector3, a: Vector3, b: Vector3| {
+        let h = ((p - a).dot(b - a) / (b - a).length_squared()).clamp(0.0, 1.0);
+        (p - a - (b - a) * h).length()
+    };
+    let twisted = |p: Vector3| {
+        let (sin, cos) = (0.8 * p.y).sin_cos();
+        Vector3::new(cos * p.x - sin * p.z, p.y, sin * p.x + cos * p.z)
+    };
+    type Inside = Box<dyn Fn(Vector3) -> bool>;
+    let cases: [(&str, &str, Inside); 5] = [
+        ("sdf_sphere", r#"{ "Sphere": { "Center": "0.2 0 0", "Radius": "1" } }"#,
+            Box::new(|p| (p - Vector3::new(0.2, 0.0, 0.0)).length() < 1.0)),
+        ("sdf_capsule", r#"{ "Capsule": { "A": "-1 -0.5 0", "B": "1 0.6 0.3", "Radius": "0.4" } }"#,
+            Box::new(move |p| segment_distance(p, Vector3::new(-1.0, -0.5, 0.0), Vector3::new(1.0, 0.6, 0.3)) < 0.4)),
+        ("sdf_torus", r#"{ "Torus": { "MajorRadius": "1", "MinorRadius": "0.3" } }"#,
+            Box::new(|p| (p.x.hypot(p.z) - 1.0).hypot(p.y) < 0.3)),
+        ("sdf_subtraction", r#"{ "Subtraction": { "Left": { "Box": { "HalfSize": "0.9 0.9 0.9" } },
+            "Right": { "Sphere": { "Center": "0.9 0.9 0", "Radius": "1" } } } }"#,
+            Box::new(|p| p.abs().max_element() < 0.9 && (p - Vector3::new(0.9, 0.9, 0.0)).length() > 1.0)),
+        ("sdf_twist", r#"{ "Twist": { "Rate": "0.8", "Node": { "Box": { "HalfSize": "0.3 1.2 0.8" } } } }"#,
+            Box::new(move |p| twisted(p).abs().cmplt(Vector3::new(0.3, 1.2, 0.8)).all())),
+    ];
+    for (name, node, inside) in cases {
+        assert_silhouette(name, silhouette(name, "0.3 -0.2 0", &sdf(node)), |ray| marches_through(ray, |p| inside(p - center)));
+    }
+}
+
+#[test]
+fn shading_of_bezier_patches() {
+    // Parabolic cylinder z = c (1 - x^2) over [-1, 1]^2 is a bicubic patch: x and y are linear in u and v,
+    // heights of its control points are 0, 4c/3, 4c/3, 0 along u. Lit by a light at the camera, a pixel is
+    // I cos / r^2 at the first hit of its ray, which solves c (1 - x(t)^2) = z(t)
+    let (c, intensity): (Float, Float) = (0.8, 2500.0);
+    let control_points: Vec<String> = (0..4).flat_map(|j| (0..4).map(move |i| {
+        let height = if i == 1 || i == 2 { 4.0 * c / 3.0 } else { 0.0 };
+        format!("{} {} {}", -1.0 + 2.0 * i as Float / 3.0, -1.0 + 2.0 * j as Float / 3.0, height)
+    })).collect();
+    let expected = |ray: &Ray| -> Option<Float> {
+        let (o, d) = (ray.origin, ray.direction);
+        let (a, b, k) = (c * d.x * d.x, 2.0 * c * o.x * d.x + d.z, o.z - c * (1.0 - o.x * o.x));
+        let disc = b * b - 4.0 * a * k;
+        if disc < 0.0 {
+            return None;
+        }
+        let q = -0.5 * (b + b.signum() * disc.sqrt()); // Roots are q / a and k / q
+        let mut roots = [q / a, k / q];
+        roots.sort_by(Float::total_cmp);
+        let p = roots.into_iter().filter(|t| *t > 0.0).map(|t| o + d * t).find(|p| p.x.abs() <= 1.0 && p.y.abs() <= 1.0)?;
+        let (n, to_light) = (Vector3::new(2.0 * c * p.x, 0.0, 1.0).normalize(), o - p);
+        Some(intensity * n.dot(to_light.normalize()) / to_light.length_squared())
+    };
+    for method in ["newton", "tessellate"] {
+        let json = scene(&camera(24, 0.5, 1), 1, 0.0, &format!(r#"
+            "Lights": {{ "AmbientLight": "0 0 0", "PointLight": {{ "_id": "1", "Position": "0 0 5", "Intensity": "{intensity} {intensity} {intensity}" }} }},
+            "Materials": {{ "Material": {{ "_id": "1", "AmbientReflectance": "0 0 0", "DiffuseReflectance": "1 1 1", "SpecularReflectance": "0 0 0" }} }},
+            "VertexData": "{}",
+            "Objects": {{ "BezierPatch": {{ "_id": "1", "Material": "1", "ControlPoints": "{}", "Method": "{method}", "Tolerance": "0.001" }} }}
+        "#, control_points.join("  "), (1..=16).map(|i| i.to_string()).collect::<Vec<_>>().join(" ")));
+        let (colors, cam) = render_scene("bezier", &json);
+        let width = cam.get_resolution().0;
+        for (i, color) in colors.iter().enumerate() {
+            match expected(&cam.generate_ray(i % width, i / width, (0.5, 0.5))) {
+                Some(radiance) => assert_close(color.x, radiance, 1e-3),
+                None => assert_eq!(color.x, 0.0, "{} patch covers pixel {}", method, i),
+            }
+        }
+    }
+}
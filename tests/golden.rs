@@ -0,0 +1,87 @@
+/*
+
+    Golden image regression tests: every scene under
+    tests/fixtures/golden is rendered and compared against
+    its reference image under tests/fixtures/golden/reference
+    so that refactors can not silently change the output.
+
+    On failure the render and an amplified difference image
+    are written to the cargo target tmp dir (path is printed).
+
+    If a change of output is intended, update references with
+        UPDATE_GOLDEN=1 cargo test --test golden
+    and check the new images before committing them.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use furry_tracer::compare::{compare, diff_image};
+use furry_tracer::numeric::Float;
+use furry_tracer::{parse_json795, render, ImageData};
+
+// Renders are deterministic, tolerance only covers floating point differences between platforms.
+// A PSNR bound alone lets a few pixels change entirely, so single channels are bounded as well (of 255).
+#[cfg(not(feature = "f32"))]
+const MIN_PSNR: Float = 40.0;
+#[cfg(not(feature = "f32"))]
+const MAX_ERROR: Float = 2.0;
+// References are rendered in f64, single precision moves a few pixels on silhouettes of reflections,
+// those pixels can change entirely so that only PSNR is bounded
+#[cfg(feature = "f32")]
+const MIN_PSNR: Float = 30.0;
+#[cfg(feature = "f32")]
+const MAX_ERROR: Float = 255.0;
+
+fn fixtures_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join("golden")
+}
+
+fn render_fixture(json_path: &Path) -> Vec<ImageData> {
+    let mut root = parse_json795(&json_path.to_string_lossy()).unwrap();
+    root.scene.setup_after_json(json_path).unwrap();
+    render(&root.scene).unwrap()
+}
+
+#[test]
+fn golden_images() {
+    let reference_dir = fixtures_dir().join("reference");
+    let failure_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut scenes: Vec<PathBuf> = fs::read_dir(fixtures_dir()).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json"))
+        .collect();
+    scenes.sort();
+    assert!(!scenes.is_empty(), "No golden scenes found under {}", fixtures_dir().display());
+
+    let mut failures = Vec::new();
+    for json_path in scenes.iter() {
+        for image in render_fixture(json_path) {
+            let reference_path = reference_dir.join(image.name());
+            if update {
+                image.save_png(&reference_path.to_string_lossy()).unwrap();
+                continue;
+            }
+
+            let reference = ImageData::load_png(&reference_path)
+                .unwrap_or_else(|e| panic!("{} (run with UPDATE_GOLDEN=1 to create it)", e));
+            let diff = compare(&image, &reference).unwrap();
+            println!("{}: {}", image.name(), diff);
+            if diff.psnr < MIN_PSNR || diff.max_error > MAX_ERROR {
+                fs::create_dir_all(&failure_dir).unwrap();
+                let stem = Path::new(image.name()).file_stem().unwrap().to_string_lossy().to_string();
+                diff_image(&image, &reference, &format!("{}_diff.png", stem)).unwrap()
+                    .save_png(&failure_dir.to_string_lossy()).unwrap();
+                let name = image.name().to_string();
+                image.save_png(&failure_dir.to_string_lossy()).unwrap();
+                failures.push(format!("{}: {}", name, diff));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "Renders differ from references (see {}):\n{}", failure_dir.display(), failures.join("\n"));
+}
@@ -0,0 +1,194 @@
+/*
+
+    Smoke tests for the public library API: load a scene
+    from JSON, set it up and render it without going through
+    the raytracer binary.
+
+    @date: Oct, 2025
+    @author: Bartu
+*/
+
+use std::fs;
+use std::path::PathBuf;
+
+use furry_tracer::numeric::Vector3;
+use furry_tracer::{parse_json795, render, validation, ImageData, Material, RenderOverrides, TracerError, ValidationMode};
+
+const SINGLE_SPHERE: &str = r#"{
+  "Scene": {
+    "MaxRecursionDepth": "2",
+    "BackgroundColor": "0 0 0",
+    "ShadowRayEpsilon": "1e-3",
+    "IntersectionTestEpsilon": "1e-6",
+    "Cameras": {
+      "Camera": {
+        "_id": "1",
+        "Position": "0 0 5",
+        "Gaze": "0 0 -1",
+        "Up": "0 1 0",
+        "NearPlane": "-1 1 -1 1",
+        "NearDistance": "2",
+        "ImageResolution": "16 12",
+        "ImageName": "single_sphere.png"
+      }
+    },
+    "Lights": {
+      "AmbientLight": "25 25 25",
+      "PointLight": { "_id": "1", "Position": "0 4 4", "Intensity": "1000 1000 1000" }
+    },
+    "Materials": {
+      "Material": {
+        "_id": "1",
+        "AmbientReflectance": "1 1 1",
+        "DiffuseReflectance": "1 1 1",
+        "SpecularReflectance": "0 0 0",
+        "PhongExponent": "1"
+      }
+    },
+    "VertexData": "0 0 0",
+    "Objects": {
+      "Sphere": { "_id": "1", "Material": "1", "Center": "1", "Radius": "1" }
+    }
+  }
+}"#;
+
+fn write_scene(name: &str, contents: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("furry_tracer_tests");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn render_scene_through_library() {
+    let path = write_scene("single_sphere.json", SINGLE_SPHERE);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+
+    let images: Vec<ImageData> = render(&root.scene).unwrap();
+    assert_eq!(images.len(), 1);
+
+    // Sphere covers the center of the image, corners see the background
+    let rgb = images[0].clone().to_rgb();
+    assert_eq!(rgb.len(), 16 * 12 * 3);
+    let center = 3 * (6 * 16 + 8);
+    assert!(rgb[center] > 0);
+    assert_eq!(&rgb[0..3], &[0, 0, 0]);
+}
+
+#[test]
+fn unknown_material_type_is_an_error() {
+    let scene = SINGLE_SPHERE.replace(r#""_id": "1",
+        "AmbientReflectance""#, r#""_id": "1", "_type": "velvet",
+        "AmbientReflectance""#);
+    let path = write_scene("unknown_material.json", &scene);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+
+    match root.scene.setup_after_json(&path) {
+        Err(TracerError::Validation { object, id, .. }) => {
+            assert_eq!(object, "Material");
+            assert_eq!(id, 1);
+        }
+        other => panic!("Expected a validation error, got {:?}", other),
+    }
+}
+
+#[test]
+fn strict_validation_reports_missing_material() {
+    let scene = SINGLE_SPHERE.replace(r#""Material": "1", "Center""#, r#""Material": "4", "Center""#);
+    let path = write_scene("missing_material.json", &scene);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+
+    let diagnostics = root.scene.validate();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].object, "Sphere");
+    assert!(validation::check(&root.scene, ValidationMode::Lenient).is_ok());
+    assert!(matches!(validation::check(&root.scene, ValidationMode::Strict), Err(TracerError::InvalidScene(_))));
+}
+
+#[test]
+fn lenient_validation_skips_shape_with_missing_material() {
+    let scene = SINGLE_SPHERE.replace(r#""Material": "1", "Center""#, r#""Material": "4", "Center""#);
+    let path = write_scene("lenient_missing_material.json", &scene);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+    assert!(validation::check(&root.scene, ValidationMode::Lenient).is_ok());
+
+    // Sphere is skipped instead of failing the render, so the center sees the background
+    let images = render(&root.scene).unwrap();
+    let rgb = images[0].clone().to_rgb();
+    let center = 3 * (6 * 16 + 8);
+    assert_eq!(&rgb[center..center + 3], &[0, 0, 0]);
+}
+
+#[test]
+fn resolution_override_keeps_aspect_ratio_of_near_plane() {
+    let path = write_scene("resolution_override.json", SINGLE_SPHERE);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+
+    // 16x12 image of the near plane [-1, 1] x [-1, 1] rendered at 32x12 sees twice as wide
+    let overrides = RenderOverrides { resolution: Some([32, 12]), ..Default::default() };
+    root.scene.apply_overrides(&overrides).unwrap();
+    let cam = &root.scene.cameras.all()[0];
+    assert_eq!(cam.image_resolution, [32, 12]);
+    assert_eq!((cam.nearplane.left, cam.nearplane.right, cam.nearplane.bottom, cam.nearplane.top), (-2.0, 2.0, -1.0, 1.0));
+}
+
+#[test]
+fn missing_reflectances_take_defaults_of_material_type() {
+    let materials = r#""Material": [
+        { "_id": "1", "_type": "conductor", "MirrorReflectance": "1 1 1", "RefractionIndex": "0.37", "AbsorptionIndex": "2.82" },
+        { "_id": "2", "_type": "mirror", "MirrorReflectance": "1 1 1" },
+        { "_id": "3", "_type": "mirror", "MirrorReflectance": "1 1 1", "DiffuseReflectance": "0.2 0.2 0.2" }
+      ]"#;
+    let start = SINGLE_SPHERE.find(r#""Material": {"#).unwrap();
+    let end = start + SINGLE_SPHERE[start..].find('}').unwrap() + 1;
+    let scene = format!("{}{}{}", &SINGLE_SPHERE[..start], materials, &SINGLE_SPHERE[end..]);
+    let path = write_scene("default_reflectances.json", &scene);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+
+    // Conductors are not diffuse unless the scene says so, mirrors are half diffuse
+    let materials = root.scene.materials.all();
+    assert_eq!(materials.len(), 3);
+    assert_eq!(materials[0].brdf().diffuse_rf, Vector3::ZERO);
+    assert_eq!(materials[0].brdf().ambient_rf, Vector3::ZERO);
+    assert_eq!(materials[1].brdf().diffuse_rf, Vector3::splat(0.5));
+    assert_eq!(materials[2].brdf().diffuse_rf, Vector3::splat(0.2));
+}
+
+#[test]
+fn emissive_material_on_unsampled_shape_is_not_sampled() {
+    // Material 2 is on a sphere and a disk, disks can not be sampled so the sphere is not a light either
+    let scene = r#"{
+      "Scene": {
+        "Cameras": { "Camera": {
+          "_id": "1", "Position": "0 0 5", "Gaze": "0 0 -1", "Up": "0 1 0", "NearPlane": "-1 1 -1 1",
+          "NearDistance": "2", "ImageResolution": "4 4", "ImageName": "unsampled_emitter.png"
+        } },
+        "Materials": { "Material": [
+          { "_id": "1", "DiffuseReflectance": "0 0 0", "Emission": "10 10 10" },
+          { "_id": "2", "DiffuseReflectance": "0 0 0", "Emission": "10 10 10" }
+        ] },
+        "VertexData": "0 0 0",
+        "Objects": {
+          "Sphere": [
+            { "_id": "1", "Material": "1", "Center": "1", "Radius": "1" },
+            { "_id": "2", "Material": "2", "Center": "1", "Radius": "2" }
+          ],
+          "Disk": { "_id": "1", "Material": "1", "Center": "1", "Normal": "0 0 1", "Radius": "1" }
+        }
+      }
+    }"#;
+    let path = write_scene("unsampled_emitter.json", scene);
+    let mut root = parse_json795(path.to_str().unwrap()).unwrap();
+    root.scene.setup_after_json(&path).unwrap();
+
+    let area_lights = &root.scene.lights.area_lights;
+    assert_eq!(area_lights.len(), 1);
+    assert!(!area_lights.is_sampled(1));
+    assert!(area_lights.is_sampled(2));
+}